@@ -29,4 +29,19 @@ int alloc4(int **p, int a, int b, int c, int d) {
     *(*p + 2) = c;
     *(*p + 3) = d;
     return 205;
-}
\ No newline at end of file
+}
+
+int add8(int a, int b, int c, int d, int e, int f, int g, int h) {
+    printf("func add8 OK %d %d %d %d %d %d %d %d\n", a, b, c, d, e, f, g, h);
+    return a + b + c + d + e + f + g + h;
+}
+
+int sub9(int a, int b, int c, int d, int e, int f, int g, int h, int i) {
+    return a - b - c - d - e - f - g - h - i;
+}
+
+int aligned() {
+    return (long)__builtin_frame_address(0) % 16 == 0;
+}
+
+int global_value = 11;
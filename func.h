@@ -5,4 +5,8 @@ int foo();
 int bar(int, int);
 int baz(int, int, int, int, int, int);
 int foobar(int);
-int alloc4(int**, int, int, int, int);
\ No newline at end of file
+int alloc4(int**, int, int, int, int);
+int add8(int, int, int, int, int, int, int, int);
+int sub9(int, int, int, int, int, int, int, int, int);
+int aligned();
+extern int global_value;
@@ -0,0 +1,357 @@
+//! レジスタの割り当て
+//!
+//! 関数の命令の列に現れる仮想レジスタに、線形走査法で物理レジスタを割り当てる。
+//! ジャンプでつないだ命令の列で生存解析をして、値が生きている最初の命令から最後の命令までを生存区間にする。
+//! 関数呼び出しをまたぐ仮想レジスタには、呼び出しで保存されるレジスタを使う。
+//! 割り当てられるレジスタがない場合だけ、仮想レジスタをスタックに退避する。
+
+use super::instruction::{Instruction, Memory, Operand, Virtual, Width};
+use super::register::{Register, CALLEE_SAVED, CALLER_SAVED};
+use std::collections::HashMap;
+
+/// 割り当てに使うレジスタ。呼び出しで保存しなくてよいものを先に使う
+const POOL: [Register; 11] = [
+    Register::Rax,
+    Register::Rcx,
+    Register::Rdx,
+    Register::Rsi,
+    Register::Rdi,
+    Register::R8,
+    Register::R9,
+    Register::Rbx,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+];
+
+/// 退避した仮想レジスタを読み書きするときに使うレジスタ。割り当てには使わない
+///
+/// メモリのオペランドのベースとインデックスに加えてもう1つ使う命令があるので3つ用意する。
+/// r15は呼び出し先で保存するレジスタなので、使うときは保存する
+const SCRATCH: [Register; 3] = [Register::R10, Register::R11, Register::R15];
+
+/// 仮想レジスタの置き場所
+#[derive(Clone, Copy)]
+enum Location {
+    Register(Register),
+    /// スタックに退避する。何番目の退避領域か
+    Spill(usize),
+}
+
+/// 仮想レジスタの生存区間。最初と最後に現れる命令の位置
+struct Interval {
+    register: Virtual,
+    start: usize,
+    end: usize,
+}
+
+/// 関数の命令の列の仮想レジスタを、物理レジスタかスタックの領域に置き換える。
+pub fn allocate(instructions: &mut Vec<Instruction>) {
+    let intervals = intervals(instructions);
+    if intervals.is_empty() {
+        return;
+    }
+    let busy: Vec<Vec<Register>> = instructions.iter().map(physical).collect();
+    let clobbered: Vec<Vec<Register>> = instructions.iter().map(clobbers).collect();
+
+    let mut locations = HashMap::new();
+    let mut active: Vec<(usize, Register)> = Vec::new();
+    let mut spills = 0;
+    for interval in &intervals {
+        active.retain(|(end, _)| *end >= interval.start);
+        let free = POOL.iter().copied().find(|register| {
+            !active.iter().any(|(_, r)| r == register)
+                && (interval.start..=interval.end).all(|k| {
+                    !busy[k].contains(register)
+                        && (k == interval.end || !clobbered[k].contains(register))
+                })
+        });
+        let location = match free {
+            Some(register) => {
+                active.push((interval.end, register));
+                Location::Register(register)
+            }
+            None => {
+                spills += 1;
+                Location::Spill(spills - 1)
+            }
+        };
+        locations.insert(interval.register, location);
+    }
+
+    let mut saved: Vec<Register> = CALLEE_SAVED
+        .iter()
+        .copied()
+        .filter(|register| {
+            locations
+                .values()
+                .any(|l| matches!(l, Location::Register(r) if r == register))
+        })
+        .collect();
+    let third = instructions.iter().any(|instruction| {
+        let spilled = instruction
+            .virtuals()
+            .iter()
+            .filter(|register| matches!(locations[*register], Location::Spill(_)))
+            .count();
+        spilled >= SCRATCH.len()
+    });
+    if third {
+        saved.push(SCRATCH[2]);
+    }
+    saved.sort_by_key(|register| POOL.iter().position(|r| r == register));
+    rewrite(instructions, &locations, &saved, spills);
+}
+
+/// 仮想レジスタの生存区間を、始まりの順に並べて返す。
+fn intervals(instructions: &[Instruction]) -> Vec<Interval> {
+    // 仮想レジスタに0からの番号をつけて、集合をビット列で表す
+    let mut numbers: HashMap<Virtual, usize> = HashMap::new();
+    let mut registers = Vec::new();
+    for instruction in instructions {
+        for register in instruction.virtuals() {
+            numbers.entry(register).or_insert_with(|| {
+                registers.push(register);
+                registers.len() - 1
+            });
+        }
+    }
+    let words = registers.len().div_ceil(64);
+    let set = |list: Vec<Virtual>| {
+        let mut bits = vec![0u64; words];
+        for register in list {
+            let n = numbers[&register];
+            bits[n / 64] |= 1 << (n % 64);
+        }
+        bits
+    };
+    let mentioned: Vec<Vec<u64>> = instructions.iter().map(|i| set(i.virtuals())).collect();
+    let defined: Vec<Vec<u64>> = instructions
+        .iter()
+        .map(|i| set(defined(i).into_iter().collect()))
+        .collect();
+    let used: Vec<Vec<u64>> = instructions.iter().map(|i| set(used(i))).collect();
+    let successors = successors(instructions);
+
+    // 後ろからたどって、命令の前で生きている仮想レジスタを求める
+    let mut live = vec![vec![0u64; words]; instructions.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for k in (0..instructions.len()).rev() {
+            let mut bits = vec![0u64; words];
+            for s in &successors[k] {
+                for (w, b) in bits.iter_mut().zip(&live[*s]) {
+                    *w |= b;
+                }
+            }
+            for (w, (d, u)) in bits.iter_mut().zip(defined[k].iter().zip(&used[k])) {
+                *w = (*w & !d) | u;
+            }
+            if bits != live[k] {
+                live[k] = bits;
+                changed = true;
+            }
+        }
+    }
+
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; registers.len()];
+    for k in 0..instructions.len() {
+        for w in 0..words {
+            let mut bits = live[k][w] | mentioned[k][w];
+            while bits != 0 {
+                let n = w * 64 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let range = ranges[n].get_or_insert((k, k));
+                range.1 = k;
+            }
+        }
+    }
+    let mut intervals: Vec<_> = registers
+        .into_iter()
+        .zip(ranges)
+        .filter_map(|(register, range)| {
+            range.map(|(start, end)| Interval {
+                register,
+                start,
+                end,
+            })
+        })
+        .collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.register));
+    intervals
+}
+
+/// 命令の次に実行する可能性のある命令の位置
+fn successors(instructions: &[Instruction]) -> Vec<Vec<usize>> {
+    let labels: HashMap<u64, usize> = instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(k, instruction)| match instruction {
+            Instruction::Label(l) => Some((*l, k)),
+            _ => None,
+        })
+        .collect();
+    let n = instructions.len();
+    let next = |k: usize| (k + 1 < n).then_some(k + 1);
+    instructions
+        .iter()
+        .enumerate()
+        .map(|(k, instruction)| match instruction {
+            Instruction::Jmp(l) => vec![labels[l]],
+            Instruction::Jcc(_, l) => next(k).into_iter().chain([labels[l]]).collect(),
+            Instruction::Ret | Instruction::TailJmp(_) => Vec::new(),
+            _ => next(k).into_iter().collect(),
+        })
+        .collect()
+}
+
+/// 命令が前の値を読まずに書き込む仮想レジスタ
+fn defined(instruction: &Instruction) -> Option<Virtual> {
+    use Instruction::{Lea, Mov, Movsx, Movsxd, Movzx, Pop, Set};
+    match instruction {
+        Mov(Operand::Virtual(register, _), _)
+        | Movzx(Operand::Virtual(register, _), _)
+        | Movsx(Operand::Virtual(register, _), _)
+        | Movsxd(Operand::Virtual(register, _), _)
+        | Lea(Operand::Virtual(register, _), _)
+        // setccは下位8ビットだけに書き込むが、上位のビットはその後の命令で捨てる
+        | Set(_, Operand::Virtual(register, _))
+        | Pop(Operand::Virtual(register, _)) => Some(*register),
+        _ => None,
+    }
+}
+
+/// 命令が書き込む仮想レジスタ。読んでから書き込むものも含む
+fn written(instruction: &Instruction) -> Option<Virtual> {
+    use Instruction::{Add, Imul, Sub};
+    match instruction {
+        Add(Operand::Virtual(register, _), _)
+        | Sub(Operand::Virtual(register, _), _)
+        | Imul(Operand::Virtual(register, _), _) => Some(*register),
+        _ => defined(instruction),
+    }
+}
+
+/// 命令が読む仮想レジスタ。書き込むだけのオペランドは除く
+fn used(instruction: &Instruction) -> Vec<Virtual> {
+    let skip = usize::from(defined(instruction).is_some());
+    instruction
+        .operands()
+        .iter()
+        .skip(skip)
+        .flat_map(|operand| operand.virtuals())
+        .collect()
+}
+
+/// 命令が物理レジスタとして読み書きするレジスタ
+fn physical(instruction: &Instruction) -> Vec<Register> {
+    let mut registers = instruction.implicit_registers();
+    for operand in instruction.operands() {
+        registers.extend(operand.registers());
+    }
+    registers
+}
+
+/// 命令が実行された後に値が壊れているレジスタ
+fn clobbers(instruction: &Instruction) -> Vec<Register> {
+    match instruction {
+        Instruction::Call(_) => CALLER_SAVED.to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+/// 割り当てに合わせて命令を書き換え、保存するレジスタと退避する領域をスタックフレームに加える。
+fn rewrite(
+    instructions: &mut Vec<Instruction>,
+    locations: &HashMap<Virtual, Location>,
+    saved: &[Register],
+    spills: usize,
+) {
+    // 関数の入口でスタックフレームを確保する命令
+    let prologue = instructions.iter().position(|instruction| {
+        matches!(
+            instruction,
+            Instruction::Sub(Operand::Register(Register::Rsp, _), Operand::Immediate(_))
+        )
+    });
+    let (prologue, stack_size) = match prologue.map(|k| (k, &instructions[k])) {
+        Some((k, Instruction::Sub(_, Operand::Immediate(n)))) => (k, *n),
+        _ => unreachable!("スタックフレームを確保していない関数です。"),
+    };
+    let slot = |n: usize| Memory::new(Register::Rbp, -(stack_size + 8 * (n as i64 + 1)));
+    let saved_slot = |register: Register| slot(saved.iter().position(|r| *r == register).unwrap());
+    let spill_slot = |n: usize| slot(saved.len() + n);
+    let frame = (stack_size + 8 * (saved.len() + spills) as i64 + 15) / 16 * 16;
+
+    let mut output = Vec::with_capacity(instructions.len());
+    for (k, mut instruction) in std::mem::take(instructions).into_iter().enumerate() {
+        if k == prologue {
+            output.push(Instruction::Sub(
+                Operand::Register(Register::Rsp, Width::Qword),
+                Operand::Immediate(frame),
+            ));
+            for register in saved {
+                let register_operand = Operand::Register(*register, Width::Qword);
+                output.push(Instruction::Mov(
+                    saved_slot(*register).into(),
+                    register_operand,
+                ));
+            }
+            continue;
+        }
+        // 関数の出口でrspを戻す前に、保存したレジスタを戻す
+        if let Instruction::Mov(
+            Operand::Register(Register::Rsp, _),
+            Operand::Register(Register::Rbp, _),
+        ) = instruction
+        {
+            for register in saved {
+                let register_operand = Operand::Register(*register, Width::Qword);
+                output.push(Instruction::Mov(
+                    register_operand,
+                    saved_slot(*register).into(),
+                ));
+            }
+        }
+
+        // 退避した仮想レジスタは、読む命令の前に読み込み、書き込む命令の後に書き戻す
+        let spilled: Vec<(Virtual, usize, Register)> = instruction
+            .virtuals()
+            .into_iter()
+            .filter_map(|register| match locations[&register] {
+                Location::Spill(n) => Some((register, n)),
+                Location::Register(_) => None,
+            })
+            .zip(SCRATCH.iter())
+            .map(|((register, n), scratch)| (register, n, *scratch))
+            .collect();
+        let read = used(&instruction);
+        let written = written(&instruction);
+        for (register, n, scratch) in &spilled {
+            if read.contains(register) {
+                let scratch = Operand::Register(*scratch, Width::Qword);
+                output.push(Instruction::Mov(scratch, spill_slot(*n).into()));
+            }
+        }
+        let assign = |register: Virtual| match locations[&register] {
+            Location::Register(r) => r,
+            Location::Spill(_) => spilled
+                .iter()
+                .find(|(v, _, _)| *v == register)
+                .map(|(_, _, scratch)| *scratch)
+                .expect("1つの命令で退避した仮想レジスタを4つ以上使っています。"),
+        };
+        for operand in instruction.operands_mut() {
+            operand.assign(&assign);
+        }
+        output.push(instruction);
+        for (register, n, scratch) in &spilled {
+            if written == Some(*register) {
+                let scratch = Operand::Register(*scratch, Width::Qword);
+                output.push(Instruction::Mov(spill_slot(*n).into(), scratch));
+            }
+        }
+    }
+    *instructions = output;
+}
@@ -0,0 +1,512 @@
+//! 機械語の命令
+//!
+//! コード生成は命令を`Instruction`の列として組み立て、最後にまとめてアセンブリの文字列にする。
+
+use super::register::{Register, ARGS_REGISTER};
+use std::fmt;
+
+/// レジスタやメモリを読み書きする大きさ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    /// 8ビット
+    Byte,
+    /// 16ビット
+    Word,
+    /// 32ビット
+    Dword,
+    /// 64ビット
+    Qword,
+}
+
+impl Width {
+    /// 型の大きさ(バイト数)に合う幅。8バイトより大きい場合は64ビットにする。
+    pub fn from_size(size: i64) -> Self {
+        match size {
+            1 => Width::Byte,
+            2 => Width::Word,
+            4 => Width::Dword,
+            _ => Width::Qword,
+        }
+    }
+}
+
+/// 仮想レジスタ。レジスタの割り当てで、物理レジスタかスタックの領域に置き換える
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Virtual(pub u32);
+
+/// メモリのアドレスの基準
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Base {
+    Register(Register),
+    Virtual(Virtual),
+    /// 次の命令のアドレス。シンボルの位置を相対アドレスで参照する
+    Rip,
+}
+
+/// アドレスで参照するシンボル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Name {
+    /// 関数や静的な変数の名前
+    Global(String),
+    /// ローカルラベル(.Ln)
+    Local(u64),
+    /// GOTにある、他のファイルのシンボルのアドレス
+    Got(String),
+}
+
+/// メモリの位置。[base+index*scale+displacement]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    /// 読み書きする大きさ。レジスタの大きさから決まる場合はNone
+    width: Option<Width>,
+    name: Option<Name>,
+    base: Base,
+    /// インデックスのレジスタとスケール(1, 2, 4, 8)。ripはインデックスにできない
+    index: Option<(Base, u8)>,
+    displacement: i64,
+}
+
+impl From<Register> for Base {
+    fn from(register: Register) -> Self {
+        Base::Register(register)
+    }
+}
+
+impl From<Virtual> for Base {
+    fn from(register: Virtual) -> Self {
+        Base::Virtual(register)
+    }
+}
+
+impl Memory {
+    /// レジスタの値にdisplacementを足したアドレス
+    pub fn new<B: Into<Base>>(base: B, displacement: i64) -> Self {
+        Memory {
+            width: None,
+            name: None,
+            base: base.into(),
+            index: None,
+            displacement,
+        }
+    }
+
+    /// レジスタのオペランドの値にdisplacementを足したアドレス
+    pub fn at(register: &Operand, displacement: i64) -> Self {
+        match register {
+            Operand::Register(register, _) => Memory::new(*register, displacement),
+            Operand::Virtual(register, _) => Memory::new(*register, displacement),
+            _ => unreachable!("レジスタではないオペランドです: {}", register),
+        }
+    }
+
+    /// シンボルのアドレス。ripからの相対アドレスで参照する
+    pub fn rip(name: Name) -> Self {
+        Memory {
+            width: None,
+            name: Some(name),
+            base: Base::Rip,
+            index: None,
+            displacement: 0,
+        }
+    }
+
+    /// レジスタのオペランドの値にscaleを掛けて、アドレスに足す。
+    pub fn indexed(self, register: &Operand, scale: u8) -> Self {
+        let index = match register {
+            Operand::Register(register, _) => Base::Register(*register),
+            Operand::Virtual(register, _) => Base::Virtual(*register),
+            _ => unreachable!("レジスタではないオペランドです: {}", register),
+        };
+        Memory {
+            index: Some((index, scale)),
+            ..self
+        }
+    }
+
+    /// アドレスにdisplacementを足す。
+    pub fn displaced(self, displacement: i64) -> Self {
+        Memory {
+            displacement: self.displacement + displacement,
+            ..self
+        }
+    }
+
+    /// 読み書きする大きさを指定する。
+    pub fn sized(self, width: Width) -> Self {
+        Memory {
+            width: Some(width),
+            ..self
+        }
+    }
+
+    /// アドレスの計算に使うレジスタ
+    pub fn registers(&self) -> Vec<Register> {
+        self.bases()
+            .filter_map(|base| match base {
+                Base::Register(register) => Some(*register),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// アドレスの計算に使う仮想レジスタ
+    pub fn virtuals(&self) -> Vec<Virtual> {
+        self.bases()
+            .filter_map(|base| match base {
+                Base::Virtual(register) => Some(*register),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// ベースとインデックス
+    fn bases(&self) -> impl Iterator<Item = &Base> {
+        std::iter::once(&self.base).chain(self.index.iter().map(|(index, _)| index))
+    }
+
+    /// アドレスの計算に使う仮想レジスタを、物理レジスタに置き換える。
+    pub fn assign(&mut self, register: &dyn Fn(Virtual) -> Register) {
+        let bases =
+            std::iter::once(&mut self.base).chain(self.index.iter_mut().map(|(index, _)| index));
+        for base in bases {
+            if let Base::Virtual(virtual_register) = *base {
+                *base = Base::Register(register(virtual_register));
+            }
+        }
+    }
+
+    /// [r+displacement]の形のとき、rを返す。
+    pub fn base_register(&self) -> Option<Register> {
+        match (&self.name, &self.base, &self.index) {
+            (None, Base::Register(base), None) => Some(*base),
+            _ => None,
+        }
+    }
+
+    /// [r+displacement]のrを、rに入っているアドレスaddressで置き換える。
+    pub fn rebase(&self, address: &Memory) -> Memory {
+        Memory {
+            width: self.width,
+            displacement: address.displacement + self.displacement,
+            ..address.clone()
+        }
+    }
+}
+
+/// 命令のオペランド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register, Width),
+    Virtual(Virtual, Width),
+    /// XMMレジスタ
+    Xmm(u8),
+    Immediate(i64),
+    Memory(Memory),
+    /// 呼び出す関数の名前
+    Symbol(String),
+}
+
+impl Operand {
+    /// オペランドが読み書きするレジスタ
+    pub fn registers(&self) -> Vec<Register> {
+        match self {
+            Operand::Register(register, _) => vec![*register],
+            Operand::Memory(memory) => memory.registers(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// オペランドが読み書きする仮想レジスタ
+    pub fn virtuals(&self) -> Vec<Virtual> {
+        match self {
+            Operand::Virtual(register, _) => vec![*register],
+            Operand::Memory(memory) => memory.virtuals(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// 仮想レジスタを、物理レジスタに置き換える。
+    pub fn assign(&mut self, register: &dyn Fn(Virtual) -> Register) {
+        match self {
+            Operand::Virtual(virtual_register, width) => {
+                *self = Operand::Register(register(*virtual_register), *width)
+            }
+            Operand::Memory(memory) => memory.assign(register),
+            _ => {}
+        }
+    }
+
+    /// 同じレジスタの、別の幅のオペランド
+    pub fn sized(&self, width: Width) -> Operand {
+        match self {
+            Operand::Register(register, _) => Operand::Register(*register, width),
+            Operand::Virtual(register, _) => Operand::Virtual(*register, width),
+            _ => unreachable!("レジスタではないオペランドです: {}", self),
+        }
+    }
+}
+
+impl From<i64> for Operand {
+    fn from(n: i64) -> Self {
+        Operand::Immediate(n)
+    }
+}
+
+impl From<i32> for Operand {
+    fn from(n: i32) -> Self {
+        Operand::Immediate(n as i64)
+    }
+}
+
+impl From<Memory> for Operand {
+    fn from(memory: Memory) -> Self {
+        Operand::Memory(memory)
+    }
+}
+
+/// 条件付きの命令の条件。比較の結果のフラグレジスタを見る
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// 等しい
+    E,
+    /// 等しくない
+    Ne,
+    /// 符号付きで小さい
+    L,
+    /// 符号付きで小さいか等しい
+    Le,
+    /// 符号なしで小さい
+    B,
+    /// 符号なしで小さいか等しい
+    Be,
+    /// 符号なしで大きいか等しい
+    Ae,
+}
+
+/// 機械語の命令と、アセンブリに出力するラベルや指令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Push(Operand),
+    Pop(Operand),
+    Mov(Operand, Operand),
+    Movzx(Operand, Operand),
+    Movsx(Operand, Operand),
+    Movsxd(Operand, Operand),
+    Lea(Operand, Operand),
+    Movaps(Operand, Operand),
+    Add(Operand, Operand),
+    Sub(Operand, Operand),
+    Imul(Operand, Operand),
+    Cqo,
+    Cdq,
+    Div(Operand),
+    Idiv(Operand),
+    Cmp(Operand, Operand),
+    Set(Condition, Operand),
+    /// ローカルラベルにジャンプする
+    Jmp(u64),
+    /// 条件を満たす場合、ローカルラベルにジャンプする
+    Jcc(Condition, u64),
+    Call(Operand),
+    Ret,
+    /// スタックフレームを片付けた後に、関数にジャンプする。引数のレジスタを読む
+    TailJmp(Operand),
+    RepStosb,
+    /// ローカルラベル(.Ln:)
+    Label(u64),
+    /// 関数や変数のラベル
+    Symbol(String),
+    /// アセンブラ指令
+    Directive(String),
+    Comment(String),
+}
+
+impl Instruction {
+    /// 命令のオペランド。書き込む側のオペランドが先になる
+    pub fn operands(&self) -> Vec<&Operand> {
+        use Instruction::*;
+        match self {
+            Push(a) | Pop(a) | Div(a) | Idiv(a) | Set(_, a) | Call(a) | TailJmp(a) => {
+                vec![a]
+            }
+            Mov(a, b)
+            | Movzx(a, b)
+            | Movsx(a, b)
+            | Movsxd(a, b)
+            | Lea(a, b)
+            | Movaps(a, b)
+            | Add(a, b)
+            | Sub(a, b)
+            | Imul(a, b)
+            | Cmp(a, b) => vec![a, b],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        use Instruction::*;
+        match self {
+            Push(a) | Pop(a) | Div(a) | Idiv(a) | Set(_, a) | Call(a) | TailJmp(a) => {
+                vec![a]
+            }
+            Mov(a, b)
+            | Movzx(a, b)
+            | Movsx(a, b)
+            | Movsxd(a, b)
+            | Lea(a, b)
+            | Movaps(a, b)
+            | Add(a, b)
+            | Sub(a, b)
+            | Imul(a, b)
+            | Cmp(a, b) => vec![a, b],
+            _ => Vec::new(),
+        }
+    }
+
+    /// 命令がオペランドのほかに暗黙に読み書きするレジスタ
+    pub fn implicit_registers(&self) -> Vec<Register> {
+        use Instruction::*;
+        match self {
+            Cqo | Cdq | Div(_) | Idiv(_) => vec![Register::Rax, Register::Rdx],
+            RepStosb => vec![Register::Rax, Register::Rcx, Register::Rdi],
+            // 引数のレジスタと、可変長引数の関数に渡すal
+            Call(_) | TailJmp(_) => ARGS_REGISTER
+                .iter()
+                .copied()
+                .chain(std::iter::once(Register::Rax))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// 命令が読み書きする仮想レジスタ
+    pub fn virtuals(&self) -> Vec<Virtual> {
+        let mut registers: Vec<_> = self.operands().iter().flat_map(|o| o.virtuals()).collect();
+        registers.sort();
+        registers.dedup();
+        registers
+    }
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Width::Byte => write!(f, "BYTE PTR"),
+            Width::Word => write!(f, "WORD PTR"),
+            Width::Dword => write!(f, "DWORD PTR"),
+            Width::Qword => write!(f, "QWORD PTR"),
+        }
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Name::Global(name) => write!(f, "{}", name),
+            Name::Local(l) => write!(f, ".L{}", l),
+            Name::Got(name) => write!(f, "{}@GOTPCREL", name),
+        }
+    }
+}
+
+impl fmt::Display for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(width) = self.width {
+            write!(f, "{} ", width)?;
+        }
+        if let Some(name) = &self.name {
+            write!(f, "{}", name)?;
+        }
+        match self.base {
+            Base::Register(base) => write!(f, "[{}", base.name(Width::Qword))?,
+            Base::Virtual(base) => write!(f, "[{}", Operand::Virtual(base, Width::Qword))?,
+            Base::Rip => write!(f, "[rip")?,
+        }
+        match &self.index {
+            Some((Base::Register(index), scale)) => {
+                write!(f, "+{}*{}", index.name(Width::Qword), scale)?
+            }
+            Some((Base::Virtual(index), scale)) => {
+                write!(f, "+{}*{}", Operand::Virtual(*index, Width::Qword), scale)?
+            }
+            Some((Base::Rip, _)) => unreachable!("ripはインデックスにできません。"),
+            None => {}
+        }
+        if self.displacement != 0 {
+            write!(f, "{:+}", self.displacement)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(register, width) => write!(f, "{}", register.name(*width)),
+            // 仮想レジスタは割り当ての前にしか現れないので、デバッグ用の表記にする
+            Operand::Virtual(Virtual(n), width) => {
+                let suffix = match width {
+                    Width::Qword => "",
+                    Width::Dword => "d",
+                    Width::Word => "w",
+                    Width::Byte => "b",
+                };
+                write!(f, "%v{}{}", n, suffix)
+            }
+            Operand::Xmm(n) => write!(f, "xmm{}", n),
+            Operand::Immediate(n) => write!(f, "{}", n),
+            Operand::Memory(memory) => write!(f, "{}", memory),
+            Operand::Symbol(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let suffix = match self {
+            Condition::E => "e",
+            Condition::Ne => "ne",
+            Condition::L => "l",
+            Condition::Le => "le",
+            Condition::B => "b",
+            Condition::Be => "be",
+            Condition::Ae => "ae",
+        };
+        write!(f, "{}", suffix)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+        match self {
+            Push(src) => write!(f, "  push {}", src),
+            Pop(dst) => write!(f, "  pop {}", dst),
+            Mov(dst, src) => write!(f, "  mov {}, {}", dst, src),
+            Movzx(dst, src) => write!(f, "  movzx {}, {}", dst, src),
+            Movsx(dst, src) => write!(f, "  movsx {}, {}", dst, src),
+            Movsxd(dst, src) => write!(f, "  movsxd {}, {}", dst, src),
+            Lea(dst, src) => write!(f, "  lea {}, {}", dst, src),
+            Movaps(dst, src) => write!(f, "  movaps {}, {}", dst, src),
+            Add(dst, src) => write!(f, "  add {}, {}", dst, src),
+            Sub(dst, src) => write!(f, "  sub {}, {}", dst, src),
+            Imul(dst, src) => write!(f, "  imul {}, {}", dst, src),
+            Cqo => write!(f, "  cqo"),
+            Cdq => write!(f, "  cdq"),
+            Div(src) => write!(f, "  div {}", src),
+            Idiv(src) => write!(f, "  idiv {}", src),
+            Cmp(dst, src) => write!(f, "  cmp {}, {}", dst, src),
+            Set(condition, dst) => write!(f, "  set{} {}", condition, dst),
+            Jmp(l) => write!(f, "  jmp .L{}", l),
+            Jcc(condition, l) => write!(f, "  j{} .L{}", condition, l),
+            Call(src) => write!(f, "  call {}", src),
+            Ret => write!(f, "  ret"),
+            TailJmp(src) => write!(f, "  jmp {}", src),
+            RepStosb => write!(f, "  rep stosb"),
+            Label(l) => write!(f, ".L{}:", l),
+            Symbol(name) => write!(f, "{}:", name),
+            Directive(directive) => write!(f, "  {}", directive),
+            Comment(comment) => write!(f, "# {}", comment),
+        }
+    }
+}
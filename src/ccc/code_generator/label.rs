@@ -1,25 +1,132 @@
+use super::instruction::{Condition, Instruction, Operand, Virtual, Width};
+use super::register::RSP;
+use super::{allocator, peephole};
+
 /// ローカルのユニークなラベル名のための構造体
-pub struct Label {
+///
+/// 命令は関数ごとに`Instruction`の列として溜めておき、`flush`で呼び出し元が渡した出力先に書き込む。
+pub struct Label<'a> {
     label_count: u64,
+    /// 16バイト境界からスタックに積んだバイト数
     push_count: i64,
+    /// 呼び出し中の関数ごとのアライメント用の領域の大きさ
+    paddings: Vec<i64>,
+    /// データ領域に置く文字列リテラルのラベルと内容
+    literals: Vec<(u64, Vec<u8>)>,
+    /// まだ出力していない命令
+    instructions: Vec<Instruction>,
+    /// 仮想レジスタの数
+    virtual_count: u32,
+    /// 中間表現から命令を選び、出力する前にレジスタの割り当てとのぞき穴最適化をするか
+    optimize: bool,
+    /// スタックマシンとして生成した命令にも、のぞき穴最適化をするか
+    peephole: bool,
+    /// アセンブリの出力先
+    output: &'a mut dyn std::io::Write,
+    /// 出力先への書き込みで最初に起きたエラー
+    error: Option<std::io::Error>,
 }
 
-impl Label {
-    pub fn new() -> Self {
+impl<'a> Label<'a> {
+    pub fn new(optimize: bool, peephole: bool, output: &'a mut dyn std::io::Write) -> Self {
         Label {
             label_count: 0,
             push_count: 0,
+            paddings: Vec::new(),
+            literals: Vec::new(),
+            instructions: Vec::new(),
+            virtual_count: 0,
+            optimize,
+            peephole,
+            output,
+            error: None,
+        }
+    }
+
+    /// 出力を終える。書き込みでエラーが起きていた場合は、最初のエラーを返す。
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.flush();
+        match self.error {
+            Some(e) => Err(e),
+            None => self.output.flush(),
         }
     }
 
+    /// 溜めておいた命令をアセンブリとして出力する。
+    /// エラーが起きた後は何も書き込まず、エラーはfinishで返す。
+    pub fn flush(&mut self) {
+        let mut instructions = std::mem::take(&mut self.instructions);
+        if self.optimize {
+            allocator::allocate(&mut instructions);
+        }
+        if self.optimize || self.peephole {
+            peephole::optimize(&mut instructions);
+        }
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = print(&instructions, self.output) {
+            self.error = Some(e);
+        }
+    }
+
+    /// 中間表現から命令を選び、仮想レジスタを使って計算するか
+    pub fn optimizes(&self) -> bool {
+        self.optimize
+    }
+
+    /// 新しい仮想レジスタ
+    pub fn new_virtual(&mut self) -> Operand {
+        self.virtual_count += 1;
+        Operand::Virtual(Virtual(self.virtual_count - 1), Width::Qword)
+    }
+
+    /// 命令を追加する。
+    fn emit(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+
     pub fn get(&mut self) -> u64 {
         let a = self.label_count;
         self.label_count += 1;
         a
     }
+
+    /// 文字列リテラルをデータ領域に追加して、そのローカルラベルを返す。
+    pub fn literal(&mut self, bytes: &[u8]) -> u64 {
+        let l = self.get();
+        self.literals.push((l, bytes.to_vec()));
+        l
+    }
+
+    /// 追加された文字列リテラルを、ヌル文字で終わるバイト列として出力する。
+    pub fn emit_literals(&mut self) {
+        if self.literals.is_empty() {
+            return;
+        }
+        self.directive(".section .rodata");
+        for (l, bytes) in std::mem::take(&mut self.literals) {
+            self.l_label(l);
+            let bytes = bytes
+                .iter()
+                .chain(std::iter::once(&0))
+                .map(|b| b.to_string())
+                .collect::<Vec<_>>();
+            self.directive(format!(".byte {}", bytes.join(", ")));
+        }
+        self.directive(".text");
+    }
 }
 
-impl Label {
+/// 命令の列をアセンブリとして1行ずつ書き込む。
+pub fn print(instructions: &[Instruction], output: &mut dyn std::io::Write) -> std::io::Result<()> {
+    for instruction in instructions {
+        writeln!(output, "{}", instruction)?;
+    }
+    Ok(())
+}
+
+impl Label<'_> {
     /// push src
     ///
     /// スタックにsrcレジスタ・src値をプッシュする。
@@ -27,15 +134,15 @@ impl Label {
     /// srcがレジスタの場合、srcレジスタに操作は行わない
     pub fn push<T>(&mut self, src: T)
     where
-        T: std::fmt::Display,
+        T: Into<Operand>,
     {
-        println!("  push {}", src);
-        println!(
-            "# push count {} + 8 => {}",
-            self.push_count,
-            self.push_count + 8
-        );
-        self.push_count += 8;
+        let (count, next) = (self.push_count, self.push_count + 8);
+        self.push_count = next;
+        self.emit(Instruction::Push(src.into()));
+        self.emit(Instruction::Comment(format!(
+            "push count {} + 8 => {}",
+            count, next
+        )));
     }
 
     /// pop src
@@ -44,15 +151,15 @@ impl Label {
     /// 現在のRSP位置の64bitをsrcに読み込み、RSPを8上げる。
     pub fn pop<T>(&mut self, src: T)
     where
-        T: std::fmt::Display,
+        T: Into<Operand>,
     {
-        println!("  pop {}", src);
-        println!(
-            "# push count {} - 8 => {}",
-            self.push_count,
-            self.push_count - 8
-        );
-        self.push_count -= 8;
+        let (count, next) = (self.push_count, self.push_count - 8);
+        self.push_count = next;
+        self.emit(Instruction::Pop(src.into()));
+        self.emit(Instruction::Comment(format!(
+            "push count {} - 8 => {}",
+            count, next
+        )));
     }
 
     /// mov dst, src
@@ -61,10 +168,10 @@ impl Label {
     /// srcがレジスタの場合、srcレジスタに操作は行わない
     pub fn mov<T, U>(&mut self, dst: T, src: U)
     where
-        T: std::fmt::Display,
-        U: std::fmt::Display,
+        T: Into<Operand>,
+        U: Into<Operand>,
     {
-        println!("  mov {}, {}", dst, src);
+        self.emit(Instruction::Mov(dst.into(), src.into()));
     }
 
     /// movzx dst, src
@@ -73,10 +180,61 @@ impl Label {
     /// srcがレジスタの場合、srcレジスタに操作は行わない
     pub fn movzx<T, U>(&mut self, dst: T, src: U)
     where
-        T: std::fmt::Display,
-        U: std::fmt::Display,
+        T: Into<Operand>,
+        U: Into<Operand>,
     {
-        println!("  movzx {}, {}", dst, src);
+        self.emit(Instruction::Movzx(dst.into(), src.into()));
+    }
+
+    /// movsx dst, src
+    ///
+    /// dstレジスタにsrcの8bit・16bitの値を符号拡張して書き込む。
+    pub fn movsx<T, U>(&mut self, dst: T, src: U)
+    where
+        T: Into<Operand>,
+        U: Into<Operand>,
+    {
+        self.emit(Instruction::Movsx(dst.into(), src.into()));
+    }
+
+    /// movsxd dst, src
+    ///
+    /// dstレジスタにsrcの32bitの値を符号拡張して書き込む。
+    pub fn movsxd<T, U>(&mut self, dst: T, src: U)
+    where
+        T: Into<Operand>,
+        U: Into<Operand>,
+    {
+        self.emit(Instruction::Movsxd(dst.into(), src.into()));
+    }
+
+    /// rep stosb
+    ///
+    /// rdiが指すアドレスからrcxバイトをalの値で埋める。
+    pub fn rep_stosb(&mut self) {
+        self.emit(Instruction::RepStosb);
+    }
+
+    /// lea dst, src
+    ///
+    /// dstレジスタにsrcのアドレスを書き込む。
+    pub fn lea<T, U>(&mut self, dst: T, src: U)
+    where
+        T: Into<Operand>,
+        U: Into<Operand>,
+    {
+        self.emit(Instruction::Lea(dst.into(), src.into()));
+    }
+
+    /// movaps dst, src
+    ///
+    /// dstにXMMレジスタsrcの128bitの値を書き込む。dstは16バイト境界になければならない。
+    pub fn movaps<T, U>(&mut self, dst: T, src: U)
+    where
+        T: Into<Operand>,
+        U: Into<Operand>,
+    {
+        self.emit(Instruction::Movaps(dst.into(), src.into()));
     }
 
     /// add dst, src
@@ -85,10 +243,10 @@ impl Label {
     /// srcがレジスタの場合、srcレジスタに操作は行わない
     pub fn add<T, U>(&mut self, dst: T, src: U)
     where
-        T: std::fmt::Display,
-        U: std::fmt::Display,
+        T: Into<Operand>,
+        U: Into<Operand>,
     {
-        println!("  add {}, {}", dst, src);
+        self.emit(Instruction::Add(dst.into(), src.into()));
     }
 
     /// sub dst, src
@@ -97,10 +255,10 @@ impl Label {
     /// srcがレジスタの場合、srcレジスタに操作は行わない
     pub fn sub<T, U>(&mut self, dst: T, src: U)
     where
-        T: std::fmt::Display,
-        U: std::fmt::Display,
+        T: Into<Operand>,
+        U: Into<Operand>,
     {
-        println!("  sub {}, {}", dst, src);
+        self.emit(Instruction::Sub(dst.into(), src.into()));
     }
 
     /// imul dst, src
@@ -109,10 +267,10 @@ impl Label {
     /// srcがレジスタの場合、srcレジスタに操作は行わない
     pub fn imul<T, U>(&mut self, dst: T, src: U)
     where
-        T: std::fmt::Display,
-        U: std::fmt::Display,
+        T: Into<Operand>,
+        U: Into<Operand>,
     {
-        println!("  imul {}, {}", dst, src);
+        self.emit(Instruction::Imul(dst.into(), src.into()));
     }
 
     /// cqo
@@ -120,7 +278,25 @@ impl Label {
     /// raxレジスタの値を符号付き拡張して、rdx:raxに書き込む。
     /// 割り算の前にrdxを設定する目的で使用される。
     pub fn cqo(&mut self) {
-        println!("  cqo");
+        self.emit(Instruction::Cqo);
+    }
+
+    /// cdq
+    ///
+    /// eaxレジスタの値を符号付き拡張して、edx:eaxに書き込む。
+    /// 32ビットの割り算の前にedxを設定する目的で使用される。
+    pub fn cdq(&mut self) {
+        self.emit(Instruction::Cdq);
+    }
+
+    /// div src
+    ///
+    /// rdx:raxレジスタの値を符号なしでsrcで割り、割った値をrax、あまりをrdxに書き込む。
+    pub fn div<T>(&mut self, src: T)
+    where
+        T: Into<Operand>,
+    {
+        self.emit(Instruction::Div(src.into()));
     }
 
     /// idiv src
@@ -129,9 +305,9 @@ impl Label {
     /// srcがレジスタの場合、srcレジスタに操作は行わない
     pub fn idiv<T>(&mut self, src: T)
     where
-        T: std::fmt::Display,
+        T: Into<Operand>,
     {
-        println!("  idiv {}", src);
+        self.emit(Instruction::Idiv(src.into()));
     }
 
     /// cmp dst, src
@@ -140,114 +316,133 @@ impl Label {
     /// dst、srcがレジスタの場合、dst、srcレジスタに操作は行わない
     pub fn cmp<T, U>(&mut self, dst: T, src: U)
     where
-        T: std::fmt::Display,
-        U: std::fmt::Display,
+        T: Into<Operand>,
+        U: Into<Operand>,
     {
-        println!("  cmp {}, {}", dst, src);
+        self.emit(Instruction::Cmp(dst.into(), src.into()));
     }
 
-    /// sete src
+    /// setcc src
     ///
-    /// フラグレジスタの値を見て、等しい場合にsrcレジスタにバイトを書き込む。
-    pub fn sete<T>(&mut self, src: T)
+    /// フラグレジスタの値を見て、条件を満たす場合にsrcレジスタに1を、満たさない場合に0を書き込む。
+    pub fn set<T>(&mut self, condition: Condition, src: T)
     where
-        T: std::fmt::Display,
+        T: Into<Operand>,
     {
-        println!("  sete {}", src);
+        self.emit(Instruction::Set(condition, src.into()));
     }
 
-    /// sete src
+    /// ret
     ///
-    /// フラグレジスタの値を見て、等しくない場合にsrcレジスタにバイトを書き込む。
-    pub fn setne<T>(&mut self, src: T)
-    where
-        T: std::fmt::Display,
-    {
-        println!("  setne {}", src);
+    /// 呼び出し元にリターンする。
+    pub fn ret(&mut self) {
+        self.emit(Instruction::Ret);
     }
 
-    /// sete src
+    /// jmp src
     ///
-    /// フラグレジスタの値を見て、小さい場合にsrcレジスタにバイトを書き込む。
-    pub fn setl<T>(&mut self, src: T)
+    /// スタックフレームを片付けた後に、src関数にジャンプする。
+    /// src関数は、この関数の呼び出し元に直接戻る。
+    pub fn tail_jmp<T>(&mut self, src: T)
     where
-        T: std::fmt::Display,
+        T: Into<Operand>,
     {
-        println!("  setl {}", src);
+        self.emit(Instruction::TailJmp(src.into()));
     }
 
-    /// sete src
+    /// jmp .Lsrc
     ///
-    /// フラグレジスタの値を見て、小さいか等しい場合にsrcレジスタにバイトを書き込む。
-    pub fn setle<T>(&mut self, src: T)
-    where
-        T: std::fmt::Display,
-    {
-        println!("  setle {}", src);
+    /// ローカルラベルにジャンプする。
+    pub fn jmp(&mut self, src: u64) {
+        self.emit(Instruction::Jmp(src));
     }
 
-    /// ret
+    /// je .Lsrc
     ///
-    /// 呼び出し元にリターンする。
-    pub fn ret(&mut self) {
-        println!("  ret");
+    /// 等しい場合、ローカルラベルにジャンプする。
+    pub fn je(&mut self, src: u64) {
+        self.emit(Instruction::Jcc(Condition::E, src));
     }
 
-    /// jmp .Lsrc
+    /// jcc .Lsrc
     ///
-    /// ローカルラベルにジャンプする。
-    pub fn jmp<T>(&mut self, src: T)
-    where
-        T: std::fmt::Display,
-    {
-        println!("  jmp .L{}", src);
+    /// 条件を満たす場合、ローカルラベルにジャンプする。
+    pub fn jcc(&mut self, condition: Condition, src: u64) {
+        self.emit(Instruction::Jcc(condition, src));
     }
 
-    /// je .Lsrc
+    /// 関数呼び出しの準備
     ///
-    /// 等しい場合、ローカルラベルにジャンプする。
-    pub fn je<T>(&mut self, src: T)
-    where
-        T: std::fmt::Display,
-    {
-        println!("  je .L{}", src);
+    /// これからスタックに積むstack_args個の引数の下にアライメント用の領域を確保して、
+    /// callの時点でrspが16の倍数になるようにする。
+    pub fn align_call(&mut self, stack_args: i64) {
+        let n = (self.push_count + stack_args * 8).rem_euclid(16);
+        if n != 0 {
+            self.sub(RSP, n);
+            self.push_count += n;
+        }
+        self.paddings.push(n);
+    }
+
+    /// jae .Lsrc
+    ///
+    /// 符号なしで大きいか等しい場合、ローカルラベルにジャンプする。
+    pub fn jae(&mut self, src: u64) {
+        self.emit(Instruction::Jcc(Condition::Ae, src));
     }
 
     /// call src
     ///
     /// src関数を呼び出す。
-    /// rspが16の倍数でない場合、16の倍数にする処理も行う。
-    pub fn call<T>(&mut self, src: T)
+    /// align_callの後に積んだstack_args個の引数と、アライメント用の領域を呼び出し後に取り除く。
+    pub fn call<T>(&mut self, src: T, stack_args: i64)
     where
-        T: std::fmt::Display,
+        T: Into<Operand>,
     {
-        let n = 16 - self.push_count % 16;
-        if n != 16 {
-            self.sub("rsp", n);
-        }
-        println!("  call {}", src);
-        if n != 16 {
-            self.add("rsp", n);
+        self.emit(Instruction::Call(src.into()));
+        let n = stack_args * 8 + self.paddings.pop().unwrap_or(0);
+        if n != 0 {
+            self.add(RSP, n);
+            self.push_count -= n;
         }
     }
 
     /// src:
     ///
     /// 関数ラベルを設定する。
+    /// 呼び出し元が積んだ戻りアドレスの分から、スタックに積んだバイト数を数え直す。
     pub fn f_label<T>(&mut self, src: T)
     where
         T: std::fmt::Display,
     {
-        println!("{}:", src);
+        self.emit(Instruction::Symbol(src.to_string()));
+        self.push_count = 8;
     }
 
-    /// .Lsrc
+    /// src:
     ///
-    /// ローカルラベルを設定する。
-    pub fn l_label<T>(&mut self, src: T)
+    /// データ領域の変数のラベルを設定する。
+    pub fn d_label<T>(&mut self, src: T)
+    where
+        T: std::fmt::Display,
+    {
+        self.emit(Instruction::Symbol(src.to_string()));
+    }
+
+    /// .directive
+    ///
+    /// アセンブラ指令を出力する。
+    pub fn directive<T>(&mut self, src: T)
     where
         T: std::fmt::Display,
     {
-        println!(".L{}:", src);
+        self.emit(Instruction::Directive(src.to_string()));
+    }
+
+    /// .Lsrc
+    ///
+    /// ローカルラベルを設定する。
+    pub fn l_label(&mut self, src: u64) {
+        self.emit(Instruction::Label(src));
     }
 }
@@ -1,11 +1,26 @@
+mod allocator;
+mod instruction;
 mod label;
+mod peephole;
 mod register;
-
-use crate::ccc::parser::node::{
-    BinaryKind, CompareKind, Expression, Function, Program, Statement, UnaryKind, Variable,
-    VariableType::Array,
+mod select;
+
+use crate::ccc::{
+    ir,
+    parser::node::visit::{self, Visitor},
+    parser::node::{
+        BinaryKind, CompareKind, Declaration, Expression, ExpressionKind, Function, Initializer,
+        Program, Statement, Storage, UnaryKind, Variable, VariableType,
+        VariableType::{
+            Array, Char, Int, Long, Pointer, Short, UnsignedChar, UnsignedInt, UnsignedLong,
+            UnsignedShort, Void,
+        },
+    },
+    semantic::constant::{self, StaticValue, Symbol},
 };
+use instruction::{Condition, Memory, Name, Operand, Width};
 use label::Label;
+use register::{Register, AL, EAX, EDI, EDX, R10, RAX, RBP, RCX, RDI, RDX, RSP};
 
 /*
 
@@ -36,28 +51,84 @@ srcは変更されない
 
 */
 
-pub fn code_generate(program: &Program) -> () {
-    println!(".intel_syntax noprefix");
-    println!(".global main");
-
-    generate_program(program, &mut Label::new());
+/// 構文木からアセンブリを生成して、outputに書き込む。
+///
+/// optimizeの場合は、関数を中間表現にして最適化してから命令を選び、
+/// レジスタの割り当てとのぞき穴最適化をする。そうでない場合は、スタックマシンとして計算し、
+/// peepholeの場合だけのぞき穴最適化をする。
+pub fn code_generate(
+    program: &Program,
+    optimize: bool,
+    peephole: bool,
+    output: &mut dyn std::io::Write,
+) -> std::io::Result<()> {
+    let mut label = Label::new(optimize, peephole, output);
+    label.directive(".intel_syntax noprefix");
+    label.flush();
+    generate_program(program, &mut label);
+    label.emit_literals();
+    // スタックを実行できる領域にしないように、リンカに伝える
+    label.directive(".section .note.GNU-stack,\"\",@progbits");
+    label.finish()
 }
 
-fn generate_program(program: &Program, label: &mut Label) -> () {
+fn generate_program(program: &Program, label: &mut Label) {
+    for global in program.globals() {
+        generate_static_variable(global, label);
+        label.flush();
+    }
+    if !label.optimizes() {
+        for code in program.codes() {
+            generate_function(code, label);
+            label.flush();
+        }
+        return;
+    }
+    for function in ir::program(program, true) {
+        select::function(&function, label);
+        label.flush();
+    }
+    // 展開して取り除いた関数のstatic変数も、展開した先から使う
     for code in program.codes() {
-        generate_function(code, label);
+        Statics(label).visit_function(code);
+        label.flush();
     }
 }
 
-fn generate_function(function: &Function, label: &mut Label) -> () {
-    label.f_label(function.name());
-    label.push("rbp");
-    label.mov("rbp", "rsp");
-    label.sub("rsp", 208);
+/// 関数の中で宣言されたstatic変数を出力する
+struct Statics<'l, 'a>(&'l mut Label<'a>);
 
-    let arguments = function.arguments();
-    for i in 0..arguments.len() {
-        gen_parameter(&arguments[i], label, i);
+impl Visitor for Statics<'_, '_> {
+    fn visit_declaration(&mut self, declaration: &Declaration) {
+        generate_static_variable(declaration, self.0);
+        visit::walk_declaration(self, declaration);
+    }
+}
+
+fn generate_function(function: &Function, label: &mut Label) {
+    let name = function.name();
+    // staticな関数は他のファイルから見えないように、.globlを付けない
+    if !function.internal() {
+        label.directive(format!(".globl {}", name));
+    }
+    label.directive(format!(".type {}, @function", name));
+    label.f_label(name);
+    label.push(RBP);
+    label.mov(RBP, RSP);
+    label.sub(RSP, function.stack_size());
+
+    if let Some(area) = function.va_area() {
+        gen_register_save_area(area, label);
+    }
+
+    // 7個目以降の引数はrbpより上にあるので、そのまま参照する
+    for (i, argument) in function
+        .arguments()
+        .iter()
+        .enumerate()
+        .take(register::ARGS_REGISTER.len())
+    {
+        gen_parameter(argument, label, i);
     }
 
     let statements = function.statements();
@@ -65,34 +136,39 @@ fn generate_function(function: &Function, label: &mut Label) -> () {
         generate_statement(statement, label);
     }
 
-    label.pop("rax");
-    label.mov("rsp", "rbp");
-    label.pop("rbp");
+    label.mov(RAX, 0);
+    label.mov(RSP, RBP);
+    label.pop(RBP);
     label.ret();
+    label.directive(format!(".size {}, .-{}", name, name));
 }
 
-fn generate_statement(node: &Statement, label: &mut Label) -> () {
+fn generate_statement(node: &Statement, label: &mut Label) {
     use Statement::{Block, Declaration, Expression, For, If, IfElse, Return, While};
     match node {
         Return(expr) => {
-            generate_expression(expr, label);
-            label.pop("rax");
-            label.mov("rsp", "rbp");
-            label.pop("rbp");
+            gen_value(expr, label);
+            label.mov(RSP, RBP);
+            label.pop(RBP);
             label.ret();
         }
 
-        Declaration(var) => generate_variable(var, label),
-        Expression(expr) => generate_expression(expr, label),
+        Declaration(declarations) => {
+            for declaration in declarations {
+                generate_declaration(declaration, label);
+            }
+        }
+
+        Expression(expr) => gen_value(expr, label),
 
         If {
             condition,
             true_statement,
         } => {
             let l = label.get();
-            gen_condition(&*condition, label);
+            gen_condition(condition, label);
             label.je(l);
-            generate_statement(&*true_statement, label);
+            generate_statement(true_statement, label);
             label.l_label(l);
         }
 
@@ -103,12 +179,12 @@ fn generate_statement(node: &Statement, label: &mut Label) -> () {
         } => {
             let lelse = label.get();
             let lend = label.get();
-            gen_condition(&*condition, label);
+            gen_condition(condition, label);
             label.je(lelse);
-            generate_statement(&*true_statement, label);
+            generate_statement(true_statement, label);
             label.jmp(lend);
             label.l_label(lelse);
-            generate_statement(&*false_statement, label);
+            generate_statement(false_statement, label);
             label.l_label(lend);
         }
 
@@ -119,9 +195,9 @@ fn generate_statement(node: &Statement, label: &mut Label) -> () {
             let lbegin = label.get();
             let lend = label.get();
             label.l_label(lbegin);
-            gen_condition(&*condition, label);
+            gen_condition(condition, label);
             label.je(lend);
-            generate_statement(&*statement, label);
+            generate_statement(statement, label);
             label.jmp(lbegin);
             label.l_label(lend);
         }
@@ -134,12 +210,12 @@ fn generate_statement(node: &Statement, label: &mut Label) -> () {
         } => {
             let lbegin = label.get();
             let lend = label.get();
-            generate_expression(&*init, label);
+            generate_statement(init, label);
             label.l_label(lbegin);
-            gen_condition(&*condition, label);
+            gen_condition(condition, label);
             label.je(lend);
-            generate_statement(&*statement, label);
-            generate_expression(&*iteration, label);
+            generate_statement(statement, label);
+            gen_value(iteration, label);
             label.jmp(lbegin);
             label.l_label(lend);
         }
@@ -147,41 +223,140 @@ fn generate_statement(node: &Statement, label: &mut Label) -> () {
         Block { statements } => {
             for statement in statements {
                 generate_statement(statement, label);
-                label.pop("rax")
             }
         }
     }
 }
 
-fn generate_expression(node: &Expression, label: &mut Label) -> () {
-    use Expression::{BinaryOperator, FunctionCall, LocalVariable, Num, UnaryOperator};
-    match node {
-        Num(i) => label.push(i),
+/// 式の値をraxに求める。
+fn gen_value(node: &Expression, label: &mut Label) {
+    generate_expression(node, label);
+    label.pop(RAX);
+}
 
-        LocalVariable(variable) => match variable.var_type() {
+/// 式の値をスタックに積む。
+fn generate_expression(node: &Expression, label: &mut Label) {
+    use ExpressionKind::{
+        BinaryOperator, Cast, FunctionCall, FunctionName, Num, SizeOf, StringLiteral,
+        UnaryOperator, Undeclared, VaArg, VaEnd, VaStart, Variable,
+    };
+    match node.kind() {
+        // 32ビットに収まらない数は、一度raxに読み込む
+        Num(i) if *i as i32 as i64 != *i => {
+            label.mov(RAX, *i);
+            label.push(RAX);
+        }
+        Num(i) => label.push(*i),
+
+        StringLiteral(bytes) => {
+            let l = label.literal(bytes);
+            label.lea(RAX, Memory::rip(Name::Local(l)));
+            label.push(RAX);
+        }
+
+        VaStart { ap, named, area } => {
+            generate_expression(ap, label);
+            label.pop(RAX);
+            let registers = register::ARGS_REGISTER.len();
+            // gp_offset, fp_offset
+            let gp_offset = *named.min(&registers) as i64 * 8;
+            label.mov(Memory::new(Register::Rax, 0).sized(Width::Dword), gp_offset);
+            label.mov(Memory::new(Register::Rax, 4).sized(Width::Dword), 48);
+            // overflow_arg_area
+            let stack_args = named.saturating_sub(registers) as i64;
+            label.lea(RDI, Memory::new(Register::Rbp, 16 + stack_args * 8));
+            label.mov(Memory::new(Register::Rax, 8), RDI);
+            // reg_save_area
+            label.lea(RDI, Memory::new(Register::Rbp, -area));
+            label.mov(Memory::new(Register::Rax, 16), RDI);
+            label.push(RAX);
+        }
+
+        VaArg { ap, var_type } => {
+            let loverflow = label.get();
+            let lend = label.get();
+            generate_expression(ap, label);
+            label.pop(RDI);
+            let gp_offset = Memory::new(Register::Rdi, 0).sized(Width::Dword);
+            label.mov(EAX, gp_offset.clone());
+            label.cmp(EAX, 48);
+            label.jae(loverflow);
+            // レジスタ保存領域から読む
+            label.lea(EDX, Memory::new(Register::Rax, 8));
+            label.mov(gp_offset, EDX);
+            label.add(RAX, Memory::new(Register::Rdi, 16));
+            label.jmp(lend);
+            // スタックで渡された引数から読む
+            label.l_label(loverflow);
+            label.mov(RAX, Memory::new(Register::Rdi, 8));
+            label.lea(RDX, Memory::new(Register::Rax, 8));
+            label.mov(Memory::new(Register::Rdi, 8), RDX);
+            label.l_label(lend);
+            gen_load(var_type, &RAX, label);
+            label.push(RAX);
+        }
+
+        VaEnd(ap) => generate_expression(ap, label),
+
+        Cast {
+            var_type,
+            expression,
+        } => {
+            generate_expression(expression, label);
+            label.pop(RAX);
+            gen_cast(expression.var_type(), var_type, &RAX, label);
+            label.push(RAX);
+        }
+
+        Variable(variable) => match variable.var_type().unqualified() {
             Array(_, _) => gen_local_variable(node, label),
 
-            _ => {
+            var_type => {
                 gen_local_variable(node, label);
-                label.pop("rax");
-                label.mov("rax", "[rax]");
-                label.push("rax");
+                label.pop(RAX);
+                gen_load(var_type, &RAX, label);
+                label.push(RAX);
             }
         },
 
         BinaryOperator { kind, left, right } => generate_binary(label, kind, left, right),
         UnaryOperator { kind, expression } => generate_unary(label, kind, expression),
 
-        FunctionCall { name, args } => {
-            for arg in args {
+        FunctionName { name, .. } => {
+            let got = Memory::rip(Name::Got(name.clone())).sized(Width::Qword);
+            label.mov(RAX, got);
+            label.push(RAX);
+        }
+
+        FunctionCall { callee, args } => {
+            // 7個目以降の引数は、右から順にスタックに積んだまま渡す
+            let stack_args = args.len().saturating_sub(register::ARGS_REGISTER.len()) as i64;
+            label.align_call(stack_args);
+            for arg in args.iter().rev() {
                 generate_expression(arg, label);
             }
-            for i in (0..args.len()).rev() {
-                label.pop(register::ARGS_REGISTER[i][0]);
+            // 関数名でない場合は、呼び出す関数のアドレスを求めてr10に入れる
+            let function = match callee.kind() {
+                FunctionName { name, .. } => Operand::Symbol(name.clone()),
+                _ => {
+                    generate_expression(callee, label);
+                    label.pop(R10);
+                    R10
+                }
+            };
+            for register in register::ARGS_REGISTER.iter().take(args.len()) {
+                label.pop(Operand::Register(*register, Width::Qword));
             }
-            label.call(name);
-            label.push("rax");
+            // 浮動小数点数の引数はないので、可変長引数の関数のためにalを0にする
+            label.mov(EAX, 0);
+            label.call(function, stack_args);
+            // 戻り値を関数の戻り値の型に合わせて符号拡張する
+            gen_cast(&Long, node.var_type(), &RAX, label);
+            label.push(RAX);
         }
+
+        SizeOf(_) => unreachable!("sizeofは意味解析で数に置き換えられます。"),
+        Undeclared(_) => unreachable!("宣言されていない名前はエラーとして報告されます。"),
     }
 }
 
@@ -192,32 +367,35 @@ fn generate_binary(label: &mut Label, kind: &BinaryKind, left: &Expression, righ
             gen_local_variable(left, label);
             generate_expression(right, label);
 
-            label.pop("rdi");
-            label.pop("rax");
-            match left.kind() {
-                Ok(t) if t.size() == 4 => label.mov("[rax]", "edi"),
-                Ok(_) => label.mov("[rax]", "rdi"),
-                Err(e) => eprintln!("{}", e),
-            }
-            label.push("rdi");
+            label.pop(RDI);
+            label.pop(RAX);
+            gen_store(left.var_type(), &RAX, &RDI, label);
+            label.push(RDI);
         }
 
         kind => {
             generate_expression(left, label);
             generate_expression(right, label);
 
-            label.pop("rdi");
-            label.pop("rax");
-            let lk = left.kind();
-            let rk = right.kind();
-            let (rax, rdi) = match (lk, rk) {
-                (Ok(l), Ok(r)) if l.size() == 4 && r.size() == 4 => ("eax", "edi"),
-                _ => ("rax", "rdi"),
-            };
+            label.pop(RDI);
+            label.pop(RAX);
+            // 整数どうしの計算の両辺は、意味解析で同じ型にそろえられている
+            let dword = is_dword(left.var_type()) && is_dword(right.var_type());
+            let (rax, rdi) = if dword { (EAX, EDI) } else { (RAX, RDI) };
+            // アドレスは符号なしで扱う
+            let unsigned = left.var_type().is_unsigned() || is_address(left.var_type());
             match kind {
                 Add => label.add(rax, rdi),
                 Subtract => label.sub(rax, rdi),
                 Multiply => label.imul(rax, rdi),
+                Divide if unsigned => {
+                    label.mov(EDX, 0);
+                    label.div(rdi);
+                }
+                Divide if dword => {
+                    label.cdq();
+                    label.idiv(rdi);
+                }
                 Divide => {
                     label.cqo();
                     label.idiv(rdi);
@@ -225,17 +403,20 @@ fn generate_binary(label: &mut Label, kind: &BinaryKind, left: &Expression, righ
                 Compare(cmp) => {
                     label.cmp(rax, rdi);
                     use CompareKind::{Equal, LessEqual, LessThan, NotEqual};
-                    match cmp {
-                        Equal => label.sete("al"),
-                        NotEqual => label.setne("al"),
-                        LessThan => label.setl("al"),
-                        LessEqual => label.setle("al"),
-                    }
-                    label.movzx("rax", "al");
+                    let condition = match cmp {
+                        Equal => Condition::E,
+                        NotEqual => Condition::Ne,
+                        LessThan if unsigned => Condition::B,
+                        LessEqual if unsigned => Condition::Be,
+                        LessThan => Condition::L,
+                        LessEqual => Condition::Le,
+                    };
+                    label.set(condition, AL);
+                    label.movzx(RAX, AL);
                 }
                 Assign => unreachable!(),
             }
-            label.push("rax");
+            label.push(RAX);
         }
     }
 }
@@ -245,50 +426,267 @@ fn generate_unary(label: &mut Label, kind: &UnaryKind, expression: &Expression)
         UnaryKind::Address => gen_local_variable(expression, label),
         UnaryKind::Deref => {
             generate_expression(expression, label);
-            label.pop("rax");
-            label.mov("rax", "[rax]");
-            label.push("rax");
+            match expression.var_type().unqualified() {
+                Pointer(t) | Array(t, _)
+                    if !matches!(t.unqualified(), Array(_, _) | VariableType::Function { .. }) =>
+                {
+                    label.pop(RAX);
+                    gen_load(t, &RAX, label);
+                    label.push(RAX);
+                }
+                _ => {}
+            }
         }
     }
 }
 
 fn gen_local_variable(node: &Expression, label: &mut Label) {
-    match node {
-        Expression::LocalVariable(var) => generate_variable(var, label),
+    match node.kind() {
+        ExpressionKind::Variable(var) => generate_variable(var, label),
 
-        Expression::UnaryOperator {
+        ExpressionKind::UnaryOperator {
             kind: UnaryKind::Deref,
             expression,
         } => {
             generate_expression(expression, label);
         }
 
-        _ => eprintln!("左辺値が代入可能ではありません。"),
+        ExpressionKind::FunctionName { .. } => generate_expression(node, label),
+
+        _ => unreachable!("左辺値ではない式は意味解析でエラーになります。"),
     }
 }
 
 fn generate_variable(node: &Variable, label: &mut Label) {
-    label.mov("rax", "rbp");
-    label.sub("rax", node.offset());
-    label.push("rax");
+    match node.storage() {
+        Storage::Local(offset) => gen_address(*offset, label),
+        Storage::Static { symbol, .. } => {
+            label.lea(RAX, Memory::rip(Name::Global(symbol.clone())));
+            label.push(RAX);
+        }
+        // 他のファイルの変数は、GOTからアドレスを読む
+        Storage::Extern => {
+            let got = Memory::rip(Name::Got(node.name().to_string())).sized(Width::Qword);
+            label.mov(RAX, got);
+            label.push(RAX);
+        }
+    }
+}
+
+fn gen_address(offset: i64, label: &mut Label) {
+    label.mov(RAX, RBP);
+    label.sub(RAX, offset);
+    label.push(RAX);
+}
+
+/// 4バイト以下の整数型か確かめる。この型の値は32ビットのレジスタで計算する。
+fn is_dword(var_type: &VariableType) -> bool {
+    matches!(
+        var_type.unqualified(),
+        Char | Short | Int | UnsignedChar | UnsignedShort | UnsignedInt
+    )
+}
+
+/// アドレスを値に持つ型か確かめる。
+fn is_address(var_type: &VariableType) -> bool {
+    matches!(
+        var_type.unqualified(),
+        Pointer(_) | Array(..) | VariableType::Function { .. }
+    )
+}
+
+/// レジスタrの値を型fromから型toに変換する。
+///
+/// 小さい整数型へは切り詰めて、符号ありなら符号拡張、符号なしならゼロ拡張する。
+/// 4バイトの整数型から8バイトの型へは、fromの符号に合わせて拡張する。
+fn gen_cast(from: &VariableType, to: &VariableType, r: &Operand, label: &mut Label) {
+    let (byte, word, dword) = (
+        r.sized(Width::Byte),
+        r.sized(Width::Word),
+        r.sized(Width::Dword),
+    );
+    match (from.unqualified(), to.unqualified()) {
+        (_, Void) => {}
+        (_, Char) => label.movsx(r.clone(), byte),
+        (_, Short) => label.movsx(r.clone(), word),
+        (_, Int) => label.movsxd(r.clone(), dword),
+        (_, UnsignedChar) => label.movzx(r.clone(), byte),
+        (_, UnsignedShort) => label.movzx(r.clone(), word),
+        (_, UnsignedInt) => label.mov(dword.clone(), dword),
+        (Int, Long) | (Int, UnsignedLong) | (Int, Pointer(_)) => label.movsxd(r.clone(), dword),
+        (UnsignedInt, Long) | (UnsignedInt, UnsignedLong) | (UnsignedInt, Pointer(_)) => {
+            label.mov(dword.clone(), dword)
+        }
+        _ => {}
+    }
+}
+
+/// レジスタrが指すアドレスから、型の大きさと符号に合わせてrに読み込む。
+fn gen_load(var_type: &VariableType, r: &Operand, label: &mut Label) {
+    let address = Memory::at(r, 0);
+    match var_type.unqualified() {
+        Char => label.movsx(r.clone(), address.sized(Width::Byte)),
+        Short => label.movsx(r.clone(), address.sized(Width::Word)),
+        Int => label.movsxd(r.clone(), address.sized(Width::Dword)),
+        UnsignedChar => label.movzx(r.clone(), address.sized(Width::Byte)),
+        UnsignedShort => label.movzx(r.clone(), address.sized(Width::Word)),
+        UnsignedInt => label.mov(r.sized(Width::Dword), address.sized(Width::Dword)),
+        _ => label.mov(r.clone(), address),
+    }
+}
+
+/// レジスタaddressが指すアドレスに、型の大きさに合わせてレジスタvalueの値を書き込む。
+fn gen_store(var_type: &VariableType, address: &Operand, value: &Operand, label: &mut Label) {
+    let value = value.sized(Width::from_size(var_type.size()));
+    label.mov(Memory::at(address, 0), value);
+}
+
+fn generate_declaration(declaration: &Declaration, label: &mut Label) {
+    let var = declaration.variable();
+    let offset = match var.storage() {
+        Storage::Local(offset) => *offset,
+        Storage::Static { .. } => return generate_static_variable(declaration, label),
+        Storage::Extern => return,
+    };
+    if let Some(init) = declaration.initializer() {
+        if let Array(_, _) = var.var_type().unqualified() {
+            // 初期化子のない要素を0にするため、先に配列全体を0で埋める
+            label.mov(RDI, RBP);
+            label.sub(RDI, offset);
+            label.mov(RCX, var.var_type().size());
+            label.mov(AL, 0);
+            label.rep_stosb();
+        }
+        gen_initializer(offset, var.var_type(), init, label);
+    }
+}
+
+/// 静的な領域に置かれる変数を、初期値とともにデータ領域に出力する。
+///
+/// 関数の中のstatic変数でも使えるように、出力した後でテキスト領域に戻る。
+fn generate_static_variable(declaration: &Declaration, label: &mut Label) {
+    let var = declaration.variable();
+    let (symbol, internal) = match var.storage() {
+        Storage::Static { symbol, internal } => (symbol, *internal),
+        _ => return,
+    };
+    let var_type = var.var_type();
+    let size = var_type.size();
+    match declaration.initializer() {
+        Some(_) => label.directive(".data"),
+        None => label.directive(".bss"),
+    }
+    if !internal {
+        label.directive(format!(".globl {}", symbol));
+    }
+    label.directive(format!(".align {}", var_type.align()));
+    label.directive(format!(".type {}, @object", symbol));
+    label.directive(format!(".size {}, {}", symbol, size));
+    label.d_label(symbol);
+    match declaration.initializer() {
+        Some(init) => gen_static_initializer(var_type, init, label),
+        None => label.directive(format!(".zero {}", size)),
+    }
+    label.directive(".text");
+}
+
+/// 型var_typeの領域の初期値を、データとして出力する。
+fn gen_static_initializer(var_type: &VariableType, init: &Initializer, label: &mut Label) {
+    match (var_type.unqualified(), init) {
+        (Array(t, size), Initializer::List(list)) => {
+            for init in list {
+                gen_static_initializer(t, init, label);
+            }
+            let rest = (*size - list.len() as i64) * t.size();
+            if rest > 0 {
+                label.directive(format!(".zero {}", rest));
+            }
+        }
+        (_, Initializer::List(list)) => match list.first() {
+            Some(init) => gen_static_initializer(var_type, init, label),
+            None => label.directive(format!(".zero {}", var_type.size())),
+        },
+        (_, Initializer::Expression(expr)) => match constant::eval_static(expr) {
+            Ok(StaticValue::Number(n)) => match var_type.size() {
+                1 => label.directive(format!(".byte {}", n as u8)),
+                2 => label.directive(format!(".short {}", n as u16)),
+                4 => label.directive(format!(".long {}", n as u32)),
+                _ => label.directive(format!(".quad {}", n)),
+            },
+            Ok(StaticValue::Address(symbol, offset)) => {
+                let symbol = match symbol {
+                    Symbol::Name(name) => name,
+                    Symbol::Literal(bytes) => format!(".L{}", label.literal(&bytes)),
+                };
+                if offset == 0 {
+                    label.directive(format!(".quad {}", symbol));
+                } else {
+                    label.directive(format!(".quad {}{:+}", symbol, offset));
+                }
+            }
+            Err(e) => unreachable!("{}", e),
+        },
+    }
+}
+
+/// rbp-offsetの位置にある型var_typeの領域を初期化子で初期化する。
+fn gen_initializer(offset: i64, var_type: &VariableType, init: &Initializer, label: &mut Label) {
+    match (var_type.unqualified(), init) {
+        (Array(t, _), Initializer::List(list)) => {
+            for (i, init) in list.iter().enumerate() {
+                gen_initializer(offset - i as i64 * t.size(), t, init, label);
+            }
+        }
+        (_, Initializer::List(list)) => {
+            for init in list {
+                gen_initializer(offset, var_type, init, label);
+            }
+        }
+        (_, Initializer::Expression(expr)) => {
+            gen_address(offset, label);
+            generate_expression(expr, label);
+            label.pop(RDI);
+            label.pop(RAX);
+            gen_store(var_type, &RAX, &RDI, label);
+        }
+    }
 }
 
 fn gen_condition(condition: &Expression, label: &mut Label) {
-    generate_expression(&*condition, label);
-    label.pop("rax");
-    let s = match condition.kind() {
-        Ok(k) if k.size() == 4 => "eax",
-        _ => "rax",
+    gen_value(condition, label);
+    let rax = if is_dword(condition.var_type()) {
+        EAX
+    } else {
+        RAX
     };
-    label.cmp(s, "0");
+    label.cmp(rax, 0);
+}
+
+/// 可変長引数の関数の先頭で、引数のレジスタをレジスタ保存領域に書き込む。
+fn gen_register_save_area(area: i64, label: &mut Label) {
+    for (i, register) in register::ARGS_REGISTER.iter().enumerate() {
+        let save = Memory::new(Register::Rbp, i as i64 * 8 - area);
+        label.mov(save, Operand::Register(*register, Width::Qword));
+    }
+    // alはXMMレジスタで渡された引数の個数
+    let lskip = label.get();
+    label.cmp(AL, 0);
+    label.je(lskip);
+    for i in 0..8 {
+        let save = Memory::new(Register::Rbp, 48 + i as i64 * 16 - area);
+        label.movaps(save, Operand::Xmm(i));
+    }
+    label.l_label(lskip);
 }
 
 fn gen_parameter(node: &Variable, label: &mut Label, parameter_index: usize) {
     generate_variable(node, label);
-    label.pop("rax");
-
-    match node.var_type().size() {
-        4 => label.mov("[rax]", register::ARGS_REGISTER[parameter_index][1]),
-        _ => label.mov("[rax]", register::ARGS_REGISTER[parameter_index][0]),
-    }
+    label.pop(RAX);
+
+    let register = register::ARGS_REGISTER[parameter_index];
+    let width = Width::from_size(node.var_type().size());
+    label.mov(
+        Memory::new(Register::Rax, 0),
+        Operand::Register(register, width),
+    );
 }
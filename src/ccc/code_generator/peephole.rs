@@ -0,0 +1,302 @@
+//! のぞき穴最適化
+//!
+//! スタックマシンとして生成した関数の命令の列から、無駄な命令を取り除く。
+
+use super::instruction::{Instruction, Memory, Operand, Width};
+use super::register::Register;
+
+/// 命令の列を書き換えられなくなるまで最適化する。
+pub fn optimize(instructions: &mut Vec<Instruction>) {
+    instructions.retain(|instruction| !matches!(instruction, Instruction::Comment(_)));
+    loop {
+        let mut changed = remove_push_pop(instructions);
+        changed |= fold_address(instructions);
+        changed |= remove_jump(instructions);
+        changed |= remove_overwritten(instructions);
+        changed |= remove_unreachable(instructions);
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// push Xの後、Xとスタックを変えずにpop Yする組を、mov Y, Xに置き換える。
+/// XとYが同じレジスタの場合は、両方とも取り除く。
+fn remove_push_pop(instructions: &mut Vec<Instruction>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i < instructions.len() {
+        let pushed = match &instructions[i] {
+            Instruction::Push(x @ Operand::Register(_, Width::Qword))
+            | Instruction::Push(x @ Operand::Immediate(_)) => x.clone(),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        match find_pop(&instructions[i + 1..], &pushed) {
+            Some((j, popped)) => {
+                let j = i + 1 + j;
+                if popped == pushed {
+                    instructions.remove(j);
+                } else {
+                    instructions[j] = Instruction::Mov(popped, pushed);
+                }
+                instructions.remove(i);
+                changed = true;
+            }
+            None => i += 1,
+        }
+    }
+    changed
+}
+
+/// pushした値をそのまま取り出せるpopを探して、その位置とpopするレジスタを返す。
+///
+/// 間の命令は、スタックやpopするレジスタを使わず、pushしたレジスタに書き込まないものだけにする。
+fn find_pop(instructions: &[Instruction], pushed: &Operand) -> Option<(usize, Operand)> {
+    let pushed = pushed.registers();
+    for (j, instruction) in instructions.iter().enumerate() {
+        if let Instruction::Pop(popped @ Operand::Register(y, Width::Qword)) = instruction {
+            let between = &instructions[..j];
+            let free = between
+                .iter()
+                .all(|i| !mentions(i).contains(y) && !writes(i).iter().any(|r| pushed.contains(r)));
+            return if free {
+                Some((j, popped.clone()))
+            } else {
+                None
+            };
+        }
+        if !is_simple(instruction) || mentions(instruction).contains(&Register::Rsp) {
+            return None;
+        }
+    }
+    None
+}
+
+/// スタックや制御の流れに関わらない命令か確かめる。
+fn is_simple(instruction: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        Mov(..)
+            | Movzx(..)
+            | Movsx(..)
+            | Movsxd(..)
+            | Lea(..)
+            | Add(..)
+            | Sub(..)
+            | Imul(..)
+            | Cqo
+            | Cdq
+            | Div(_)
+            | Idiv(_)
+            | Cmp(..)
+            | Set(..)
+    )
+}
+
+/// 命令が読み書きするレジスタ
+fn mentions(instruction: &Instruction) -> Vec<Register> {
+    let mut registers = instruction.implicit_registers();
+    for operand in instruction.operands() {
+        registers.extend(operand.registers());
+    }
+    registers
+}
+
+/// 命令が書き込むレジスタ
+fn writes(instruction: &Instruction) -> Vec<Register> {
+    use Instruction::*;
+    let mut registers = instruction.implicit_registers();
+    match instruction {
+        Mov(Operand::Register(r, _), _)
+        | Movzx(Operand::Register(r, _), _)
+        | Movsx(Operand::Register(r, _), _)
+        | Movsxd(Operand::Register(r, _), _)
+        | Lea(Operand::Register(r, _), _)
+        | Add(Operand::Register(r, _), _)
+        | Sub(Operand::Register(r, _), _)
+        | Imul(Operand::Register(r, _), _)
+        | Set(_, Operand::Register(r, _))
+        | Pop(Operand::Register(r, _)) => registers.push(*r),
+        _ => {}
+    }
+    registers
+}
+
+/// アドレスの計算を、メモリのオペランドにまとめる。
+///
+/// mov r, rbp; sub r, N はlea r, [rbp-N]にする。
+/// lea r, M の直後でrを上書きしながら[r+d]を読む命令は、M+dを直接読む。
+fn fold_address(instructions: &mut Vec<Instruction>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i + 1 < instructions.len() {
+        if let Some(lea) = address(&instructions[i], &instructions[i + 1]) {
+            instructions[i] = lea;
+            instructions.remove(i + 1);
+            changed = true;
+        } else if let Some(load) = load(&instructions[i], &instructions[i + 1]) {
+            instructions[i] = load;
+            instructions.remove(i + 1);
+            changed = true;
+        } else {
+            i += 1;
+        }
+    }
+    changed
+}
+
+/// mov r, rbp; sub r, N をlea r, [rbp-N]にする。
+fn address(first: &Instruction, second: &Instruction) -> Option<Instruction> {
+    match (first, second) {
+        (
+            Instruction::Mov(
+                dst @ Operand::Register(r, Width::Qword),
+                Operand::Register(Register::Rbp, Width::Qword),
+            ),
+            Instruction::Sub(Operand::Register(s, Width::Qword), Operand::Immediate(n)),
+        ) if r == s => Some(Instruction::Lea(
+            dst.clone(),
+            Memory::new(Register::Rbp, -n).into(),
+        )),
+        _ => None,
+    }
+}
+
+/// lea r, M; mov r, [r+d] をmov r, M+dにする。符号拡張やゼロ拡張の読み込みも同じ。
+fn load(first: &Instruction, second: &Instruction) -> Option<Instruction> {
+    use Instruction::{Lea, Mov, Movsx, Movsxd, Movzx};
+    let (r, address) = match first {
+        Lea(Operand::Register(r, Width::Qword), Operand::Memory(address)) => (*r, address),
+        _ => return None,
+    };
+    let (dst, src) = match second {
+        Mov(dst, Operand::Memory(src))
+        | Movzx(dst, Operand::Memory(src))
+        | Movsx(dst, Operand::Memory(src))
+        | Movsxd(dst, Operand::Memory(src)) => (dst, src),
+        _ => return None,
+    };
+    // 32ビット以上のレジスタへの書き込みは、レジスタ全体を上書きする
+    match dst {
+        Operand::Register(d, Width::Qword) | Operand::Register(d, Width::Dword) if *d == r => {}
+        _ => return None,
+    }
+    if src.base_register() != Some(r) {
+        return None;
+    }
+    let src = Operand::Memory(src.rebase(address));
+    Some(match second {
+        Mov(..) => Mov(dst.clone(), src),
+        Movzx(..) => Movzx(dst.clone(), src),
+        Movsx(..) => Movsx(dst.clone(), src),
+        _ => Movsxd(dst.clone(), src),
+    })
+}
+
+/// 直後のラベルへのジャンプを取り除く。
+fn remove_jump(instructions: &mut Vec<Instruction>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i < instructions.len() {
+        let target = match instructions[i] {
+            Instruction::Jmp(l) | Instruction::Jcc(_, l) => l,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let next = instructions[i + 1..]
+            .iter()
+            .take_while(|instruction| matches!(instruction, Instruction::Label(_)))
+            .any(|instruction| *instruction == Instruction::Label(target));
+        if next {
+            instructions.remove(i);
+            changed = true;
+        } else {
+            i += 1;
+        }
+    }
+    changed
+}
+
+/// 直後の命令が読まずに上書きするレジスタへの書き込みを取り除く。
+///
+/// volatileな変数の読み込みを消さないように、メモリから読む命令は取り除かない。
+fn remove_overwritten(instructions: &mut Vec<Instruction>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i + 1 < instructions.len() {
+        let overwritten = match (overwrite(&instructions[i]), overwrite(&instructions[i + 1])) {
+            (Some((first, _)), Some((second, src))) => {
+                first == second && !src.contains(&first) && !loads(&instructions[i])
+            }
+            _ => false,
+        };
+        if overwritten {
+            instructions.remove(i);
+            changed = true;
+        } else {
+            i += 1;
+        }
+    }
+    changed
+}
+
+/// レジスタ全体を上書きするだけの命令のとき、書き込むレジスタと、読むレジスタを返す。
+fn overwrite(instruction: &Instruction) -> Option<(Register, Vec<Register>)> {
+    use Instruction::{Lea, Mov, Movsx, Movsxd, Movzx};
+    match instruction {
+        Mov(Operand::Register(r, width), src)
+        | Movzx(Operand::Register(r, width), src)
+        | Movsx(Operand::Register(r, width), src)
+        | Movsxd(Operand::Register(r, width), src)
+        | Lea(Operand::Register(r, width), src)
+            if *width == Width::Qword || *width == Width::Dword =>
+        {
+            Some((*r, src.registers()))
+        }
+        _ => None,
+    }
+}
+
+/// メモリから値を読む命令か確かめる。leaはアドレスを求めるだけなので読まない
+fn loads(instruction: &Instruction) -> bool {
+    !matches!(instruction, Instruction::Lea(..))
+        && instruction
+            .operands()
+            .iter()
+            .skip(1)
+            .any(|operand| matches!(operand, Operand::Memory(_)))
+}
+
+/// retやjmpの後の、次のラベルまでの実行されない命令を取り除く。
+fn remove_unreachable(instructions: &mut Vec<Instruction>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i < instructions.len() {
+        if matches!(
+            instructions[i],
+            Instruction::Ret | Instruction::Jmp(_) | Instruction::TailJmp(_)
+        ) {
+            let end = instructions[i + 1..]
+                .iter()
+                .position(|instruction| {
+                    matches!(
+                        instruction,
+                        Instruction::Label(_) | Instruction::Symbol(_) | Instruction::Directive(_)
+                    )
+                })
+                .map_or(instructions.len(), |n| i + 1 + n);
+            if end > i + 1 {
+                instructions.drain(i + 1..end);
+                changed = true;
+            }
+        }
+        i += 1;
+    }
+    changed
+}
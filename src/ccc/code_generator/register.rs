@@ -1,20 +1,97 @@
-type RegisterAlias = [&'static str; 4];
-// const RAX: RegisterAlias = ["RAX", "EAX", "AX", "AL"];
-const RDI: RegisterAlias = ["RDI", "EDI", "DI", "DIL"];
-const RSI: RegisterAlias = ["RSI", "ESI", "SI", "SIL"];
-const RDX: RegisterAlias = ["RDX", "EDX", "DX", "DL"];
-const RCX: RegisterAlias = ["RCX", "ECX", "CX", "CL"];
-// const RBP: RegisterAlias = ["RBP", "EBP", "BP", "BPL"];
-// const RSP: RegisterAlias = ["RSP", "ESP", "SP", "SPL"];
-// const RBX: RegisterAlias = ["RBX", "EBX", "BX", "BL"];
-const R8: RegisterAlias = ["R8", "R8D", "R8W", "R8B"];
-const R9: RegisterAlias = ["R9", "R9D", "R9W", "R9B"];
-// const R10: RegisterAlias = ["R10", "R10D", "R10W", "R10B"];
-// const R11: RegisterAlias = ["R11", "R11D", "R11W", "R11B"];
-// const R12: RegisterAlias = ["R12", "R12D", "R12W", "R12B"];
-// const R13: RegisterAlias = ["R13", "R13D", "R13W", "R13B"];
-// const R14: RegisterAlias = ["R14", "R14D", "R14W", "R14B"];
-// const R15: RegisterAlias = ["R15", "R15D", "R15W", "R15B"];
+use super::instruction::{Operand, Width};
 
-/// 関数の引数に使うレジスタ。６個までの引数に対応。
-pub const ARGS_REGISTER: [RegisterAlias; 6] = [RDI, RSI, RDX, RCX, R8, R9];
+/// 汎用レジスタ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Register {
+    /// 幅に合わせたレジスタの名前
+    pub fn name(self, width: Width) -> &'static str {
+        let names = match self {
+            Register::Rax => ["rax", "eax", "ax", "al"],
+            Register::Rcx => ["rcx", "ecx", "cx", "cl"],
+            Register::Rdx => ["rdx", "edx", "dx", "dl"],
+            Register::Rbx => ["rbx", "ebx", "bx", "bl"],
+            Register::Rsp => ["rsp", "esp", "sp", "spl"],
+            Register::Rbp => ["rbp", "ebp", "bp", "bpl"],
+            Register::Rsi => ["rsi", "esi", "si", "sil"],
+            Register::Rdi => ["rdi", "edi", "di", "dil"],
+            Register::R8 => ["r8", "r8d", "r8w", "r8b"],
+            Register::R9 => ["r9", "r9d", "r9w", "r9b"],
+            Register::R10 => ["r10", "r10d", "r10w", "r10b"],
+            Register::R11 => ["r11", "r11d", "r11w", "r11b"],
+            Register::R12 => ["r12", "r12d", "r12w", "r12b"],
+            Register::R13 => ["r13", "r13d", "r13w", "r13b"],
+            Register::R14 => ["r14", "r14d", "r14w", "r14b"],
+            Register::R15 => ["r15", "r15d", "r15w", "r15b"],
+        };
+        match width {
+            Width::Qword => names[0],
+            Width::Dword => names[1],
+            Width::Word => names[2],
+            Width::Byte => names[3],
+        }
+    }
+}
+
+pub const RAX: Operand = Operand::Register(Register::Rax, Width::Qword);
+pub const EAX: Operand = Operand::Register(Register::Rax, Width::Dword);
+pub const AL: Operand = Operand::Register(Register::Rax, Width::Byte);
+pub const RDI: Operand = Operand::Register(Register::Rdi, Width::Qword);
+pub const EDI: Operand = Operand::Register(Register::Rdi, Width::Dword);
+pub const RDX: Operand = Operand::Register(Register::Rdx, Width::Qword);
+pub const EDX: Operand = Operand::Register(Register::Rdx, Width::Dword);
+pub const RCX: Operand = Operand::Register(Register::Rcx, Width::Qword);
+pub const RBP: Operand = Operand::Register(Register::Rbp, Width::Qword);
+pub const RSP: Operand = Operand::Register(Register::Rsp, Width::Qword);
+pub const R10: Operand = Operand::Register(Register::R10, Width::Qword);
+pub const R11: Operand = Operand::Register(Register::R11, Width::Qword);
+
+/// 関数の引数に使うレジスタ。7個目以降の引数はスタックで渡す。
+pub const ARGS_REGISTER: [Register; 6] = [
+    Register::Rdi,
+    Register::Rsi,
+    Register::Rdx,
+    Register::Rcx,
+    Register::R8,
+    Register::R9,
+];
+
+/// 関数呼び出しで壊れるレジスタ
+pub const CALLER_SAVED: [Register; 9] = [
+    Register::Rax,
+    Register::Rcx,
+    Register::Rdx,
+    Register::Rsi,
+    Register::Rdi,
+    Register::R8,
+    Register::R9,
+    Register::R10,
+    Register::R11,
+];
+
+/// 関数呼び出しで保存されるレジスタ。使う関数は、入口で保存して出口で戻す
+pub const CALLEE_SAVED: [Register; 5] = [
+    Register::Rbx,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
@@ -0,0 +1,674 @@
+//! 中間表現からの命令選択
+//!
+//! 中間表現の値をそれぞれ仮想レジスタにして、基本ブロックの順に命令を並べる。
+//! φ関数は、前のブロックから移るときのコピーにする。
+
+use super::gen_register_save_area;
+use super::instruction::{Condition, Memory, Name, Operand, Virtual, Width};
+use super::label::Label;
+use super::register::{self, Register, AL, EAX, EDX, R11, RAX, RBP, RCX, RDI, RSP};
+use crate::ccc::ir::{self, BinaryOp, Callee, Kind, Terminator, Type, Value};
+use std::collections::{HashMap, HashSet};
+
+/// 中間表現の関数の命令を並べる。
+pub fn function(function: &ir::Function, label: &mut Label) {
+    let name = &function.name;
+    // staticな関数は他のファイルから見えないように、.globlを付けない
+    if !function.internal {
+        label.directive(format!(".globl {}", name));
+    }
+    label.directive(format!(".type {}, @function", name));
+    label.f_label(name);
+    label.push(RBP);
+    label.mov(RBP, RSP);
+    label.sub(RSP, function.stack_size);
+    if let Some(area) = function.va_area {
+        gen_register_save_area(area, label);
+    }
+
+    let labels = function.blocks.iter().map(|_| label.get()).collect();
+    let (addresses, skipped) = addresses(function);
+    let mut selector = Selector {
+        function,
+        label,
+        registers: HashMap::new(),
+        labels,
+        addresses,
+        skipped,
+        uses: function.uses(),
+        edges: Vec::new(),
+    };
+    for (b, block) in function.blocks.iter().enumerate() {
+        selector.block(b, block);
+    }
+    label.directive(format!(".size {}, .-{}", name, name));
+}
+
+/// メモリのオペランドにまとめるアドレス。[base+index*scale]
+#[derive(Clone)]
+struct Address {
+    base: AddressBase,
+    /// インデックスの値とスケール
+    index: Option<(Value, u8)>,
+}
+
+#[derive(Clone)]
+enum AddressBase {
+    /// ローカル変数や静的な変数の位置
+    Memory(Memory),
+    /// 値のアドレスにdisplacementを足した位置
+    Value(Value, i64),
+}
+
+/// アドレスの足し算の項
+enum Term {
+    /// ローカル変数や静的な変数のアドレスの値と、その位置
+    Base(Value, Memory),
+    /// 1, 2, 4, 8倍する値と、その倍率、掛け算の値
+    Index(Value, u8, Value),
+    Register(Value),
+    Displacement(i64),
+}
+
+/// 読み書きのアドレスとしてだけ使われる値を、メモリのオペランドにまとめる。
+///
+/// ローカル変数や静的な変数のアドレスと、base+index*scale+displacementの形の足し算をまとめる。
+/// まとめた値と、まとめた足し算だけが使う掛け算の値を返す。どちらも命令を出力しない。
+fn addresses(function: &ir::Function) -> (HashMap<Value, Address>, HashSet<Value>) {
+    let uses = function.uses();
+    let mut defs = HashMap::new();
+    let mut address_uses: HashMap<Value, usize> = HashMap::new();
+    for instruction in function.blocks.iter().flat_map(|block| &block.instructions) {
+        if let Some(value) = instruction.value {
+            defs.insert(value, &instruction.kind);
+        }
+        match &instruction.kind {
+            Kind::Load {
+                address: ir::Operand::Value(value),
+                ..
+            }
+            | Kind::Store {
+                address: ir::Operand::Value(value),
+                ..
+            }
+            | Kind::Zero(ir::Operand::Value(value), _) => {
+                *address_uses.entry(*value).or_insert(0) += 1;
+            }
+            _ => {}
+        }
+    }
+    let count = |map: &HashMap<Value, usize>, value: &Value| map.get(value).copied().unwrap_or(0);
+
+    // 足し算を先に決めて、まとめた足し算が使う値を数える
+    let mut addresses = HashMap::new();
+    let mut consumed: HashMap<Value, usize> = HashMap::new();
+    let mut skipped = HashSet::new();
+    for (value, kind) in &defs {
+        if count(&address_uses, value) != count(&uses, value) {
+            continue;
+        }
+        if let Kind::Binary(BinaryOp::Add, Type::I64 | Type::U64, l, r) = kind {
+            let (l, r) = (term(l, &defs, &uses), term(r, &defs, &uses));
+            if let Some(address) = combine(&l, &r).or_else(|| combine(&r, &l)) {
+                for term in [l, r] {
+                    match term {
+                        Some(Term::Base(base, _)) => *consumed.entry(base).or_insert(0) += 1,
+                        Some(Term::Index(_, _, product)) => {
+                            skipped.insert(product);
+                        }
+                        _ => {}
+                    }
+                }
+                addresses.insert(*value, address);
+            }
+        }
+    }
+    for (value, kind) in &defs {
+        let memory = match kind {
+            Kind::Local(offset) => Memory::new(Register::Rbp, -offset),
+            Kind::Global(name) => Memory::rip(Name::Global(name.clone())),
+            _ => continue,
+        };
+        if count(&address_uses, value) + count(&consumed, value) == count(&uses, value) {
+            let base = AddressBase::Memory(memory);
+            addresses.insert(*value, Address { base, index: None });
+        }
+    }
+    (addresses, skipped)
+}
+
+/// アドレスの足し算のオペランドを項にする。
+fn term(
+    operand: &ir::Operand,
+    defs: &HashMap<Value, &Kind>,
+    uses: &HashMap<Value, usize>,
+) -> Option<Term> {
+    let value = match operand {
+        ir::Operand::Number(n) if *n as i32 as i64 == *n => return Some(Term::Displacement(*n)),
+        ir::Operand::Number(_) => return None,
+        ir::Operand::Value(value) => *value,
+    };
+    Some(match defs.get(&value) {
+        Some(Kind::Local(offset)) => Term::Base(value, Memory::new(Register::Rbp, -offset)),
+        Some(Kind::Global(name)) => Term::Base(value, Memory::rip(Name::Global(name.clone()))),
+        Some(Kind::Binary(BinaryOp::Mul, Type::I64 | Type::U64, a, b)) if uses[&value] == 1 => {
+            match (a, b) {
+                (ir::Operand::Value(index), ir::Operand::Number(scale @ (1 | 2 | 4 | 8)))
+                | (ir::Operand::Number(scale @ (1 | 2 | 4 | 8)), ir::Operand::Value(index)) => {
+                    Term::Index(*index, *scale as u8, value)
+                }
+                _ => Term::Register(value),
+            }
+        }
+        _ => Term::Register(value),
+    })
+}
+
+/// 2つの項の和を、アドレスにまとめる。
+fn combine(l: &Option<Term>, r: &Option<Term>) -> Option<Address> {
+    let (base, index) = match (l.as_ref()?, r.as_ref()?) {
+        (Term::Base(_, memory), Term::Displacement(d)) => {
+            (AddressBase::Memory(memory.clone().displaced(*d)), None)
+        }
+        // ripからの相対アドレスには、インデックスを使えない
+        (Term::Base(_, memory), Term::Index(index, scale, _))
+            if memory.base_register().is_some() =>
+        {
+            (AddressBase::Memory(memory.clone()), Some((*index, *scale)))
+        }
+        (Term::Base(_, memory), Term::Register(index)) if memory.base_register().is_some() => {
+            (AddressBase::Memory(memory.clone()), Some((*index, 1)))
+        }
+        (Term::Register(base), Term::Index(index, scale, _)) => {
+            (AddressBase::Value(*base, 0), Some((*index, *scale)))
+        }
+        (Term::Register(base), Term::Register(index)) => {
+            (AddressBase::Value(*base, 0), Some((*index, 1)))
+        }
+        (Term::Register(base), Term::Displacement(d)) => (AddressBase::Value(*base, *d), None),
+        _ => return None,
+    };
+    Some(Address { base, index })
+}
+
+struct Selector<'f, 'l, 'a> {
+    function: &'f ir::Function,
+    label: &'l mut Label<'a>,
+    /// 値を入れる仮想レジスタ
+    registers: HashMap<Value, Virtual>,
+    /// ブロックのラベル
+    labels: Vec<u64>,
+    /// アドレスとしてだけ使う値と、そのアドレス
+    addresses: HashMap<Value, Address>,
+    /// アドレスにまとめて、求めなくてよい値
+    skipped: HashSet<Value>,
+    /// 値を使っているオペランドの数
+    uses: HashMap<Value, usize>,
+    /// ブロックの後に置く、φ関数のコピーをしてから移るラベルと、移る前と後のブロック
+    edges: Vec<(u64, usize, usize)>,
+}
+
+impl Selector<'_, '_, '_> {
+    fn block(&mut self, b: usize, block: &ir::Block) {
+        self.label.l_label(self.labels[b]);
+        let fused = self.fused(block);
+        for (k, instruction) in block.instructions.iter().enumerate() {
+            if fused && k + 1 == block.instructions.len() {
+                break;
+            }
+            self.instruction(instruction);
+        }
+
+        match &block.terminator {
+            Terminator::Jump(t) => {
+                self.copies(b, *t);
+                self.label.jmp(self.labels[*t]);
+            }
+            Terminator::Branch(_, ir::Operand::Number(n), t, f) => {
+                let t = if *n != 0 { *t } else { *f };
+                self.copies(b, t);
+                self.label.jmp(self.labels[t]);
+            }
+            Terminator::Branch(ty, condition, t, f) => {
+                let (lt, lf) = (self.edge(b, *t), self.edge(b, *f));
+                let condition = if fused {
+                    let last = block.instructions.last().expect("比較の命令がありません。");
+                    match &last.kind {
+                        Kind::Binary(op, ty, l, r) => self.compare(*op, *ty, l, r),
+                        _ => unreachable!("比較の命令ではありません。"),
+                    }
+                } else {
+                    let condition = self.register(condition, width(*ty));
+                    self.label.cmp(condition, 0);
+                    Condition::Ne
+                };
+                self.label.jcc(condition, lt);
+                self.label.jmp(lf);
+            }
+            Terminator::Return(value) => {
+                let value = self.operand(value, Width::Qword);
+                self.label.mov(RAX, value);
+                self.label.mov(RSP, RBP);
+                self.label.pop(RBP);
+                self.label.ret();
+            }
+            Terminator::TailCall(callee, args) => self.tail_call(callee, args),
+        }
+
+        for (l, from, to) in std::mem::take(&mut self.edges) {
+            self.label.l_label(l);
+            self.copies(from, to);
+            self.label.jmp(self.labels[to]);
+        }
+    }
+
+    /// ブロックの最後の比較を、分岐の条件として直接使うか確かめる。
+    fn fused(&self, block: &ir::Block) -> bool {
+        let condition = match &block.terminator {
+            Terminator::Branch(_, ir::Operand::Value(condition), _, _) => condition,
+            _ => return false,
+        };
+        match block.instructions.last() {
+            Some(ir::Instruction {
+                value: Some(value),
+                kind: Kind::Binary(op, _, _, _),
+            }) => {
+                value == condition
+                    && self.uses.get(value) == Some(&1)
+                    && !matches!(
+                        op,
+                        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    /// ブロックfromからtoに移るときのラベル。toにφ関数がある場合は、コピーをするラベルを作る。
+    fn edge(&mut self, from: usize, to: usize) -> u64 {
+        let has_phi = self.function.blocks[to]
+            .instructions
+            .iter()
+            .any(|instruction| matches!(instruction.kind, Kind::Phi(_)));
+        if !has_phi {
+            return self.labels[to];
+        }
+        let l = self.label.get();
+        self.edges.push((l, from, to));
+        l
+    }
+
+    /// ブロックfromからtoに移るときに、toのφ関数の値をコピーする。
+    fn copies(&mut self, from: usize, to: usize) {
+        let mut moves = Vec::new();
+        for instruction in &self.function.blocks[to].instructions {
+            if let (Some(value), Kind::Phi(incoming)) = (instruction.value, &instruction.kind) {
+                if let Some((_, source)) = incoming.iter().find(|(b, _)| *b == from) {
+                    if *source != ir::Operand::Value(value) {
+                        moves.push((value, *source));
+                    }
+                }
+            }
+        }
+        // コピー先を別のコピーで読む場合は、先にすべての値を一時的なレジスタに移す
+        let overlapped = moves
+            .iter()
+            .any(|(_, source)| moves.iter().any(|(v, _)| *source == ir::Operand::Value(*v)));
+        let mut sources = Vec::new();
+        for (_, source) in &moves {
+            let source = self.operand(source, Width::Qword);
+            if overlapped {
+                let temporary = self.label.new_virtual();
+                self.label.mov(temporary.clone(), source);
+                sources.push(temporary);
+            } else {
+                sources.push(source);
+            }
+        }
+        for ((value, _), source) in moves.iter().zip(sources) {
+            let register = self.value(*value);
+            self.label.mov(register, source);
+        }
+    }
+
+    /// 値を入れる仮想レジスタ
+    fn value(&mut self, value: Value) -> Operand {
+        let register = match self.registers.get(&value) {
+            Some(register) => *register,
+            None => {
+                let register = match self.label.new_virtual() {
+                    Operand::Virtual(register, _) => register,
+                    _ => unreachable!(),
+                };
+                self.registers.insert(value, register);
+                register
+            }
+        };
+        Operand::Virtual(register, Width::Qword)
+    }
+
+    /// オペランドを、命令のオペランドにする。32ビットに収まらない数はレジスタに読み込む。
+    fn operand(&mut self, operand: &ir::Operand, width: Width) -> Operand {
+        match operand {
+            ir::Operand::Number(n) if *n as i32 as i64 == *n => Operand::Immediate(*n),
+            _ => self.register(operand, width),
+        }
+    }
+
+    /// オペランドを、レジスタに入れる。
+    fn register(&mut self, operand: &ir::Operand, width: Width) -> Operand {
+        match operand {
+            ir::Operand::Value(value) => self.value(*value).sized(width),
+            ir::Operand::Number(n) => {
+                let register = self.label.new_virtual();
+                self.label.mov(register.clone(), *n);
+                register.sized(width)
+            }
+        }
+    }
+
+    /// アドレスのオペランドが指すメモリ
+    fn memory(&mut self, address: &ir::Operand) -> Memory {
+        let folded = match address {
+            ir::Operand::Value(value) => self.addresses.get(value).cloned(),
+            ir::Operand::Number(_) => None,
+        };
+        let Address { base, index } = match folded {
+            Some(address) => address,
+            None => return Memory::at(&self.register(address, Width::Qword), 0),
+        };
+        let memory = match base {
+            AddressBase::Memory(memory) => memory,
+            AddressBase::Value(value, displacement) => Memory::at(&self.value(value), displacement),
+        };
+        match index {
+            Some((value, scale)) => memory.indexed(&self.value(value), scale),
+            None => memory,
+        }
+    }
+
+    fn instruction(&mut self, instruction: &ir::Instruction) {
+        let result = match instruction.value {
+            Some(value) if self.addresses.contains_key(&value) || self.skipped.contains(&value) => {
+                return
+            }
+            Some(value) => self.value(value),
+            // 値を定義しない命令では使わない
+            None => RAX,
+        };
+        match &instruction.kind {
+            Kind::Parameter(i) => {
+                let register = register::ARGS_REGISTER[*i];
+                self.label
+                    .mov(result, Operand::Register(register, Width::Qword));
+            }
+
+            Kind::Copy(a) => {
+                let a = self.operand(a, Width::Qword);
+                self.label.mov(result, a);
+            }
+
+            Kind::Binary(op, ty, l, r) => self.binary(&result, *op, *ty, l, r),
+
+            Kind::Cast(from, to, a) => {
+                let a = self.operand(a, Width::Qword);
+                self.label.mov(result.clone(), a);
+                cast(*from, *to, &result, self.label);
+            }
+
+            Kind::Local(offset) => self.label.lea(result, Memory::new(Register::Rbp, -offset)),
+
+            Kind::Global(name) => self
+                .label
+                .lea(result, Memory::rip(Name::Global(name.clone()))),
+
+            Kind::Got(name) => {
+                let got = Memory::rip(Name::Got(name.clone())).sized(Width::Qword);
+                self.label.mov(result, got);
+            }
+
+            Kind::String(bytes) => {
+                let l = self.label.literal(bytes);
+                self.label.lea(result, Memory::rip(Name::Local(l)));
+            }
+
+            Kind::Load { ty, address, .. } => {
+                let address = self.memory(address);
+                match ty {
+                    Type::I8 => self.label.movsx(result, address.sized(Width::Byte)),
+                    Type::I16 => self.label.movsx(result, address.sized(Width::Word)),
+                    Type::I32 => self.label.movsxd(result, address.sized(Width::Dword)),
+                    Type::U8 => self.label.movzx(result, address.sized(Width::Byte)),
+                    Type::U16 => self.label.movzx(result, address.sized(Width::Word)),
+                    Type::U32 => self
+                        .label
+                        .mov(result.sized(Width::Dword), address.sized(Width::Dword)),
+                    Type::I64 | Type::U64 => self.label.mov(result, address),
+                }
+            }
+
+            Kind::Store {
+                ty, address, value, ..
+            } => {
+                let width = Width::from_size(ty.size());
+                let value = match value {
+                    // 書き込む大きさに切り詰めた数を、そのまま書き込む
+                    ir::Operand::Number(n) => match width {
+                        Width::Byte => Operand::Immediate(*n as i8 as i64),
+                        Width::Word => Operand::Immediate(*n as i16 as i64),
+                        Width::Dword => Operand::Immediate(*n as i32 as i64),
+                        Width::Qword => self.operand(value, width),
+                    },
+                    _ => self.register(value, width),
+                };
+                let address = self.memory(address).sized(width);
+                self.label.mov(address, value);
+            }
+
+            Kind::Zero(address, size) => {
+                let address = self.memory(address);
+                self.label.lea(RDI, address);
+                self.label.mov(RCX, *size);
+                self.label.mov(AL, 0);
+                self.label.rep_stosb();
+            }
+
+            Kind::Call(callee, args) => self.call(&result, callee, args),
+
+            Kind::VaStart { ap, named, area } => {
+                let ap = self.register(ap, Width::Qword);
+                let registers = register::ARGS_REGISTER.len();
+                // gp_offset, fp_offset
+                let gp_offset = *named.min(&registers) as i64 * 8;
+                self.label
+                    .mov(Memory::at(&ap, 0).sized(Width::Dword), gp_offset);
+                self.label.mov(Memory::at(&ap, 4).sized(Width::Dword), 48);
+                // overflow_arg_area
+                let stack_args = named.saturating_sub(registers) as i64;
+                let area_address = self.label.new_virtual();
+                self.label.lea(
+                    area_address.clone(),
+                    Memory::new(Register::Rbp, 16 + stack_args * 8),
+                );
+                self.label.mov(Memory::at(&ap, 8), area_address.clone());
+                // reg_save_area
+                self.label
+                    .lea(area_address.clone(), Memory::new(Register::Rbp, -area));
+                self.label.mov(Memory::at(&ap, 16), area_address);
+            }
+
+            Kind::VaArg(ap) => {
+                let loverflow = self.label.get();
+                let lend = self.label.get();
+                let ap = self.register(ap, Width::Qword);
+                let next = self.label.new_virtual();
+                let gp_offset = Memory::at(&ap, 0).sized(Width::Dword);
+                self.label
+                    .mov(result.sized(Width::Dword), gp_offset.clone());
+                self.label.cmp(result.sized(Width::Dword), 48);
+                self.label.jae(loverflow);
+                // レジスタ保存領域から読む
+                self.label
+                    .lea(next.sized(Width::Dword), Memory::at(&result, 8));
+                self.label.mov(gp_offset, next.sized(Width::Dword));
+                self.label.add(result.clone(), Memory::at(&ap, 16));
+                self.label.jmp(lend);
+                // スタックで渡された引数から読む
+                self.label.l_label(loverflow);
+                self.label.mov(result.clone(), Memory::at(&ap, 8));
+                self.label.lea(next.clone(), Memory::at(&result, 8));
+                self.label.mov(Memory::at(&ap, 8), next);
+                self.label.l_label(lend);
+            }
+
+            // 前のブロックから移るときにコピーする
+            Kind::Phi(_) => {}
+        }
+    }
+
+    fn binary(
+        &mut self,
+        result: &Operand,
+        op: BinaryOp,
+        ty: Type,
+        l: &ir::Operand,
+        r: &ir::Operand,
+    ) {
+        let width = width(ty);
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                let l = self.operand(l, width);
+                let r = self.operand(r, width);
+                self.label.mov(result.sized(width), l);
+                match op {
+                    BinaryOp::Add => self.label.add(result.sized(width), r),
+                    BinaryOp::Sub => self.label.sub(result.sized(width), r),
+                    _ => self.label.imul(result.sized(width), r),
+                }
+            }
+            BinaryOp::Div => {
+                let l = self.operand(l, width);
+                let r = self.register(r, width);
+                self.label.mov(RAX.sized(width), l);
+                if ty.is_unsigned() {
+                    self.label.mov(EDX, 0);
+                    self.label.div(r);
+                } else if width == Width::Dword {
+                    self.label.cdq();
+                    self.label.idiv(r);
+                } else {
+                    self.label.cqo();
+                    self.label.idiv(r);
+                }
+                self.label.mov(result.clone(), RAX);
+            }
+            _ => {
+                let condition = self.compare(op, ty, l, r);
+                self.label.set(condition, result.sized(Width::Byte));
+                self.label.movzx(result.clone(), result.sized(Width::Byte));
+            }
+        }
+    }
+
+    /// 2つの値を比べて、比較が成り立つときの条件を返す。
+    fn compare(&mut self, op: BinaryOp, ty: Type, l: &ir::Operand, r: &ir::Operand) -> Condition {
+        let width = width(ty);
+        let l = self.register(l, width);
+        let r = self.operand(r, width);
+        self.label.cmp(l, r);
+        let unsigned = ty.is_unsigned();
+        match op {
+            BinaryOp::Eq => Condition::E,
+            BinaryOp::Ne => Condition::Ne,
+            BinaryOp::Lt if unsigned => Condition::B,
+            BinaryOp::Le if unsigned => Condition::Be,
+            BinaryOp::Lt => Condition::L,
+            BinaryOp::Le => Condition::Le,
+            _ => unreachable!("比較ではない計算です。"),
+        }
+    }
+
+    /// 関数を呼び出して、戻り値をresultに入れる。
+    fn call(&mut self, result: &Operand, callee: &Callee, args: &[ir::Operand]) {
+        let function = match callee {
+            Callee::Name(name) => Operand::Symbol(name.clone()),
+            Callee::Address(address) => self.register(address, Width::Qword),
+        };
+        // 7個目以降の引数は、右から順にスタックに積んで渡す
+        let registers = register::ARGS_REGISTER.len();
+        let stack_args = args.len().saturating_sub(registers) as i64;
+        let values: Vec<Operand> = args
+            .iter()
+            .map(|arg| self.operand(arg, Width::Qword))
+            .collect();
+        self.label.align_call(stack_args);
+        for value in values.iter().skip(registers).rev() {
+            self.label.push(value.clone());
+        }
+        for (register, value) in register::ARGS_REGISTER.iter().zip(values) {
+            self.label
+                .mov(Operand::Register(*register, Width::Qword), value);
+        }
+        // 浮動小数点数の引数はないので、可変長引数の関数のためにalを0にする
+        self.label.mov(EAX, 0);
+        self.label.call(function, stack_args);
+        self.label.mov(result.clone(), RAX);
+    }
+
+    /// 引数をレジスタに入れ、スタックフレームを片付けてから関数にジャンプする。
+    fn tail_call(&mut self, callee: &Callee, args: &[ir::Operand]) {
+        let function = match callee {
+            Callee::Name(name) => Operand::Symbol(name.clone()),
+            Callee::Address(address) => self.register(address, Width::Qword),
+        };
+        let values: Vec<Operand> = args
+            .iter()
+            .map(|arg| self.operand(arg, Width::Qword))
+            .collect();
+        for (register, value) in register::ARGS_REGISTER.iter().zip(values) {
+            self.label
+                .mov(Operand::Register(*register, Width::Qword), value);
+        }
+        // 呼び出しで保存されるレジスタはフレームを片付ける前に戻すので、アドレスはr11に移す
+        let function = match function {
+            Operand::Symbol(_) => function,
+            address => {
+                self.label.mov(R11, address);
+                R11
+            }
+        };
+        self.label.mov(EAX, 0);
+        self.label.mov(RSP, RBP);
+        self.label.pop(RBP);
+        self.label.tail_jmp(function);
+    }
+}
+
+/// 型の値を計算するレジスタの幅。4バイト以下の型は32ビットで計算する
+fn width(ty: Type) -> Width {
+    if ty.size() <= 4 {
+        Width::Dword
+    } else {
+        Width::Qword
+    }
+}
+
+/// レジスタrの値を型fromから型toに変換する。
+fn cast(from: Type, to: Type, r: &Operand, label: &mut Label) {
+    let (byte, word, dword) = (
+        r.sized(Width::Byte),
+        r.sized(Width::Word),
+        r.sized(Width::Dword),
+    );
+    match (from, to) {
+        (_, Type::I8) => label.movsx(r.clone(), byte),
+        (_, Type::I16) => label.movsx(r.clone(), word),
+        (_, Type::I32) => label.movsxd(r.clone(), dword),
+        (_, Type::U8) => label.movzx(r.clone(), byte),
+        (_, Type::U16) => label.movzx(r.clone(), word),
+        (_, Type::U32) => label.mov(dword.clone(), dword),
+        (Type::I32, _) => label.movsxd(r.clone(), dword),
+        (Type::U32, _) => label.mov(dword.clone(), dword),
+        _ => {}
+    }
+}
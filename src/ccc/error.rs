@@ -16,13 +16,98 @@ impl CompileError {
             error: error.to_string(),
         }
     }
+
+    /// エラーの内容
+    pub fn message(&self) -> &str {
+        &self.error
+    }
+
+    /// エラーが見つかったソースコード上の位置(文字数)
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// エラーが見つかったソースコード
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// エラーが見つかった行と列。どちらも1から数える
+    pub fn line_column(&self) -> (usize, usize) {
+        let before: Vec<char> = self.code.chars().take(self.position).collect();
+        let start = before.iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+        let line = before[..start].iter().filter(|c| **c == '\n').count() + 1;
+        (line, before.len() - start + 1)
+    }
+
+    /// エラーが見つかった行のソースコード
+    pub fn line(&self) -> &str {
+        let (line, _) = self.line_column();
+        self.code.lines().nth(line - 1).unwrap_or("")
+    }
 }
 
+/// 行:列: メッセージ と、エラーが見つかった行、その位置を指す^を出力する。
 impl std::fmt::Debug for CompileError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        writeln!(f, "Compile Error")?;
-        writeln!(f, "{}", self.error)?;
-        writeln!(f, "{}", self.code)?;
-        writeln!(f, "{}^", " ".repeat(self.position))
+        let (line, column) = self.line_column();
+        let source = self.line();
+        writeln!(f, "{}:{}: {}", line, column, self.error)?;
+        writeln!(f, "{}", source)?;
+        // タブはそのまま残して、^の位置を行と揃える
+        let indent: String = source
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        writeln!(f, "{}^", indent)
+    }
+}
+
+/// コンパイルで見つかったエラーの一覧
+pub struct CompileErrors {
+    errors: Vec<CompileError>,
+}
+
+impl CompileErrors {
+    /// エラーの数
+    pub fn count(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, CompileError> {
+        self.errors.iter()
+    }
+}
+
+impl From<CompileError> for CompileErrors {
+    fn from(error: CompileError) -> Self {
+        CompileErrors {
+            errors: vec![error],
+        }
+    }
+}
+
+impl From<Vec<CompileError>> for CompileErrors {
+    fn from(errors: Vec<CompileError>) -> Self {
+        CompileErrors { errors }
+    }
+}
+
+impl IntoIterator for CompileErrors {
+    type Item = CompileError;
+    type IntoIter = std::vec::IntoIter<CompileError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl std::fmt::Debug for CompileErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for e in &self.errors {
+            write!(f, "{:?}", e)?;
+        }
+        write!(f, "エラーが{}個ありました。", self.count())
     }
 }
@@ -0,0 +1,145 @@
+//! 制御フローグラフと支配木
+
+use super::Function;
+
+/// 制御フローグラフ。ブロックの番号で前後のブロックをたどる
+pub struct Cfg {
+    pub successors: Vec<Vec<usize>>,
+    pub predecessors: Vec<Vec<usize>>,
+    /// 最初のブロックからたどり着けるブロックの逆後順
+    pub order: Vec<usize>,
+}
+
+impl Cfg {
+    pub fn new(function: &Function) -> Self {
+        let n = function.blocks.len();
+        let successors: Vec<Vec<usize>> = function
+            .blocks
+            .iter()
+            .map(|block| block.terminator.successors())
+            .collect();
+        let mut predecessors = vec![Vec::new(); n];
+        for (b, succ) in successors.iter().enumerate() {
+            for s in succ {
+                if !predecessors[*s].contains(&b) {
+                    predecessors[*s].push(b);
+                }
+            }
+        }
+
+        // 深さ優先でたどり、帰りがけの順を逆にする
+        let mut order = Vec::with_capacity(n);
+        let mut visited = vec![false; n];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((b, i)) = stack.pop() {
+            match successors[b].get(i) {
+                Some(&s) => {
+                    stack.push((b, i + 1));
+                    if !visited[s] {
+                        visited[s] = true;
+                        stack.push((s, 0));
+                    }
+                }
+                None => order.push(b),
+            }
+        }
+        order.reverse();
+
+        Cfg {
+            successors,
+            predecessors,
+            order,
+        }
+    }
+}
+
+/// 支配木。最初のブロックからあるブロックへのすべての道が通るブロックが、そのブロックを支配する
+pub struct Dominators {
+    /// 支配木の子
+    pub children: Vec<Vec<usize>>,
+    /// 支配辺境。支配が途切れる先のブロック
+    pub frontiers: Vec<Vec<usize>>,
+}
+
+impl Dominators {
+    /// Cooper、Harvey、Kennedyの反復法で支配木を求める。
+    pub fn new(cfg: &Cfg) -> Self {
+        let n = cfg.successors.len();
+        let mut position = vec![usize::MAX; n];
+        for (i, b) in cfg.order.iter().enumerate() {
+            position[*b] = i;
+        }
+        let mut idom: Vec<Option<usize>> = vec![None; n];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in cfg.order.iter().skip(1) {
+                let mut new_idom = None;
+                for &p in &cfg.predecessors[b] {
+                    if idom[p].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => p,
+                        Some(q) => intersect(&idom, &position, p, q),
+                    });
+                }
+                if new_idom.is_some() && idom[b] != new_idom {
+                    idom[b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+
+        let mut children = vec![Vec::new(); n];
+        for &b in &cfg.order {
+            if let Some(d) = idom[b] {
+                children[d].push(b);
+            }
+        }
+
+        let mut frontiers = vec![Vec::new(); n];
+        for &b in &cfg.order {
+            let predecessors: Vec<_> = cfg.predecessors[b]
+                .iter()
+                .filter(|p| position[**p] != usize::MAX)
+                .collect();
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for &p in predecessors {
+                let mut runner = p;
+                while Some(runner) != idom[b] {
+                    if !frontiers[runner].contains(&b) {
+                        frontiers[runner].push(b);
+                    }
+                    match idom[runner] {
+                        Some(d) => runner = d,
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        Dominators {
+            children,
+            frontiers,
+        }
+    }
+}
+
+/// 2つのブロックを共に支配する、最も近いブロック
+fn intersect(idom: &[Option<usize>], position: &[usize], mut a: usize, mut b: usize) -> usize {
+    while a != b {
+        while position[a] > position[b] {
+            a = idom[a].expect("支配ブロックが決まっていません。");
+        }
+        while position[b] > position[a] {
+            b = idom[b].expect("支配ブロックが決まっていません。");
+        }
+    }
+    a
+}
@@ -0,0 +1,61 @@
+//! 共通部分式の削除
+//!
+//! 支配木をたどり、支配するブロックで同じ計算をしている命令を、その計算の値のコピーにする。
+
+use super::cfg::{Cfg, Dominators};
+use super::{BinaryOp, Function, Kind, Operand, Value};
+use std::collections::HashMap;
+
+/// 同じ計算をしている命令をコピーに置き換える。
+pub fn eliminate(function: &mut Function) {
+    let cfg = Cfg::new(function);
+    let dominators = Dominators::new(&cfg);
+    walk(function, &dominators, 0, HashMap::new());
+}
+
+fn walk(
+    function: &mut Function,
+    dominators: &Dominators,
+    b: usize,
+    mut available: HashMap<Kind, Value>,
+) {
+    for instruction in &mut function.blocks[b].instructions {
+        let value = match instruction.value {
+            Some(value) => value,
+            None => continue,
+        };
+        let key = match key(&instruction.kind) {
+            Some(key) => key,
+            None => continue,
+        };
+        match available.get(&key) {
+            Some(found) => instruction.kind = Kind::Copy(Operand::Value(*found)),
+            None => {
+                available.insert(key, value);
+            }
+        }
+    }
+    for &child in &dominators.children[b] {
+        walk(function, dominators, child, available.clone());
+    }
+}
+
+/// 同じ値になる計算を見分けるための命令。メモリを読む命令や副作用のある命令はNone
+fn key(kind: &Kind) -> Option<Kind> {
+    match kind {
+        // 入れ替えても同じ計算は、オペランドの順をそろえる
+        Kind::Binary(
+            op @ (BinaryOp::Add | BinaryOp::Mul | BinaryOp::Eq | BinaryOp::Ne),
+            ty,
+            a,
+            b,
+        ) if a > b => Some(Kind::Binary(*op, *ty, *b, *a)),
+        Kind::Binary(..)
+        | Kind::Cast(..)
+        | Kind::Local(_)
+        | Kind::Global(_)
+        | Kind::Got(_)
+        | Kind::Parameter(_) => Some(kind.clone()),
+        _ => None,
+    }
+}
@@ -0,0 +1,152 @@
+//! 中間表現をテキストとして出力する。
+//!
+//! 値は%n、ブロックはbnと書き、命令は1行に1つずつ字下げして並べる。
+
+use super::{BinaryOp, Block, Callee, Function, Instruction, Kind, Operand, Terminator, Type};
+use std::fmt::{Display, Formatter, Result};
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        if self.internal {
+            write!(f, "static ")?;
+        }
+        if self.inline {
+            write!(f, "inline ")?;
+        }
+        writeln!(f, "function {} {{", self.name)?;
+        for (b, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{}:", b)?;
+            write!(f, "{}", block)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Block {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        for instruction in &self.instructions {
+            writeln!(f, "  {}", instruction)?;
+        }
+        writeln!(f, "  {}", self.terminator)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        if let Some(value) = self.value {
+            write!(f, "%{} = ", value.0)?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Kind::Parameter(i) => write!(f, "parameter {}", i),
+            Kind::Copy(a) => write!(f, "copy {}", a),
+            Kind::Binary(op, ty, a, b) => write!(f, "{} {} {}, {}", op, ty, a, b),
+            Kind::Cast(from, to, a) => write!(f, "cast {} {} to {}", from, a, to),
+            Kind::Local(offset) => write!(f, "local {}", offset),
+            Kind::Global(name) => write!(f, "global {}", name),
+            Kind::Got(name) => write!(f, "got {}", name),
+            Kind::String(bytes) => write!(f, "string {:?}", String::from_utf8_lossy(bytes)),
+            Kind::Load {
+                ty,
+                address,
+                volatile,
+            } => {
+                let volatile = if *volatile { " volatile" } else { "" };
+                write!(f, "load{} {} {}", volatile, ty, address)
+            }
+            Kind::Store {
+                ty,
+                address,
+                value,
+                volatile,
+            } => {
+                let volatile = if *volatile { " volatile" } else { "" };
+                write!(f, "store{} {} {}, {}", volatile, ty, address, value)
+            }
+            Kind::Zero(address, size) => write!(f, "zero {}, {}", address, size),
+            Kind::Call(callee, args) => write!(f, "call {}", Call(callee, args)),
+            Kind::VaStart { ap, named, area } => {
+                write!(f, "va_start {}, {}, {}", ap, named, area)
+            }
+            Kind::VaArg(ap) => write!(f, "va_arg {}", ap),
+            Kind::Phi(incoming) => {
+                let incoming: Vec<_> = incoming
+                    .iter()
+                    .map(|(b, a)| format!("[b{}: {}]", b, a))
+                    .collect();
+                write!(f, "phi {}", incoming.join(", "))
+            }
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Terminator::Jump(b) => write!(f, "jump b{}", b),
+            Terminator::Branch(ty, a, t, e) => write!(f, "branch {} {}, b{}, b{}", ty, a, t, e),
+            Terminator::Return(a) => write!(f, "return {}", a),
+            Terminator::TailCall(callee, args) => write!(f, "tail call {}", Call(callee, args)),
+        }
+    }
+}
+
+/// 呼び出す関数と引数
+struct Call<'a>(&'a Callee, &'a [Operand]);
+
+impl Display for Call<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self.0 {
+            Callee::Name(name) => write!(f, "{}(", name)?,
+            Callee::Address(a) => write!(f, "{}(", a)?,
+        }
+        let args: Vec<_> = self.1.iter().map(|a| a.to_string()).collect();
+        write!(f, "{})", args.join(", "))
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Operand::Value(value) => write!(f, "%{}", value.0),
+            Operand::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let name = match self {
+            Type::I8 => "i8",
+            Type::I16 => "i16",
+            Type::I32 => "i32",
+            Type::I64 => "i64",
+            Type::U8 => "u8",
+            Type::U16 => "u16",
+            Type::U32 => "u32",
+            Type::U64 => "u64",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let name = match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+            BinaryOp::Eq => "eq",
+            BinaryOp::Ne => "ne",
+            BinaryOp::Lt => "lt",
+            BinaryOp::Le => "le",
+        };
+        write!(f, "{}", name)
+    }
+}
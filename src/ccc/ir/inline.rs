@@ -0,0 +1,310 @@
+//! 関数のインライン展開
+//!
+//! 同じファイルで定義された小さい関数の呼び出しを、呼び出される関数の本体で置き換える。
+//! 展開した本体の値、ブロック、ローカル変数の位置は、呼び出す関数のものと重ならないように付け替える。
+
+use super::{Block, Callee, Function, Instruction, Kind, Operand, Terminator, REGISTER_ARGS};
+use crate::ccc::parser::node::{
+    self,
+    visit::{self, Visitor},
+    Declaration, Expression, ExpressionKind, Storage,
+};
+use crate::ccc::parser::STACK_LIMIT;
+use std::collections::{HashMap, HashSet};
+
+/// 展開する関数の命令の数の上限
+const LIMIT: usize = 16;
+/// inline指定された関数を展開する、命令の数の上限
+const INLINE_LIMIT: usize = 64;
+
+/// 呼び出される関数から順に、展開できる呼び出しを展開して最適化し直す。
+///
+/// 再帰する関数は展開しない。最後に、外から見える関数や静的な変数の初期化子から使われない、
+/// staticな関数を取り除く。
+pub fn program(functions: &mut Vec<Function>, statics: &HashSet<String>) {
+    let names = names(functions);
+    let calls: Vec<Vec<usize>> = functions
+        .iter()
+        .map(|f| {
+            references(f)
+                .into_iter()
+                .filter_map(|name| names.get(name).copied())
+                .collect()
+        })
+        .collect();
+    let recursive: Vec<bool> = (0..functions.len())
+        .map(|i| reaches(&calls, i, i))
+        .collect();
+    for i in postorder(&calls) {
+        if expand(functions, i, &names, &recursive) {
+            functions[i].remove_unreachable();
+            functions[i].optimize();
+        }
+    }
+    remove_unused(functions, statics);
+}
+
+/// 静的な変数の初期化子でアドレスを使う関数の名前を集める。
+pub fn static_references(program: &node::Program) -> HashSet<String> {
+    let mut references = StaticReferences::default();
+    references.visit_program(program);
+    references.names
+}
+
+#[derive(Default)]
+struct StaticReferences {
+    names: HashSet<String>,
+    /// 静的な変数の初期化子を読んでいるか
+    in_static: bool,
+}
+
+impl Visitor for StaticReferences {
+    fn visit_declaration(&mut self, declaration: &Declaration) {
+        let outer = self.in_static;
+        self.in_static = !matches!(declaration.variable().storage(), Storage::Local(_));
+        visit::walk_declaration(self, declaration);
+        self.in_static = outer;
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let ExpressionKind::FunctionName { name, .. } = expression.kind() {
+            if self.in_static {
+                self.names.insert(name.clone());
+            }
+        }
+        visit::walk_expression(self, expression);
+    }
+}
+
+fn names(functions: &[Function]) -> HashMap<String, usize> {
+    functions
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.name.clone(), i))
+        .collect()
+}
+
+/// 関数が呼び出したり、アドレスを使ったりする名前
+fn references(function: &Function) -> HashSet<&str> {
+    function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instructions)
+        .filter_map(|instruction| match &instruction.kind {
+            Kind::Call(Callee::Name(name), _) | Kind::Got(name) | Kind::Global(name) => {
+                Some(name.as_str())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// fromから呼び出しをたどって、toにたどり着けるか確かめる。
+fn reaches(calls: &[Vec<usize>], from: usize, to: usize) -> bool {
+    let mut visited = vec![false; calls.len()];
+    let mut stack = vec![from];
+    while let Some(i) = stack.pop() {
+        for &j in &calls[i] {
+            if j == to {
+                return true;
+            }
+            if !visited[j] {
+                visited[j] = true;
+                stack.push(j);
+            }
+        }
+    }
+    false
+}
+
+/// 呼び出される関数が先に来る順番
+fn postorder(calls: &[Vec<usize>]) -> Vec<usize> {
+    fn visit(calls: &[Vec<usize>], i: usize, visited: &mut [bool], order: &mut Vec<usize>) {
+        if visited[i] {
+            return;
+        }
+        visited[i] = true;
+        for &j in &calls[i] {
+            visit(calls, j, visited, order);
+        }
+        order.push(i);
+    }
+    let mut visited = vec![false; calls.len()];
+    let mut order = Vec::new();
+    for i in 0..calls.len() {
+        visit(calls, i, &mut visited, &mut order);
+    }
+    order
+}
+
+/// args個の引数で呼び出すとき、展開してよい関数か確かめる。
+fn inlinable(function: &Function, args: usize) -> bool {
+    let size: usize = function
+        .blocks
+        .iter()
+        .map(|block| block.instructions.len() + 1)
+        .sum();
+    let limit = if function.inline { INLINE_LIMIT } else { LIMIT };
+    // スタックで渡された引数を読む関数は展開しない
+    function.va_area.is_none()
+        && function.parameters <= REGISTER_ARGS
+        && function.parameters == args
+        && size <= limit
+}
+
+/// i番目の関数の中の呼び出しを展開する。展開した場合はtrueを返す。
+fn expand(
+    functions: &mut [Function],
+    i: usize,
+    names: &HashMap<String, usize>,
+    recursive: &[bool],
+) -> bool {
+    let mut expanded = false;
+    let mut b = 0;
+    while b < functions[i].blocks.len() {
+        let found = functions[i].blocks[b]
+            .instructions
+            .iter()
+            .enumerate()
+            .find_map(|(k, instruction)| match &instruction.kind {
+                Kind::Call(Callee::Name(name), args) => {
+                    let j = *names.get(name)?;
+                    // 展開した後のローカル変数の領域も、構文解析で受け付ける上限に収める
+                    let ok = j != i
+                        && !recursive[j]
+                        && inlinable(&functions[j], args.len())
+                        && functions[i].stack_size + functions[j].stack_size <= STACK_LIMIT;
+                    ok.then_some((k, j))
+                }
+                _ => None,
+            });
+        match found {
+            Some((k, j)) => {
+                let callee = functions[j].clone();
+                splice(&mut functions[i], b, k, &callee);
+                expanded = true;
+            }
+            // 呼び出しの後の命令は新しいブロックに移すので、ブロックごとに先頭から探し直す
+            None => b += 1,
+        }
+    }
+    expanded
+}
+
+/// callerのb番目のブロックのk番目の命令の呼び出しを、calleeの本体で置き換える。
+///
+/// 呼び出しの後の命令は、本体の後に置く新しいブロックに移し、戻り値はφ関数で受け取る。
+fn splice(caller: &mut Function, b: usize, k: usize, callee: &Function) {
+    let values = caller.value_count;
+    caller.value_count += callee.value_count;
+    let offset = caller.stack_size;
+    caller.stack_size += callee.stack_size;
+    let first = caller.blocks.len();
+    let next = first + callee.blocks.len();
+
+    let block = &mut caller.blocks[b];
+    let rest = block.instructions.split_off(k + 1);
+    let call = block
+        .instructions
+        .pop()
+        .expect("呼び出しの命令がありません。");
+    let args = match call.kind {
+        Kind::Call(_, args) => args,
+        _ => unreachable!("呼び出しの命令ではありません。"),
+    };
+    let terminator = std::mem::replace(&mut block.terminator, Terminator::Jump(first));
+    // 後のブロックへは新しいブロックから進むので、φ関数の前のブロックを付け替える
+    for s in terminator.successors() {
+        for instruction in &mut caller.blocks[s].instructions {
+            if let Kind::Phi(incoming) = &mut instruction.kind {
+                for (p, _) in incoming.iter_mut() {
+                    if *p == b {
+                        *p = next;
+                    }
+                }
+            }
+        }
+    }
+
+    let rename = |operand: &mut Operand| {
+        if let Operand::Value(value) = operand {
+            value.0 += values;
+        }
+    };
+    let mut returns = Vec::new();
+    for (c, block) in callee.blocks.iter().enumerate() {
+        let mut block = block.clone();
+        for instruction in &mut block.instructions {
+            if let Some(value) = &mut instruction.value {
+                value.0 += values;
+            }
+            instruction.kind.operands_mut().into_iter().for_each(rename);
+            if let Kind::Parameter(n) = instruction.kind {
+                instruction.kind = Kind::Copy(args[n]);
+            }
+            match &mut instruction.kind {
+                Kind::Local(o) => *o += offset,
+                Kind::Phi(incoming) => {
+                    for (p, _) in incoming.iter_mut() {
+                        *p += first;
+                    }
+                }
+                _ => {}
+            }
+        }
+        block.terminator.operands_mut().into_iter().for_each(rename);
+        match &mut block.terminator {
+            Terminator::Jump(t) => *t += first,
+            Terminator::Branch(_, _, t, f) => {
+                *t += first;
+                *f += first;
+            }
+            Terminator::Return(value) => {
+                returns.push((first + c, *value));
+                block.terminator = Terminator::Jump(next);
+            }
+            Terminator::TailCall(..) => unreachable!("末尾呼び出しは展開の後に決めます。"),
+        }
+        caller.blocks.push(block);
+    }
+
+    let mut instructions = Vec::new();
+    if let Some(value) = call.value {
+        let kind = if returns.len() == 1 {
+            Kind::Copy(returns[0].1)
+        } else {
+            Kind::Phi(returns)
+        };
+        instructions.push(Instruction {
+            value: Some(value),
+            kind,
+        });
+    }
+    instructions.extend(rest);
+    caller.blocks.push(Block {
+        instructions,
+        terminator,
+    });
+}
+
+/// 外から見える関数と静的な変数の初期化子から、呼び出しやアドレスでたどれないstaticな関数を取り除く。
+fn remove_unused(functions: &mut Vec<Function>, statics: &HashSet<String>) {
+    let names = names(functions);
+    let mut used: Vec<bool> = functions
+        .iter()
+        .map(|f| !f.internal || statics.contains(&f.name))
+        .collect();
+    let mut stack: Vec<usize> = (0..functions.len()).filter(|i| used[*i]).collect();
+    while let Some(i) = stack.pop() {
+        for name in references(&functions[i]) {
+            if let Some(&j) = names.get(name) {
+                if !used[j] {
+                    used[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+    }
+    let mut used = used.into_iter();
+    functions.retain(|_| used.next().unwrap_or(true));
+}
@@ -0,0 +1,390 @@
+//! 構文木から中間表現への変換
+//!
+//! ローカル変数はすべてrbpからのオフセットの位置に読み書きする。
+//! アドレスを取られない変数は、最適化でレジスタに置く値に置き換える。
+
+use super::{
+    BinaryOp, Block, Callee, Function, Instruction, Kind, Operand, Terminator, Type, Value,
+    REGISTER_ARGS,
+};
+use crate::ccc::parser::node::{
+    self, BinaryKind, CompareKind, Declaration, Expression, ExpressionKind, Initializer, Statement,
+    Storage, UnaryKind, Variable, VariableType,
+};
+
+/// 構文木の関数を、中間表現の関数に変換する。
+pub fn function(function: &node::Function) -> Function {
+    let mut builder = Builder {
+        function: Function {
+            name: function.name().clone(),
+            internal: function.internal(),
+            inline: function.inline(),
+            parameters: function.arguments().len(),
+            blocks: Vec::new(),
+            value_count: 0,
+            stack_size: function.stack_size(),
+            va_area: function.va_area(),
+        },
+        current: 0,
+    };
+    builder.current = builder.new_block();
+
+    // レジスタで渡された引数を、引数の変数に書き込む。
+    // 7個目以降の引数はrbpより上にあるので、そのまま参照する
+    for (i, argument) in function.arguments().iter().enumerate().take(REGISTER_ARGS) {
+        let ty = Type::of(argument.var_type());
+        let parameter = builder.emit(Kind::Parameter(i));
+        let value = builder.cast(Type::I64, ty, parameter);
+        let address = builder.variable(argument);
+        builder.store(argument.var_type(), address, value);
+    }
+
+    for statement in function.statements() {
+        builder.statement(statement);
+    }
+    let mut function = builder.function;
+    function.remove_unreachable();
+    function
+}
+
+/// 変換の途中の状態
+struct Builder {
+    function: Function,
+    /// 命令を追加しているブロック
+    current: usize,
+}
+
+impl Builder {
+    /// 新しいブロックを作る。最後まで実行したブロックは0を返す
+    fn new_block(&mut self) -> usize {
+        self.function.blocks.push(Block {
+            instructions: Vec::new(),
+            terminator: Terminator::Return(Operand::Number(0)),
+        });
+        self.function.blocks.len() - 1
+    }
+
+    /// 今のブロックを終えて、次のブロックに移る。
+    fn terminate(&mut self, terminator: Terminator, next: usize) {
+        self.function.blocks[self.current].terminator = terminator;
+        self.current = next;
+    }
+
+    /// 値を定義する命令を追加する。
+    fn emit(&mut self, kind: Kind) -> Operand {
+        let value = self.function.new_value();
+        self.push(Some(value), kind);
+        Operand::Value(value)
+    }
+
+    fn push(&mut self, value: Option<Value>, kind: Kind) {
+        self.function.blocks[self.current]
+            .instructions
+            .push(Instruction { value, kind });
+    }
+
+    fn cast(&mut self, from: Type, to: Type, value: Operand) -> Operand {
+        self.emit(Kind::Cast(from, to, value))
+    }
+
+    fn load(&mut self, var_type: &VariableType, address: Operand) -> Operand {
+        self.emit(Kind::Load {
+            ty: Type::of(var_type),
+            address,
+            volatile: var_type.is_volatile(),
+        })
+    }
+
+    fn store(&mut self, var_type: &VariableType, address: Operand, value: Operand) {
+        let kind = Kind::Store {
+            ty: Type::of(var_type),
+            address,
+            value,
+            volatile: var_type.is_volatile(),
+        };
+        self.push(None, kind);
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Return(expr) => {
+                let value = self.expression(expr);
+                let next = self.new_block();
+                self.terminate(Terminator::Return(value), next);
+            }
+
+            Statement::Declaration(declarations) => {
+                for declaration in declarations {
+                    self.declaration(declaration);
+                }
+            }
+
+            Statement::Expression(expr) => {
+                self.expression(expr);
+            }
+
+            Statement::If {
+                condition,
+                true_statement,
+            } => {
+                let then = self.new_block();
+                let end = self.new_block();
+                self.branch(condition, then, end);
+                self.statement(true_statement);
+                self.terminate(Terminator::Jump(end), end);
+            }
+
+            Statement::IfElse {
+                condition,
+                true_statement,
+                false_statement,
+            } => {
+                let then = self.new_block();
+                let otherwise = self.new_block();
+                let end = self.new_block();
+                self.branch(condition, then, otherwise);
+                self.statement(true_statement);
+                self.terminate(Terminator::Jump(end), otherwise);
+                self.statement(false_statement);
+                self.terminate(Terminator::Jump(end), end);
+            }
+
+            Statement::While {
+                condition,
+                statement,
+            } => {
+                let begin = self.new_block();
+                let body = self.new_block();
+                let end = self.new_block();
+                self.terminate(Terminator::Jump(begin), begin);
+                self.branch(condition, body, end);
+                self.statement(statement);
+                self.terminate(Terminator::Jump(begin), end);
+            }
+
+            Statement::For {
+                init,
+                condition,
+                iteration,
+                statement,
+            } => {
+                self.statement(init);
+                let begin = self.new_block();
+                let body = self.new_block();
+                let end = self.new_block();
+                self.terminate(Terminator::Jump(begin), begin);
+                self.branch(condition, body, end);
+                self.statement(statement);
+                self.expression(iteration);
+                self.terminate(Terminator::Jump(begin), end);
+            }
+
+            Statement::Block { statements } => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+        }
+    }
+
+    /// 条件が0でなければthenに、0ならotherwiseに進んで、thenのブロックに移る。
+    fn branch(&mut self, condition: &Expression, then: usize, otherwise: usize) {
+        let value = self.expression(condition);
+        let ty = Type::of(condition.var_type());
+        self.terminate(Terminator::Branch(ty, value, then, otherwise), then);
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) {
+        let var = declaration.variable();
+        let offset = match var.storage() {
+            Storage::Local(offset) => *offset,
+            // 静的な変数はコード生成でデータ領域に出力する
+            Storage::Static { .. } | Storage::Extern => return,
+        };
+        if let Some(init) = declaration.initializer() {
+            let address = self.emit(Kind::Local(offset));
+            if let VariableType::Array(_, _) = var.var_type().unqualified() {
+                // 初期化子のない要素を0にするため、先に配列全体を0で埋める
+                let size = var.var_type().size();
+                self.push(None, Kind::Zero(address, size));
+            }
+            self.initializer(address, 0, var.var_type(), init);
+        }
+    }
+
+    /// addressからoffsetバイト先にある型var_typeの領域を初期化子で初期化する。
+    ///
+    /// 配列の要素も配列の先頭からのアドレスで書き込み、要素を別の変数として扱わないようにする。
+    fn initializer(
+        &mut self,
+        address: Operand,
+        offset: i64,
+        var_type: &VariableType,
+        init: &Initializer,
+    ) {
+        match (var_type.unqualified(), init) {
+            (VariableType::Array(t, _), Initializer::List(list)) => {
+                for (i, init) in list.iter().enumerate() {
+                    self.initializer(address, offset + i as i64 * t.size(), t, init);
+                }
+            }
+            (_, Initializer::List(list)) => {
+                for init in list {
+                    self.initializer(address, offset, var_type, init);
+                }
+            }
+            (_, Initializer::Expression(expr)) => {
+                let value = self.expression(expr);
+                let element = if offset == 0 {
+                    address
+                } else {
+                    let offset = Operand::Number(offset);
+                    self.emit(Kind::Binary(BinaryOp::Add, Type::U64, address, offset))
+                };
+                self.store(var_type, element, value);
+            }
+        }
+    }
+
+    /// 式の値を求める。void型の式は0にする。
+    fn expression(&mut self, expr: &Expression) -> Operand {
+        match expr.kind() {
+            ExpressionKind::Num(n) => Operand::Number(*n),
+
+            ExpressionKind::StringLiteral(bytes) => self.emit(Kind::String(bytes.clone())),
+
+            ExpressionKind::VaStart { ap, named, area } => {
+                let ap = self.expression(ap);
+                let kind = Kind::VaStart {
+                    ap,
+                    named: *named,
+                    area: *area,
+                };
+                self.push(None, kind);
+                ap
+            }
+
+            ExpressionKind::VaArg { ap, var_type } => {
+                let ap = self.expression(ap);
+                let address = self.emit(Kind::VaArg(ap));
+                self.load(var_type, address)
+            }
+
+            ExpressionKind::VaEnd(ap) => self.expression(ap),
+
+            ExpressionKind::Cast {
+                var_type,
+                expression,
+            } => {
+                let value = self.expression(expression);
+                if *var_type.unqualified() == VariableType::Void {
+                    return Operand::Number(0);
+                }
+                let from = Type::of(expression.var_type());
+                self.cast(from, Type::of(var_type), value)
+            }
+
+            ExpressionKind::Variable(variable) => {
+                let address = self.variable(variable);
+                match variable.var_type().unqualified() {
+                    VariableType::Array(_, _) => address,
+                    _ => self.load(variable.var_type(), address),
+                }
+            }
+
+            ExpressionKind::BinaryOperator {
+                kind: BinaryKind::Assign,
+                left,
+                right,
+            } => {
+                let address = self.address(left);
+                let value = self.expression(right);
+                self.store(left.var_type(), address, value);
+                value
+            }
+
+            ExpressionKind::BinaryOperator { kind, left, right } => {
+                let l = self.expression(left);
+                let r = self.expression(right);
+                let op = match kind {
+                    BinaryKind::Add => BinaryOp::Add,
+                    BinaryKind::Subtract => BinaryOp::Sub,
+                    BinaryKind::Multiply => BinaryOp::Mul,
+                    BinaryKind::Divide => BinaryOp::Div,
+                    BinaryKind::Compare(CompareKind::Equal) => BinaryOp::Eq,
+                    BinaryKind::Compare(CompareKind::NotEqual) => BinaryOp::Ne,
+                    BinaryKind::Compare(CompareKind::LessThan) => BinaryOp::Lt,
+                    BinaryKind::Compare(CompareKind::LessEqual) => BinaryOp::Le,
+                    BinaryKind::Assign => unreachable!(),
+                };
+                // 整数どうしの計算の両辺は、意味解析で同じ型にそろえられている
+                self.emit(Kind::Binary(op, Type::of(left.var_type()), l, r))
+            }
+
+            ExpressionKind::UnaryOperator {
+                kind: UnaryKind::Address,
+                expression,
+            } => self.address(expression),
+
+            ExpressionKind::UnaryOperator {
+                kind: UnaryKind::Deref,
+                expression,
+            } => {
+                let address = self.expression(expression);
+                match expression.var_type().unqualified() {
+                    VariableType::Pointer(t) | VariableType::Array(t, _)
+                        if !matches!(
+                            t.unqualified(),
+                            VariableType::Array(_, _) | VariableType::Function { .. }
+                        ) =>
+                    {
+                        self.load(t, address)
+                    }
+                    _ => address,
+                }
+            }
+
+            ExpressionKind::FunctionName { name, .. } => self.emit(Kind::Got(name.clone())),
+
+            ExpressionKind::FunctionCall { callee, args } => {
+                let args = args.iter().map(|arg| self.expression(arg)).collect();
+                let callee = match callee.kind() {
+                    ExpressionKind::FunctionName { name, .. } => Callee::Name(name.clone()),
+                    _ => Callee::Address(self.expression(callee)),
+                };
+                let value = self.emit(Kind::Call(callee, args));
+                match expr.var_type().unqualified() {
+                    VariableType::Void => Operand::Number(0),
+                    // 戻り値を関数の戻り値の型に合わせて拡張する
+                    t => self.cast(Type::I64, Type::of(t), value),
+                }
+            }
+
+            ExpressionKind::SizeOf(_) => unreachable!("sizeofは意味解析で数に置き換えられます。"),
+            ExpressionKind::Undeclared(_) => {
+                unreachable!("宣言されていない名前はエラーとして報告されます。")
+            }
+        }
+    }
+
+    /// 左辺値のアドレスを求める。
+    fn address(&mut self, expr: &Expression) -> Operand {
+        match expr.kind() {
+            ExpressionKind::Variable(var) => self.variable(var),
+            ExpressionKind::UnaryOperator {
+                kind: UnaryKind::Deref,
+                expression,
+            } => self.expression(expression),
+            ExpressionKind::FunctionName { .. } => self.expression(expr),
+            _ => unreachable!("左辺値ではない式は意味解析でエラーになります。"),
+        }
+    }
+
+    fn variable(&mut self, var: &Variable) -> Operand {
+        match var.storage() {
+            Storage::Local(offset) => self.emit(Kind::Local(*offset)),
+            Storage::Static { symbol, .. } => self.emit(Kind::Global(symbol.clone())),
+            Storage::Extern => self.emit(Kind::Got(var.name().clone())),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! ローカル変数のレジスタへの昇格
+//!
+//! アドレスを取られないローカル変数の読み書きを、SSA形式の値に置き換える。
+//! 書き込むブロックの反復支配辺境にφ関数を置き、支配木をたどって読み込みを直前に書いた値にする。
+
+use super::cfg::{Cfg, Dominators};
+use super::{Function, Instruction, Kind, Operand, Type, Value};
+use std::collections::{HashMap, HashSet};
+
+/// レジスタに置ける変数を昇格する。
+pub fn promote(function: &mut Function) {
+    let slots = promotable(function);
+    if slots.is_empty() {
+        return;
+    }
+    let cfg = Cfg::new(function);
+    let dominators = Dominators::new(&cfg);
+
+    // 変数のアドレスを表す値から、変数の位置へ
+    let mut addresses = HashMap::new();
+    let mut defined: HashMap<i64, Vec<usize>> = HashMap::new();
+    for (b, block) in function.blocks.iter().enumerate() {
+        for instruction in &block.instructions {
+            match (&instruction.kind, instruction.value) {
+                (Kind::Local(offset), Some(value)) if slots.contains_key(offset) => {
+                    addresses.insert(value, *offset);
+                }
+                (
+                    Kind::Store {
+                        address: Operand::Value(address),
+                        ..
+                    },
+                    _,
+                ) => {
+                    if let Some(offset) = addresses.get(address) {
+                        defined.entry(*offset).or_default().push(b);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 書き込むブロックの反復支配辺境に、φ関数を置く
+    let mut phis: HashMap<Value, i64> = HashMap::new();
+    let mut offsets: Vec<_> = slots.keys().copied().collect();
+    offsets.sort();
+    for offset in offsets {
+        let mut work = defined.remove(&offset).unwrap_or_default();
+        let mut placed = HashSet::new();
+        while let Some(b) = work.pop() {
+            for &f in &dominators.frontiers[b] {
+                if placed.insert(f) {
+                    let value = function.new_value();
+                    function.blocks[f].instructions.insert(
+                        0,
+                        Instruction {
+                            value: Some(value),
+                            kind: Kind::Phi(Vec::new()),
+                        },
+                    );
+                    phis.insert(value, offset);
+                    work.push(f);
+                }
+            }
+        }
+    }
+
+    let mut renamer = Renamer {
+        addresses,
+        phis,
+        cfg: &cfg,
+        dominators: &dominators,
+    };
+    renamer.rename(function, 0, HashMap::new());
+}
+
+/// アドレスが読み書きにしか使われず、同じ型で読み書きされる変数の位置と型
+fn promotable(function: &Function) -> HashMap<i64, Type> {
+    let mut locals: HashMap<Value, i64> = HashMap::new();
+    for block in &function.blocks {
+        for instruction in &block.instructions {
+            // rbpより上にあるスタックで渡された引数は、書き込まれずに読まれる
+            if let (Kind::Local(offset), Some(value)) = (&instruction.kind, instruction.value) {
+                if *offset > 0 {
+                    locals.insert(value, *offset);
+                }
+            }
+        }
+    }
+
+    let mut types: HashMap<i64, Option<Type>> = HashMap::new();
+    let mut check = |operand: &Operand, access: Option<Type>| {
+        if let Operand::Value(value) = operand {
+            if let Some(offset) = locals.get(value) {
+                let t = types.entry(*offset).or_insert(access);
+                if *t != access {
+                    *t = None;
+                }
+            }
+        }
+    };
+    for block in &function.blocks {
+        for instruction in &block.instructions {
+            match &instruction.kind {
+                Kind::Load {
+                    ty,
+                    address,
+                    volatile,
+                } => check(address, Some(*ty).filter(|_| !volatile)),
+                Kind::Store {
+                    ty,
+                    address,
+                    value,
+                    volatile,
+                } => {
+                    check(address, Some(*ty).filter(|_| !volatile));
+                    check(value, None);
+                }
+                kind => kind.operands().into_iter().for_each(|a| check(a, None)),
+            }
+        }
+        block
+            .terminator
+            .operands()
+            .into_iter()
+            .for_each(|a| check(a, None));
+    }
+    types
+        .into_iter()
+        .filter_map(|(offset, t)| t.map(|t| (offset, t)))
+        .collect()
+}
+
+struct Renamer<'a> {
+    /// 昇格する変数のアドレスを表す値と、変数の位置
+    addresses: HashMap<Value, i64>,
+    /// 置いたφ関数の値と、変数の位置
+    phis: HashMap<Value, i64>,
+    cfg: &'a Cfg,
+    dominators: &'a Dominators,
+}
+
+impl Renamer<'_> {
+    /// 支配木をたどり、変数の読み込みを、その時点の変数の値で置き換える。
+    /// 書き込まれる前に読む変数の値は0にする。
+    fn rename(&mut self, function: &mut Function, b: usize, mut current: HashMap<i64, Operand>) {
+        let instructions = std::mem::take(&mut function.blocks[b].instructions);
+        let mut renamed = Vec::with_capacity(instructions.len());
+        for mut instruction in instructions {
+            let slot = match &instruction.kind {
+                Kind::Load {
+                    address: Operand::Value(address),
+                    ..
+                }
+                | Kind::Store {
+                    address: Operand::Value(address),
+                    ..
+                } => self.addresses.get(address).copied(),
+                Kind::Phi(_) => instruction.value.and_then(|v| self.phis.get(&v).copied()),
+                _ => None,
+            };
+            if let Some(offset) = slot {
+                match &instruction.kind {
+                    Kind::Phi(_) => {
+                        let value = instruction.value.expect("φ関数に値がありません。");
+                        current.insert(offset, Operand::Value(value));
+                    }
+                    Kind::Store { value, .. } => {
+                        current.insert(offset, *value);
+                        continue;
+                    }
+                    _ => {
+                        let value = current.get(&offset).cloned().unwrap_or(Operand::Number(0));
+                        instruction.kind = Kind::Copy(value);
+                    }
+                }
+            }
+            renamed.push(instruction);
+        }
+        function.blocks[b].instructions = renamed;
+
+        for &s in &self.cfg.successors[b] {
+            for instruction in &mut function.blocks[s].instructions {
+                let offset = match instruction.value.and_then(|v| self.phis.get(&v)) {
+                    Some(offset) => *offset,
+                    None => continue,
+                };
+                if let Kind::Phi(incoming) = &mut instruction.kind {
+                    let value = current.get(&offset).cloned().unwrap_or(Operand::Number(0));
+                    incoming.push((b, value));
+                }
+            }
+        }
+
+        for child in self.dominators.children[b].clone() {
+            self.rename(function, child, current.clone());
+        }
+    }
+}
@@ -0,0 +1,460 @@
+//! SSA形式の中間表現
+//!
+//! 意味解析した構文木の関数を、基本ブロックの列に変換する。各値は一度だけ定義される。
+//! 最適化はこの中間表現の上で行い、-O1以上のコード生成は最適化した中間表現からアセンブリを作る。
+
+mod cfg;
+mod cse;
+mod display;
+mod inline;
+mod lower;
+mod mem2reg;
+mod propagate;
+mod tail;
+
+use crate::ccc::parser::node::{self, VariableType};
+use std::collections::HashMap;
+
+/// レジスタで渡す引数の数。7個目以降の引数はスタックで渡す
+const REGISTER_ARGS: usize = 6;
+
+/// 値の番号。関数の中で一度だけ定義される
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+/// 値の型。ポインタや配列、関数のアドレスは符号なしの64ビットとして扱う。
+///
+/// 32ビットの型の値は、レジスタの下位32ビットだけが意味を持つ。
+/// それより小さい型の値は、64ビットまで符号拡張かゼロ拡張されている。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Type {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+/// 命令のオペランド
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Operand {
+    Value(Value),
+    Number(i64),
+}
+
+/// 2つの値の計算。比較の結果はint型の0か1になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+}
+
+/// 呼び出す関数
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Callee {
+    /// 名前で呼び出す関数
+    Name(String),
+    /// アドレスを求めて呼び出す関数
+    Address(Operand),
+}
+
+/// 命令の種類
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// 関数のレジスタで渡されたi番目の引数
+    Parameter(usize),
+    Copy(Operand),
+    /// 型の値どうしの計算。割り算と大小の比較の符号は型で決まる
+    Binary(BinaryOp, Type, Operand, Operand),
+    /// 1つ目の型の値を2つ目の型に変換する
+    Cast(Type, Type, Operand),
+    /// rbp-offsetのアドレス
+    Local(i64),
+    /// このファイルの静的な変数や関数のアドレス
+    Global(String),
+    /// GOTから読む、他のファイルの変数や関数のアドレス
+    Got(String),
+    /// 文字列リテラルのアドレス
+    String(Vec<u8>),
+    Load {
+        ty: Type,
+        address: Operand,
+        volatile: bool,
+    },
+    Store {
+        ty: Type,
+        address: Operand,
+        value: Operand,
+        volatile: bool,
+    },
+    /// アドレスから指定したバイト数を0で埋める
+    Zero(Operand, i64),
+    /// 関数を呼び出して、戻り値をlong型の値として受け取る
+    Call(Callee, Vec<Operand>),
+    /// va_start。namedは名前のついた引数の個数、areaはレジスタの引数を保存した領域の位置
+    VaStart {
+        ap: Operand,
+        named: usize,
+        area: i64,
+    },
+    /// va_arg。次の引数のアドレスを求めて、apを進める
+    VaArg(Operand),
+    /// 直前に実行したブロックに合わせて値を選ぶ
+    Phi(Vec<(usize, Operand)>),
+}
+
+/// 命令と、命令が定義する値
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub value: Option<Value>,
+    pub kind: Kind,
+}
+
+/// 基本ブロックの最後で、次に実行するブロックを決める命令
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Jump(usize),
+    /// 値が0でなければ1つ目のブロックに、0なら2つ目のブロックに進む
+    Branch(Type, Operand, usize, usize),
+    Return(Operand),
+    /// 関数を呼び出して、その戻り値をそのまま返す。スタックフレームを片付けてからジャンプする
+    TailCall(Callee, Vec<Operand>),
+}
+
+/// 基本ブロック。途中から入ったり途中で抜けたりしない命令の列
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// 中間表現の関数。最初のブロックから実行する
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub internal: bool,
+    /// inline指定された関数
+    pub inline: bool,
+    /// 引数の数
+    pub parameters: usize,
+    pub blocks: Vec<Block>,
+    /// 定義した値の数
+    pub value_count: u32,
+    pub stack_size: i64,
+    /// 可変長引数の関数のとき、レジスタの引数を保存する領域の位置
+    pub va_area: Option<i64>,
+}
+
+/// 構文木の関数を中間表現にする。optimizeの場合は、最適化して関数の呼び出しを展開し、
+/// 末尾の呼び出しをジャンプにする。
+///
+/// 展開して呼び出されなくなったstaticな関数は取り除く。
+pub fn program(program: &node::Program, optimize: bool) -> Vec<Function> {
+    let mut functions: Vec<_> = program.codes().iter().map(lower::function).collect();
+    if optimize {
+        for function in &mut functions {
+            function.optimize();
+        }
+        inline::program(&mut functions, &inline::static_references(program));
+        for function in &mut functions {
+            tail::calls(function);
+        }
+    }
+    functions
+}
+
+impl Type {
+    /// 構文木の型に対応する値の型
+    pub fn of(var_type: &VariableType) -> Type {
+        match var_type.unqualified() {
+            VariableType::Char => Type::I8,
+            VariableType::Short => Type::I16,
+            VariableType::Int => Type::I32,
+            VariableType::Long => Type::I64,
+            VariableType::UnsignedChar => Type::U8,
+            VariableType::UnsignedShort => Type::U16,
+            VariableType::UnsignedInt => Type::U32,
+            VariableType::Void => unreachable!("void型の値はありません。"),
+            _ => Type::U64,
+        }
+    }
+
+    /// 型の大きさ(バイト数)
+    pub fn size(self) -> i64 {
+        match self {
+            Type::I8 | Type::U8 => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 => 4,
+            Type::I64 | Type::U64 => 8,
+        }
+    }
+
+    pub fn is_unsigned(self) -> bool {
+        matches!(self, Type::U8 | Type::U16 | Type::U32 | Type::U64)
+    }
+
+    /// fromの値をそのまま使えば、この型に変換した値になるか確かめる。
+    pub fn holds(self, from: Type) -> bool {
+        match (from.size(), self.size()) {
+            _ if from == self => true,
+            // 下位32ビットしか見ないので、切り詰めも拡張もいらない
+            (_, 4) | (8, 8) => true,
+            // 小さい型の値は64ビットまで拡張されている
+            (1 | 2, 8) => true,
+            (1, 2) => from.is_unsigned() || !self.is_unsigned(),
+            _ => false,
+        }
+    }
+}
+
+impl Kind {
+    /// 値を使わなければ取り除ける命令か確かめる。
+    pub fn is_pure(&self) -> bool {
+        match self {
+            Kind::Load { volatile, .. } => !volatile,
+            Kind::Store { .. }
+            | Kind::Zero(..)
+            | Kind::Call(..)
+            | Kind::VaStart { .. }
+            | Kind::VaArg(_) => false,
+            _ => true,
+        }
+    }
+
+    /// 命令が読むオペランド
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Kind::Copy(a) | Kind::Cast(_, _, a) | Kind::Zero(a, _) | Kind::VaArg(a) => vec![a],
+            Kind::VaStart { ap, .. } => vec![ap],
+            Kind::Binary(_, _, a, b) => vec![a, b],
+            Kind::Load { address, .. } => vec![address],
+            Kind::Store { address, value, .. } => vec![address, value],
+            Kind::Call(callee, args) => {
+                let mut operands: Vec<_> = args.iter().collect();
+                if let Callee::Address(a) = callee {
+                    operands.push(a);
+                }
+                operands
+            }
+            Kind::Phi(incoming) => incoming.iter().map(|(_, a)| a).collect(),
+            Kind::Parameter(_)
+            | Kind::Local(_)
+            | Kind::Global(_)
+            | Kind::Got(_)
+            | Kind::String(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Kind::Copy(a) | Kind::Cast(_, _, a) | Kind::Zero(a, _) | Kind::VaArg(a) => vec![a],
+            Kind::VaStart { ap, .. } => vec![ap],
+            Kind::Binary(_, _, a, b) => vec![a, b],
+            Kind::Load { address, .. } => vec![address],
+            Kind::Store { address, value, .. } => vec![address, value],
+            Kind::Call(callee, args) => {
+                let mut operands: Vec<_> = args.iter_mut().collect();
+                if let Callee::Address(a) = callee {
+                    operands.push(a);
+                }
+                operands
+            }
+            Kind::Phi(incoming) => incoming.iter_mut().map(|(_, a)| a).collect(),
+            Kind::Parameter(_)
+            | Kind::Local(_)
+            | Kind::Global(_)
+            | Kind::Got(_)
+            | Kind::String(_) => Vec::new(),
+        }
+    }
+}
+
+impl Terminator {
+    /// 次に実行する可能性のあるブロック
+    pub fn successors(&self) -> Vec<usize> {
+        match self {
+            Terminator::Jump(b) => vec![*b],
+            Terminator::Branch(_, _, t, f) => vec![*t, *f],
+            Terminator::Return(_) | Terminator::TailCall(..) => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Jump(_) => Vec::new(),
+            Terminator::Branch(_, a, _, _) | Terminator::Return(a) => vec![a],
+            Terminator::TailCall(callee, args) => {
+                let mut operands: Vec<_> = args.iter_mut().collect();
+                if let Callee::Address(a) = callee {
+                    operands.push(a);
+                }
+                operands
+            }
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Jump(_) => Vec::new(),
+            Terminator::Branch(_, a, _, _) | Terminator::Return(a) => vec![a],
+            Terminator::TailCall(callee, args) => {
+                let mut operands: Vec<_> = args.iter().collect();
+                if let Callee::Address(a) = callee {
+                    operands.push(a);
+                }
+                operands
+            }
+        }
+    }
+}
+
+impl Function {
+    /// 新しい値
+    pub fn new_value(&mut self) -> Value {
+        self.value_count += 1;
+        Value(self.value_count - 1)
+    }
+
+    /// 値を使っているオペランドの数
+    pub fn uses(&self) -> HashMap<Value, usize> {
+        let mut uses = HashMap::new();
+        for block in &self.blocks {
+            let operands = block
+                .instructions
+                .iter()
+                .flat_map(|instruction| instruction.kind.operands())
+                .chain(block.terminator.operands());
+            for operand in operands {
+                if let Operand::Value(value) = operand {
+                    *uses.entry(*value).or_insert(0) += 1;
+                }
+            }
+        }
+        uses
+    }
+
+    /// すべてのオペランドの値を、mapに従って置き換える。
+    pub fn replace(&mut self, map: &HashMap<Value, Operand>) {
+        let resolve = |operand: &mut Operand| {
+            // 置き換えた先がさらに置き換えられる場合は、最後までたどる
+            while let Operand::Value(value) = operand {
+                match map.get(value) {
+                    Some(to) if to != operand => *operand = *to,
+                    _ => break,
+                }
+            }
+        };
+        for block in &mut self.blocks {
+            for instruction in &mut block.instructions {
+                instruction
+                    .kind
+                    .operands_mut()
+                    .into_iter()
+                    .for_each(resolve);
+            }
+            block
+                .terminator
+                .operands_mut()
+                .into_iter()
+                .for_each(resolve);
+        }
+    }
+
+    /// 使われていない値を定義する、取り除ける命令を取り除く。
+    pub fn remove_unused(&mut self) {
+        loop {
+            let uses = self.uses();
+            let mut changed = false;
+            for block in &mut self.blocks {
+                block.instructions.retain(|instruction| {
+                    let unused = match instruction.value {
+                        Some(value) => !uses.contains_key(&value),
+                        None => false,
+                    };
+                    let removed = unused && instruction.kind.is_pure();
+                    changed |= removed;
+                    !removed
+                });
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// 最初のブロックからたどり着けないブロックを取り除き、ブロックの番号を詰める。
+    pub fn remove_unreachable(&mut self) {
+        let cfg = cfg::Cfg::new(self);
+        let mut reachable = vec![false; self.blocks.len()];
+        for b in &cfg.order {
+            reachable[*b] = true;
+        }
+        let mut numbers = Vec::with_capacity(self.blocks.len());
+        let mut n = 0;
+        for r in &reachable {
+            numbers.push(n);
+            if *r {
+                n += 1;
+            }
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (b, mut block) in blocks.into_iter().enumerate() {
+            if !reachable[b] {
+                continue;
+            }
+            for instruction in &mut block.instructions {
+                if let Kind::Phi(incoming) = &mut instruction.kind {
+                    incoming.retain(|(p, _)| reachable[*p]);
+                    for (p, _) in incoming.iter_mut() {
+                        *p = numbers[*p];
+                    }
+                }
+            }
+            match &mut block.terminator {
+                Terminator::Jump(b) => *b = numbers[*b],
+                Terminator::Branch(_, _, t, f) => {
+                    *t = numbers[*t];
+                    *f = numbers[*f];
+                }
+                Terminator::Return(_) | Terminator::TailCall(..) => {}
+            }
+            self.blocks.push(block);
+        }
+    }
+
+    /// 使われなくなったローカル変数の領域を、スタックフレームから取り除く。
+    ///
+    /// 変数はrbp-offsetから上に置くので、一番大きいオフセットまでを16の倍数に切り上げる。
+    pub fn shrink_frame(&mut self) {
+        let offsets = self.blocks.iter().flat_map(|block| &block.instructions);
+        let used = offsets
+            .filter_map(|instruction| match instruction.kind {
+                Kind::Local(offset) => Some(offset),
+                _ => None,
+            })
+            .chain(self.va_area)
+            .fold(0, i64::max);
+        self.stack_size = (used + 15) / 16 * 16;
+    }
+
+    /// 最適化をする。
+    pub fn optimize(&mut self) {
+        // 展開した関数の引数のコピーを先に伝播して、アドレスを渡した変数もレジスタに置けるようにする
+        propagate::copies(self);
+        mem2reg::promote(self);
+        propagate::copies(self);
+        cse::eliminate(self);
+        propagate::copies(self);
+        self.remove_unused();
+        self.shrink_frame();
+    }
+}
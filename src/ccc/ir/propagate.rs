@@ -0,0 +1,58 @@
+//! コピー伝播
+//!
+//! 別の値をそのまま使う命令を取り除き、その値を使っているオペランドを元の値に置き換える。
+
+use super::{Function, Kind, Operand, Value};
+use std::collections::HashMap;
+
+/// コピーを置き換えられなくなるまで伝播する。
+pub fn copies(function: &mut Function) {
+    loop {
+        let mut map: HashMap<Value, Operand> = HashMap::new();
+        for block in &function.blocks {
+            for instruction in &block.instructions {
+                let value = match instruction.value {
+                    Some(value) => value,
+                    None => continue,
+                };
+                if let Some(operand) = copied(value, &instruction.kind) {
+                    map.insert(value, operand);
+                }
+            }
+        }
+        if map.is_empty() {
+            break;
+        }
+        for block in &mut function.blocks {
+            block
+                .instructions
+                .retain(|instruction| match instruction.value {
+                    Some(value) => !map.contains_key(&value),
+                    None => true,
+                });
+        }
+        function.replace(&map);
+    }
+}
+
+/// 命令が別の値をそのまま使うとき、その値
+fn copied(value: Value, kind: &Kind) -> Option<Operand> {
+    match kind {
+        Kind::Copy(operand) => Some(*operand),
+        Kind::Cast(from, to, operand) if to.holds(*from) => Some(*operand),
+        // 自分自身を除いて同じ値だけを選ぶφ関数
+        Kind::Phi(incoming) => {
+            let mut operands = incoming
+                .iter()
+                .map(|(_, operand)| operand)
+                .filter(|operand| **operand != Operand::Value(value));
+            let first = operands.next().cloned().unwrap_or(Operand::Number(0));
+            if operands.all(|operand| *operand == first) {
+                Some(first)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
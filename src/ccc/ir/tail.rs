@@ -0,0 +1,64 @@
+//! 末尾呼び出しの最適化
+//!
+//! 呼び出した関数の戻り値をそのまま返す呼び出しを、スタックフレームを片付けてからのジャンプにする。
+//! 呼び出された関数はこの関数の呼び出し元に直接戻るので、再帰してもスタックが伸びない。
+
+use super::{Function, Instruction, Kind, Operand, Terminator, Value, REGISTER_ARGS};
+use std::collections::HashSet;
+
+/// 末尾の位置にある呼び出しを、末尾呼び出しにする。
+///
+/// 引数がすべてレジスタに収まる呼び出しだけを対象にする。
+/// 片付けるスタックフレームを指すアドレスが残らないように、ローカル変数のアドレスを
+/// 読み書きのほかに使う関数と、可変長引数の関数では何もしない。
+pub fn calls(function: &mut Function) {
+    if function.va_area.is_some() || escapes(function) {
+        return;
+    }
+    for block in &mut function.blocks {
+        let tail = match (&block.terminator, block.instructions.last()) {
+            (
+                Terminator::Return(Operand::Value(returned)),
+                Some(Instruction {
+                    value: Some(value),
+                    kind: Kind::Call(_, args),
+                }),
+            ) => returned == value && args.len() <= REGISTER_ARGS,
+            _ => false,
+        };
+        if !tail {
+            continue;
+        }
+        if let Some(Instruction {
+            kind: Kind::Call(callee, args),
+            ..
+        }) = block.instructions.pop()
+        {
+            block.terminator = Terminator::TailCall(callee, args);
+        }
+    }
+}
+
+/// ローカル変数のアドレスを、読み書きするアドレスのほかに使っているか確かめる。
+fn escapes(function: &Function) -> bool {
+    let locals: HashSet<Value> = function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instructions)
+        .filter_map(|instruction| match instruction.kind {
+            Kind::Local(_) => instruction.value,
+            _ => None,
+        })
+        .collect();
+    let local = |operand: &Operand| matches!(operand, Operand::Value(v) if locals.contains(v));
+    function.blocks.iter().any(|block| {
+        block.instructions.iter().any(|instruction| {
+            let operands = match &instruction.kind {
+                Kind::Load { .. } | Kind::Zero(..) => Vec::new(),
+                Kind::Store { value, .. } => vec![value],
+                kind => kind.operands(),
+            };
+            operands.into_iter().any(local)
+        }) || block.terminator.operands().into_iter().any(local)
+    })
+}
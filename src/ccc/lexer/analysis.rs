@@ -15,20 +15,27 @@ pub fn analyze(code: &str) -> Result<TokenList, CompileError> {
                 cur += 1;
             }
 
-            '/' => match codev[cur + 1] {
-                '/' => {
+            '/' => match codev.get(cur + 1) {
+                Some('/') => {
                     let mut c = cur + 2;
-                    while codev[c] != '\n' {
-                        c = c + 1;
+                    while c < len && codev[c] != '\n' {
+                        c += 1;
                     }
                     cur = c;
                 }
-                '*' => {
-                    let mut c = cur + 3;
-                    while codev[c - 1] != '*' && codev[c] != '/' {
-                        c = c + 1;
+                Some('*') => {
+                    let mut c = cur + 2;
+                    while !(codev.get(c) == Some(&'*') && codev.get(c + 1) == Some(&'/')) {
+                        if c >= len {
+                            return Err(CompileError::new(
+                                "コメントが閉じられていません。",
+                                cur,
+                                code,
+                            ));
+                        }
+                        c += 1;
                     }
-                    cur = c;
+                    cur = c + 2;
                 }
                 _ => {
                     vect.push(Token::new_reserved(codev[cur], cur));
@@ -41,14 +48,25 @@ pub fn analyze(code: &str) -> Result<TokenList, CompileError> {
                 cur += 1;
             }
 
+            '.' if codev.get(cur + 1) == Some(&'.') && codev.get(cur + 2) == Some(&'.') => {
+                vect.push(Token::new_reserved("...", cur));
+                cur += 3;
+            }
+
+            '"' => {
+                let (bytes, c) = read_string(code, &codev, cur)?;
+                vect.push(Token::new_string(bytes, cur));
+                cur = c;
+            }
+
             '0'..='9' => {
-                let (lo, c) = str_to_long(code, cur);
+                let (lo, c) = str_to_long(&codev, cur);
                 vect.push(Token::new_number(lo, cur));
                 cur = c;
             }
 
             '=' | '!' | '<' | '>' => {
-                if codev[cur + 1] == '=' {
+                if codev.get(cur + 1) == Some(&'=') {
                     vect.push(Token::new_reserved(
                         format!("{}{}", codev[cur], codev[cur + 1]),
                         cur,
@@ -61,11 +79,12 @@ pub fn analyze(code: &str) -> Result<TokenList, CompileError> {
             }
 
             'a'..='z' | 'A'..='Z' => {
-                let (identify, c) = get_identify(code, &codev, cur);
-                vect.push(match identify {
-                    "return" | "if" | "else" | "while" | "for" | "int" | "sizeof" => {
-                        Token::new_reserved(identify, cur)
-                    }
+                let (identify, c) = get_identify(&codev, cur);
+                vect.push(match identify.as_str() {
+                    "return" | "if" | "else" | "while" | "for" | "void" | "char" | "short"
+                    | "int" | "long" | "sizeof" | "va_list" | "va_start" | "va_arg" | "va_end"
+                    | "static" | "extern" | "inline" | "const" | "volatile" | "signed"
+                    | "unsigned" => Token::new_reserved(identify, cur),
                     _ => Token::new_identify(identify, cur),
                 });
                 cur = c;
@@ -77,21 +96,104 @@ pub fn analyze(code: &str) -> Result<TokenList, CompileError> {
     Ok(TokenList::new(code, vect))
 }
 
-fn str_to_long(code: &str, cursor: usize) -> (i64, usize) {
-    let mut len = cursor;
-    while len + 1 <= code.len() && code[cursor..len + 1].parse::<i64>().is_ok() {
-        len += 1
+/// 数字の並びを、i64に収まるところまで読む。位置はどれも文字の数で数える。
+fn str_to_long(codev: &[char], cursor: usize) -> (i64, usize) {
+    let mut value: i64 = 0;
+    let mut cur = cursor;
+    while let Some(d) = codev.get(cur).and_then(|c| c.to_digit(10)) {
+        match value.checked_mul(10).and_then(|v| v.checked_add(d as i64)) {
+            Some(v) => value = v,
+            None => break,
+        }
+        cur += 1;
     }
-    (code[cursor..len].parse().unwrap(), len)
+    (value, cur)
 }
 
-fn get_identify<'a>(code: &'a str, codev: &Vec<char>, cursor: usize) -> (&'a str, usize) {
-    let len = codev.len();
-    for now in cursor + 1..len {
-        match codev[now] {
-            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => continue,
-            _ => return (&code[cursor..now], now),
+/// 文字列リテラルを読み込み、エスケープを解釈したバイト列と終わりの位置を返す。
+fn read_string(
+    code: &str,
+    codev: &[char],
+    cursor: usize,
+) -> Result<(Vec<u8>, usize), CompileError> {
+    let mut bytes = Vec::new();
+    let mut cur = cursor + 1;
+    loop {
+        match codev.get(cur) {
+            None | Some('\n') => {
+                return Err(CompileError::new(
+                    "文字列リテラルが閉じられていません。",
+                    cursor,
+                    code,
+                ))
+            }
+            Some('"') => return Ok((bytes, cur + 1)),
+            Some('\\') => {
+                let (byte, c) = read_escape(code, codev, cur)?;
+                bytes.push(byte);
+                cur = c;
+            }
+            Some(&c) => {
+                let mut buf = [0; 4];
+                bytes.extend(c.encode_utf8(&mut buf).bytes());
+                cur += 1;
+            }
         }
     }
-    (&code[cursor..len], len)
+}
+
+/// バックスラッシュの位置から、エスケープシーケンスが表すバイトと終わりの位置を返す。
+fn read_escape(code: &str, codev: &[char], cursor: usize) -> Result<(u8, usize), CompileError> {
+    let error = |message| Err(CompileError::new(message, cursor, code));
+    let byte = match codev.get(cursor + 1) {
+        Some('n') => b'\n',
+        Some('t') => b'\t',
+        Some('r') => b'\r',
+        Some('a') => 0x07,
+        Some('b') => 0x08,
+        Some('f') => 0x0c,
+        Some('v') => 0x0b,
+        Some(&c @ ('\'' | '"' | '?' | '\\')) => c as u8,
+        // 8進数は3桁まで
+        Some('0'..='7') => {
+            let digits = codev[cursor + 1..]
+                .iter()
+                .take(3)
+                .map_while(|c| c.to_digit(8))
+                .collect::<Vec<_>>();
+            let value = digits.iter().fold(0, |v, d| v * 8 + d);
+            return if value <= 0xff {
+                Ok((value as u8, cursor + 1 + digits.len()))
+            } else {
+                error("エスケープした値が1バイトに収まりません。")
+            };
+        }
+        // 16進数は続く限り読む
+        Some('x') => {
+            let digits = codev[cursor + 2..]
+                .iter()
+                .map_while(|c| c.to_digit(16))
+                .collect::<Vec<_>>();
+            if digits.is_empty() {
+                return error("\\xの後に16進数がありません。");
+            }
+            let value = digits
+                .iter()
+                .try_fold(0u8, |v, d| v.checked_mul(16)?.checked_add(*d as u8));
+            return match value {
+                Some(byte) => Ok((byte, cursor + 2 + digits.len())),
+                None => error("エスケープした値が1バイトに収まりません。"),
+            };
+        }
+        _ => return error("不正なエスケープです。"),
+    };
+    Ok((byte, cursor + 2))
+}
+
+fn get_identify(codev: &[char], cursor: usize) -> (String, usize) {
+    let end = codev[cursor + 1..]
+        .iter()
+        .position(|c| !matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_'))
+        .map_or(codev.len(), |n| cursor + 1 + n);
+    (codev[cursor..end].iter().collect(), end)
 }
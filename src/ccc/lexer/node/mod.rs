@@ -1,13 +1,15 @@
 use crate::ccc::error::CompileError;
 
-#[derive(Debug)]
-enum TokenKind {
-    RESERVED(String),
-    IDENTIFY(String),
-    NUMBER(i64),
+/// トークンの種類
+#[derive(Debug, Clone)]
+pub enum TokenKind {
+    Reserved(String),
+    Identify(String),
+    Number(i64),
+    String(Vec<u8>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
     kind: TokenKind,
     position: usize,
@@ -19,7 +21,7 @@ impl Token {
         S: std::string::ToString,
     {
         Token {
-            kind: TokenKind::RESERVED(name.to_string()),
+            kind: TokenKind::Reserved(name.to_string()),
             position,
         }
     }
@@ -29,17 +31,33 @@ impl Token {
         S: std::string::ToString,
     {
         Token {
-            kind: TokenKind::IDENTIFY(name.to_string()),
+            kind: TokenKind::Identify(name.to_string()),
             position,
         }
     }
 
     pub fn new_number(num: i64, position: usize) -> Self {
         Token {
-            kind: TokenKind::NUMBER(num),
+            kind: TokenKind::Number(num),
+            position,
+        }
+    }
+
+    pub fn new_string(bytes: Vec<u8>, position: usize) -> Self {
+        Token {
+            kind: TokenKind::String(bytes),
             position,
         }
     }
+
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    /// ソースコード上の位置(文字数)
+    pub fn position(&self) -> usize {
+        self.position
+    }
 }
 
 /// トークンの配列
@@ -79,7 +97,24 @@ impl TokenList {
     }
 
     pub fn at_eof(&self) -> bool {
-        self.list.len() == 0
+        self.list.is_empty()
+    }
+
+    /// 次のトークンのソースコード上の位置。最後まで読んだ場合はソースコードの終わり。
+    pub fn next_position(&self) -> usize {
+        match self.list.first() {
+            Some(token) => token.position,
+            None => self.code.chars().count(),
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// まだ読んでいないトークン
+    pub fn tokens(&self) -> &[Token] {
+        &self.list
     }
 
     pub fn error<S>(&self, err: S) -> CompileError
@@ -90,16 +125,25 @@ impl TokenList {
     }
 
     pub fn next_reserved(&mut self, stri: &str) -> bool {
-        matches!(self.get(),Some(TokenKind::RESERVED(ref s)) if s == stri )
+        matches!(self.get(),Some(TokenKind::Reserved(ref s)) if s == stri )
+    }
+
+    /// n個先のトークンが指定した予約語か確かめる。
+    pub fn nth_reserved(&self, n: usize, stri: &str) -> bool {
+        matches!(self.list.get(n), Some(Token { kind: TokenKind::Reserved(ref s), .. }) if s == stri)
     }
 
     pub fn next_identify(&mut self) -> bool {
-        matches!(self.get(), Some(TokenKind::IDENTIFY(_)))
+        matches!(self.get(), Some(TokenKind::Identify(_)))
+    }
+
+    pub fn next_string(&mut self) -> bool {
+        matches!(self.get(), Some(TokenKind::String(_)))
     }
 
     pub fn consume_reserved(&mut self, stri: &str) -> bool {
         match self.get() {
-            Some(TokenKind::RESERVED(ref s)) if s == stri => {
+            Some(TokenKind::Reserved(ref s)) if s == stri => {
                 self.pop();
                 true
             }
@@ -109,22 +153,29 @@ impl TokenList {
 
     pub fn expect_num(&mut self) -> Result<i64, CompileError> {
         match self.pop() {
-            Some(TokenKind::NUMBER(i)) => Ok(i),
+            Some(TokenKind::Number(i)) => Ok(i),
             _ => Err(self.error("数ではありません。")),
         }
     }
 
     pub fn expect_reserved(&mut self, t: &str) -> Result<String, CompileError> {
         match self.pop() {
-            Some(TokenKind::RESERVED(s)) if s == t => Ok(s),
+            Some(TokenKind::Reserved(s)) if s == t => Ok(s),
             _ => Err(self.error(format!("{} がありません。", t))),
         }
     }
 
     pub fn expect_identify(&mut self) -> Option<String> {
         match self.pop() {
-            Some(TokenKind::IDENTIFY(s)) => Some(s),
+            Some(TokenKind::Identify(s)) => Some(s),
             _ => None,
         }
     }
+
+    pub fn expect_string(&mut self) -> Result<Vec<u8>, CompileError> {
+        match self.pop() {
+            Some(TokenKind::String(s)) => Ok(s),
+            _ => Err(self.error("文字列ではありません。")),
+        }
+    }
 }
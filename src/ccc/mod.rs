@@ -1,13 +1,140 @@
 mod code_generator;
-mod error;
-mod lexer;
-mod parser;
-
-pub fn compile(code: &str) -> Result<(), error::CompileError> {
-    use code_generator::code_generate;
-    let mut tokens = lexer::analyze(&code)?;
-    let parsed = parser::analyze(&mut tokens)?;
-    eprintln!("{:?}", parsed);
-    code_generate(&parsed);
+pub mod error;
+mod ir;
+pub mod lexer;
+mod optimizer;
+pub mod parser;
+mod semantic;
+
+use lexer::node::Token;
+use parser::node::Program;
+
+/// コンパイルをどこまで進めるか。後の段階ほど大きい。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// 構文解析まで
+    Parse,
+    /// 意味解析まで
+    Analyze,
+    /// アセンブリの生成まで
+    Generate,
+}
+
+/// コンパイルの設定
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub stage: Stage,
+    /// 最適化のレベル。1以上で定数の畳み込み、中間表現での最適化と関数の展開、末尾呼び出しの最適化、レジスタの割り当て、のぞき穴最適化をする
+    pub optimization: u8,
+    /// 最適化のレベルが0のとき、スタックマシンとして生成した命令にのぞき穴最適化をするか(-Og)
+    pub peephole: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            stage: Stage::Generate,
+            optimization: 0,
+            peephole: false,
+        }
+    }
+}
+
+/// コンパイルの結果
+pub struct Output {
+    tokens: Vec<Token>,
+    program: Program,
+    assembly: Option<String>,
+}
+
+impl Output {
+    /// 字句解析した結果のトークン
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// 構文木。意味解析した場合は、型変換などを書き込んだ後の構文木で、最適化した場合は最適化した後の構文木
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// アセンブリ。アセンブリの生成まで進めなかった場合はNone
+    pub fn assembly(&self) -> Option<&str> {
+        self.assembly.as_deref()
+    }
+}
+
+/// ソースコードを設定された段階までコンパイルする。
+/// 標準出力には何も出力しないので、複数のスレッドから同時に呼び出せる。
+pub fn compile_to_string(code: &str, options: &Options) -> Result<Output, error::CompileErrors> {
+    let mut tokens = lexer::analyze(code)?;
+    let token_list = tokens.tokens().to_vec();
+    let (mut program, mut errors) = parser::analyze(&mut tokens)?;
+    if options.stage >= Stage::Analyze {
+        if let Err(e) = semantic::analyze(&mut program, code) {
+            errors.extend(e);
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.position());
+        return Err(errors.into());
+    }
+    if options.stage >= Stage::Analyze && options.optimization >= 1 {
+        optimizer::fold(&mut program);
+    }
+    let assembly = if options.stage >= Stage::Generate {
+        let mut buffer = Vec::new();
+        // Vecへの書き込みは失敗しない
+        write_assembly(&program, options, &mut buffer).expect("アセンブリを書き込めません。");
+        Some(String::from_utf8(buffer).expect("アセンブリがUTF-8ではありません。"))
+    } else {
+        None
+    };
+    Ok(Output {
+        tokens: token_list,
+        program,
+        assembly,
+    })
+}
+
+/// 意味解析した構文木から、設定に合わせてアセンブリを生成して、outputに書き込む。
+/// 書き込みに失敗した場合は、そのエラーを返す。
+pub fn write_assembly(
+    program: &Program,
+    options: &Options,
+    output: &mut dyn std::io::Write,
+) -> std::io::Result<()> {
+    code_generator::code_generate(program, options.optimization >= 1, options.peephole, output)
+}
+
+/// 意味解析した構文木の関数を中間表現にして、テキストとしてoutputに書き込む。
+/// 最適化のレベルが1以上の場合は、最適化、関数の展開、末尾呼び出しの最適化をした後の中間表現を書き込む。
+pub fn write_ir(
+    program: &Program,
+    options: &Options,
+    output: &mut dyn std::io::Write,
+) -> std::io::Result<()> {
+    let functions = ir::program(program, options.optimization >= 1);
+    for (i, function) in functions.iter().enumerate() {
+        if i > 0 {
+            writeln!(output)?;
+        }
+        write!(output, "{}", function)?;
+    }
     Ok(())
 }
+
+/// ソースコードを構文解析して、構文木をC言語のソースコードとして出力する。
+pub fn format(code: &str) -> Result<String, error::CompileErrors> {
+    let options = Options {
+        stage: Stage::Parse,
+        ..Options::default()
+    };
+    Ok(compile_to_string(code, &options)?.program.to_string())
+}
+
+/// ソースコードをコンパイルして、アセンブリを返す。エラーの場合は、見つかったエラーの一覧を返す。
+pub fn compile(code: &str) -> Result<String, error::CompileErrors> {
+    let output = compile_to_string(code, &Options::default())?;
+    Ok(output.assembly.unwrap_or_default())
+}
@@ -0,0 +1,205 @@
+//! 定数の畳み込みと、不要なコードの削除
+//!
+//! 意味解析した構文木で、値がコンパイル時に決まる計算を数に置き換え、
+//! 実行されない文や、値を使わない副作用のない式の文を取り除く。
+
+use crate::ccc::{
+    parser::node::{
+        self,
+        visit::{self, VisitorMut},
+        BinaryKind, Expression, ExpressionKind, Function, Program, Statement, UnaryKind,
+        VariableType,
+    },
+    semantic::constant::{self, is_integer},
+};
+
+/// 構文木を書き換えられるところまで最適化する。
+pub fn program(program: &mut Program) {
+    Folder.visit_program_mut(program);
+}
+
+struct Folder;
+
+impl VisitorMut for Folder {
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        visit::walk_function_mut(self, function);
+        statements(function.statements_mut());
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        visit::walk_statement_mut(self, statement);
+        if let Statement::Block { statements: list } = statement {
+            statements(list);
+            return;
+        }
+        let replacement = match statement {
+            Statement::Expression(expr) if is_pure(expr) => empty(),
+            Statement::If {
+                condition,
+                true_statement,
+            } => match number(condition) {
+                Some(0) => empty(),
+                Some(_) => std::mem::replace(true_statement.as_mut(), empty()),
+                None => return,
+            },
+            Statement::IfElse {
+                condition,
+                true_statement,
+                false_statement,
+            } => match number(condition) {
+                Some(0) => std::mem::replace(false_statement.as_mut(), empty()),
+                Some(_) => std::mem::replace(true_statement.as_mut(), empty()),
+                None => return,
+            },
+            Statement::While { condition, .. } if number(condition) == Some(0) => empty(),
+            // 初期化は一度だけ実行される
+            Statement::For {
+                init, condition, ..
+            } if number(condition) == Some(0) => std::mem::replace(init.as_mut(), empty()),
+            _ => return,
+        };
+        *statement = replacement;
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        visit::walk_expression_mut(self, expr);
+        fold(expr);
+        simplify(expr);
+    }
+}
+
+/// 文の並びから空の文を取り除き、必ずreturnする文より後の文を取り除く。
+fn statements(list: &mut Vec<Statement>) {
+    list.retain(|statement| !is_empty(statement));
+    if let Some(k) = list.iter().position(returns) {
+        list.truncate(k + 1);
+    }
+}
+
+/// 両辺が数になった計算やキャストを、計算した数に置き換える。
+///
+/// 符号ありの計算のオーバーフローと0での割り算は、未定義の動作なので実行時に任せる。
+fn fold(expr: &mut Expression) {
+    if !is_integer(expr.var_type()) {
+        return;
+    }
+    let constant = match expr.kind() {
+        ExpressionKind::BinaryOperator {
+            kind: BinaryKind::Assign,
+            ..
+        } => false,
+        ExpressionKind::BinaryOperator { left, right, .. } => {
+            is_integer(left.var_type())
+                && is_integer(right.var_type())
+                && number(left).is_some()
+                && number(right).is_some()
+        }
+        ExpressionKind::Cast { expression, .. } => {
+            is_integer(expression.var_type()) && number(expression).is_some()
+        }
+        _ => false,
+    };
+    if !constant {
+        return;
+    }
+    if let Ok(n) = constant::eval_integer(expr) {
+        *expr = new_number(n, expr.var_type().unqualified().clone(), expr.position());
+    }
+}
+
+/// x+0、x-0、x*1、x/1をxに、副作用のないxのx*0を0に置き換える。
+fn simplify(expr: &mut Expression) {
+    use BinaryKind::{Add, Divide, Multiply, Subtract};
+    let t = expr.var_type().unqualified().clone();
+    let position = expr.position();
+    let replacement = match expr.kind_mut() {
+        ExpressionKind::BinaryOperator { kind, left, right } => {
+            // 型が変わる場合は、キャストが必要なので置き換えない
+            let same = |e: &Expression| *e.var_type().unqualified() == t;
+            match (kind, number(left), number(right)) {
+                (Add | Subtract, _, Some(0)) | (Multiply | Divide, _, Some(1)) if same(left) => {
+                    take(left)
+                }
+                (Add, Some(0), _) | (Multiply, Some(1), _) if same(right) => take(right),
+                (Multiply, Some(0), _) | (Multiply, _, Some(0))
+                    if is_pure(left) && is_pure(right) =>
+                {
+                    new_number(0, t, position)
+                }
+                _ => return,
+            }
+        }
+        _ => return,
+    };
+    *expr = replacement;
+}
+
+/// 値を使わなければ取り除ける、副作用のない式か確かめる。volatileな領域の読み込みは副作用とする。
+fn is_pure(expr: &Expression) -> bool {
+    match expr.kind() {
+        ExpressionKind::Num(_)
+        | ExpressionKind::StringLiteral(_)
+        | ExpressionKind::FunctionName { .. } => true,
+        ExpressionKind::Variable(variable) => !variable.var_type().is_volatile(),
+        ExpressionKind::UnaryOperator {
+            kind: UnaryKind::Deref,
+            expression,
+        } => !expr.var_type().is_volatile() && is_pure(expression),
+        ExpressionKind::UnaryOperator { expression, .. }
+        | ExpressionKind::Cast { expression, .. } => is_pure(expression),
+        ExpressionKind::BinaryOperator {
+            kind: BinaryKind::Assign,
+            ..
+        } => false,
+        ExpressionKind::BinaryOperator { left, right, .. } => is_pure(left) && is_pure(right),
+        ExpressionKind::FunctionCall { .. }
+        | ExpressionKind::VaStart { .. }
+        | ExpressionKind::VaArg { .. }
+        | ExpressionKind::VaEnd(_)
+        | ExpressionKind::SizeOf(_)
+        | ExpressionKind::Undeclared(_) => false,
+    }
+}
+
+/// 必ずreturnで抜ける文か確かめる。
+fn returns(statement: &Statement) -> bool {
+    match statement {
+        Statement::Return(_) => true,
+        Statement::Block { statements } => statements.iter().any(returns),
+        Statement::IfElse {
+            true_statement,
+            false_statement,
+            ..
+        } => returns(true_statement) && returns(false_statement),
+        _ => false,
+    }
+}
+
+/// 式が数のとき、その値
+fn number(expr: &Expression) -> Option<i64> {
+    match expr.kind() {
+        ExpressionKind::Num(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn new_number(n: i64, var_type: VariableType, position: usize) -> Expression {
+    let mut expr = node::new_expression(ExpressionKind::Num(n), position);
+    expr.set_var_type(var_type);
+    expr
+}
+
+/// 式を取り出して、元の場所には仮の数を置く。
+fn take(expr: &mut Expression) -> Expression {
+    let position = expr.position();
+    std::mem::replace(expr, node::new_expression(ExpressionKind::Num(0), position))
+}
+
+/// 何もしない文
+fn empty() -> Statement {
+    node::new_block(Vec::new())
+}
+
+fn is_empty(statement: &Statement) -> bool {
+    matches!(statement, Statement::Block { statements } if statements.is_empty())
+}
@@ -0,0 +1,3 @@
+mod fold;
+
+pub use fold::program as fold;
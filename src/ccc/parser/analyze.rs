@@ -1,55 +1,360 @@
 use crate::ccc::{
-    error::CompileError,
+    error::{CompileError, CompileErrors},
     lexer::node::TokenList,
     parser::node::{
-        self, BinaryKind, CompareKind, Expression, Function, Program, Statement, UnaryKind,
-        Variable, VariableType,
+        self, BinaryKind, CompareKind, Declaration, Expression, ExpressionKind, Function,
+        Initializer, Program, Qualifiers, Statement, Storage, UnaryKind, Variable, VariableType,
     },
+    semantic,
 };
 
 type ParseResult<T> = Result<T, CompileError>;
-type VariableList = Vec<(String, VariableType, i64)>;
+/// 関数の引数の型と名前
+type ParameterList = Vec<(VariableType, Option<String>)>;
 
-pub fn program(token: &mut TokenList) -> ParseResult<Program> {
-    let mut codes = Vec::new();
-    while !token.at_eof() {
-        codes.push(function(token)?);
+/// レジスタで渡される引数の個数
+const REGISTER_ARGUMENTS: usize = 6;
+/// ローカル変数の領域の大きさの上限。rspから引く大きさを、命令の32ビットの即値に収める
+pub const STACK_LIMIT: i64 = i32::MAX as i64 / 2;
+
+/// 宣言の記憶域クラス
+#[derive(Clone, Copy, PartialEq)]
+enum StorageClass {
+    Auto,
+    Static,
+    Extern,
+}
+
+/// 宣言された関数と変数の一覧
+///
+/// ローカル変数はブロックごとにスコープを持つ。スタック上の位置はスコープを抜けても再利用しない。
+struct VariableList {
+    /// ファイルスコープで宣言された関数の名前と型
+    functions: Vec<(String, VariableType)>,
+    /// 本体まで定義された関数の名前
+    defined: Vec<String>,
+    /// ファイルスコープで宣言された変数
+    globals: Vec<Variable>,
+    scopes: Vec<Vec<Variable>>,
+    offset: i64,
+    /// 可変長引数の関数のとき、名前のついた引数の個数とレジスタの引数を保存する領域の位置
+    va_area: Option<(usize, i64)>,
+    /// 関数の定義の中を読んでいるか
+    in_function: bool,
+    /// 見つけても構文解析を続けられる、名前についてのエラー
+    errors: Vec<CompileError>,
+    /// static宣言されたローカル変数のシンボル名につける番号
+    static_count: usize,
+}
+
+impl VariableList {
+    fn new() -> Self {
+        VariableList {
+            functions: Vec::new(),
+            defined: Vec::new(),
+            globals: Vec::new(),
+            scopes: vec![Vec::new()],
+            offset: 0,
+            va_area: None,
+            in_function: false,
+            errors: Vec::new(),
+            static_count: 0,
+        }
+    }
+
+    /// 関数の定義に入る。ローカル変数とスタックフレームを空にする。
+    fn enter_function(&mut self) {
+        self.scopes = vec![Vec::new()];
+        self.offset = 0;
+        self.va_area = None;
+        self.in_function = true;
+    }
+
+    /// 関数の定義を抜けて、ファイルスコープに戻る。
+    fn leave_function(&mut self) {
+        self.scopes = vec![Vec::new()];
+        self.in_function = false;
+    }
+
+    /// 関数を宣言する。前の宣言と型が合わない場合はエラーにする。
+    fn declare_function(
+        &mut self,
+        token: &TokenList,
+        name: String,
+        var_type: VariableType,
+    ) -> ParseResult<()> {
+        match self.functions.iter_mut().find(|(n, _)| n == &name) {
+            Some((_, t)) => {
+                if !same_function(t, &var_type) {
+                    return Err(token.error("関数の宣言が前の宣言と合いません。"));
+                }
+                // 引数の並びが空の宣言より、引数のわかる宣言を残す
+                if !parameters_unknown(&var_type) {
+                    *t = var_type;
+                }
+            }
+            None => self.functions.push((name, var_type)),
+        }
+        Ok(())
+    }
+
+    fn find_function(&self, name: &str) -> Option<&VariableType> {
+        self.functions
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, t)| t)
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn leave_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// 内側のスコープから順に変数を探し、最後にファイルスコープの変数を探す。
+    fn find(&self, name: &str) -> Option<&Variable> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter())
+            .chain(self.globals.iter())
+            .find(|var| var.name() == name)
+    }
+
+    /// 現在のスコープに変数を追加する。
+    fn push_scope(&mut self, token: &TokenList, var: Variable) -> ParseResult<Variable> {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.iter().any(|v| v.name() == var.name()) {
+            return Err(token.error("すでに宣言された変数です。"));
+        }
+        scope.push(var.clone());
+        Ok(var)
+    }
+
+    /// 現在のスコープに変数を追加し、スタック上の位置を割り当てる。
+    fn declare(
+        &mut self,
+        token: &TokenList,
+        name: String,
+        var_type: VariableType,
+    ) -> ParseResult<Variable> {
+        if var_type.size() > STACK_LIMIT - self.offset {
+            return Err(token.error("ローカル変数が大きすぎます。"));
+        }
+        let align = var_type.align();
+        let offset = (self.offset + var_type.size() + align - 1) / align * align;
+        let var = node::new_variable(var_type, name, Storage::Local(offset));
+        let var = self.push_scope(token, var)?;
+        self.offset = offset;
+        Ok(var)
+    }
+
+    /// スタックの外に置かれる変数を宣言する。
+    ///
+    /// ファイルスコープの変数は変数名をシンボル名にし、extern宣言の後の定義は同じ変数として扱う。
+    /// staticなローカル変数には、他の関数の変数と重ならないシンボル名をつける。
+    fn declare_static(
+        &mut self,
+        token: &TokenList,
+        name: String,
+        var_type: VariableType,
+        class: StorageClass,
+    ) -> ParseResult<Variable> {
+        let storage = match class {
+            StorageClass::Extern => Storage::Extern,
+            StorageClass::Static if self.in_function => {
+                self.static_count += 1;
+                Storage::Static {
+                    symbol: format!("{}.{}", name, self.static_count),
+                    internal: true,
+                }
+            }
+            class => Storage::Static {
+                symbol: name.clone(),
+                internal: class == StorageClass::Static,
+            },
+        };
+        let var = node::new_variable(var_type, name, storage);
+        if self.in_function {
+            return self.push_scope(token, var);
+        }
+
+        match self.globals.iter().position(|v| v.name() == var.name()) {
+            Some(i) => match (self.globals[i].storage(), var.storage()) {
+                (_, Storage::Extern) => {}
+                (Storage::Extern, _) => self.globals[i] = var.clone(),
+                _ => return Err(token.error("すでに定義された変数です。")),
+            },
+            None => self.globals.push(var.clone()),
+        }
+        Ok(var)
+    }
+
+    /// スタックで渡された引数を現在のスコープに追加する。
+    ///
+    /// 引数は呼び出し元が積んだ位置(rbp+16から8バイトずつ)にあるので、新しい領域は割り当てない。
+    fn declare_stack_argument(
+        &mut self,
+        token: &TokenList,
+        name: String,
+        var_type: VariableType,
+        index: usize,
+    ) -> ParseResult<Variable> {
+        let var = node::new_variable(var_type, name, Storage::Local(-16 - 8 * index as i64));
+        self.push_scope(token, var)
+    }
+
+    /// レジスタで渡された引数を保存する領域(レジスタ保存領域)を割り当てる。
+    ///
+    /// 汎用レジスタ6個分の48バイトと、XMMレジスタ8個分の128バイトを16バイト境界に置く。
+    fn declare_va_area(&mut self, named: usize) -> i64 {
+        self.offset = (self.offset + 176 + 15) / 16 * 16;
+        self.va_area = Some((named, self.offset));
+        self.offset
+    }
+
+    /// 関数のスタックフレームの大きさ。16の倍数にする。
+    fn stack_size(&self) -> i64 {
+        (self.offset + 15) / 16 * 16
     }
-    Ok(node::new_program(codes))
 }
 
-fn function(token: &mut TokenList) -> ParseResult<Function> {
-    let mut vars = Vec::new();
-    token.expect_reserved("int")?;
-    if let Some(name) = token.expect_identify() {
-        token.expect_reserved("(")?;
-        let mut args = Vec::new();
-        let mut multi = false;
-        while !token.consume_reserved(")") {
-            if multi {
-                token.expect_reserved(",")?;
+/// 構文木と、構文解析を続けられたエラーを返す。
+///
+/// 構文解析を続けられないエラーを見つけた場合は、それまでに見つけたエラーとまとめて返す。
+pub fn program(token: &mut TokenList) -> Result<(Program, Vec<CompileError>), CompileErrors> {
+    let mut vars = VariableList::new();
+    match translation_unit(token, &mut vars) {
+        Ok(program) => Ok((program, vars.errors)),
+        Err(e) => {
+            let mut errors = vars.errors;
+            errors.push(e);
+            Err(errors.into())
+        }
+    }
+}
+
+fn translation_unit(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Program> {
+    let mut codes = Vec::new();
+    let mut globals = Vec::new();
+    while !token.at_eof() {
+        let (class, inline) = specifiers(token);
+        let base = type_specifier(token)?;
+        let (derivations, name) = declarator(token, vars)?;
+        if let Some(Derivation::Function(..)) = derivations.last() {
+            if let Some(mut function) = function(token, vars, class, base, derivations, name)? {
+                function.set_inline(inline);
+                codes.push(function);
+            }
+        } else if inline {
+            return Err(token.error("inlineは関数にだけ指定できます。"));
+        } else {
+            globals.push(declare_variable(
+                token,
+                vars,
+                class,
+                base.clone(),
+                derivations,
+                name,
+            )?);
+            while token.consume_reserved(",") {
+                globals.push(init_declarator(token, vars, class, base.clone())?);
             }
-            args.push(declaration(token, &mut vars)?);
-            multi = true;
+            token.expect_reserved(";")?;
         }
+    }
+    Ok(node::new_program(codes, globals))
+}
 
-        token.expect_reserved("{")?;
-        let mut stmt = Vec::new();
-        while !token.consume_reserved("}") {
-            stmt.push(statement(token, &mut vars)?);
+/// 関数の定義を読む。プロトタイプ宣言の場合はNoneを返す。
+///
+/// 戻り値の型と関数の宣言子は読んだ後で呼び出す。
+fn function(
+    token: &mut TokenList,
+    vars: &mut VariableList,
+    class: StorageClass,
+    base: VariableType,
+    mut derivations: Vec<Derivation>,
+    name: Option<String>,
+) -> ParseResult<Option<Function>> {
+    let (name, parameters, variadic, prototype) = match (derivations.pop(), name) {
+        (Some(Derivation::Function(parameters, variadic, prototype)), Some(name)) => {
+            (name, parameters, variadic, prototype)
         }
-        Ok(node::new_function(name, args, stmt))
+        _ => return Err(token.error("関数を定義してください。")),
+    };
+    let return_type = apply_derivations(token, base, derivations)?;
+    vars.declare_function(
+        token,
+        name.clone(),
+        VariableType::Function {
+            return_type: Box::new(return_type.clone()),
+            parameters: parameters.iter().map(|(t, _)| t.clone()).collect(),
+            variadic,
+            prototype,
+        },
+    )?;
+
+    if token.consume_reserved(";") {
+        return Ok(None);
+    }
+    if vars.defined.contains(&name) {
+        vars.errors.push(CompileError::new(
+            "関数が二重に定義されています。",
+            token.next_position(),
+            token.code(),
+        ));
     } else {
-        Err(token.error("関数を定義してください。"))
+        vars.defined.push(name.clone());
     }
+
+    vars.enter_function();
+    let mut args = Vec::new();
+    for (i, (t, s)) in parameters.into_iter().enumerate() {
+        let s = s.ok_or_else(|| token.error("引数の名前がありません。"))?;
+        args.push(if i < REGISTER_ARGUMENTS {
+            vars.declare(token, s, t)?
+        } else {
+            vars.declare_stack_argument(token, s, t, i - REGISTER_ARGUMENTS)?
+        });
+    }
+
+    let va_area = if variadic {
+        Some(vars.declare_va_area(args.len()))
+    } else {
+        None
+    };
+
+    token.expect_reserved("{")?;
+    let mut stmt = Vec::new();
+    while !token.consume_reserved("}") {
+        stmt.push(statement(token, vars)?);
+    }
+    let stack_size = vars.stack_size();
+    vars.leave_function();
+    let mut function = node::new_function(
+        name,
+        return_type,
+        class == StorageClass::Static,
+        args,
+        stmt,
+        stack_size,
+        va_area,
+    );
+    function.set_prototype(prototype);
+    Ok(Some(function))
 }
 
 fn statement(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Statement> {
     if token.consume_reserved("{") {
+        vars.enter_scope();
         let mut vect = Vec::new();
         while !token.consume_reserved("}") {
             vect.push(statement(token, vars)?);
         }
+        vars.leave_scope();
         Ok(node::new_block(vect))
     } else if token.consume_reserved("if") {
         token.expect_reserved("(")?;
@@ -69,37 +374,46 @@ fn statement(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Stat
         Ok(node::new_while(cond, stmt))
     } else if token.consume_reserved("for") {
         token.expect_reserved("(")?;
-        let init = if !token.consume_reserved(";") {
-            let init = expression(token, vars)?;
+        vars.enter_scope();
+        let init = if token.consume_reserved(";") {
+            Statement::Expression(node::new_expression(
+                ExpressionKind::Num(1),
+                token.next_position(),
+            ))
+        } else if next_declaration(token) {
+            let init = declaration(token, vars)?;
             token.expect_reserved(";")?;
             init
         } else {
-            Expression::Num(1)
+            let init = expression(token, vars)?;
+            token.expect_reserved(";")?;
+            Statement::Expression(init)
         };
         let cond = if !token.consume_reserved(";") {
             let cond = expression(token, vars)?;
             token.expect_reserved(";")?;
             cond
         } else {
-            Expression::Num(1)
+            node::new_expression(ExpressionKind::Num(1), token.next_position())
         };
         let iter = if !token.consume_reserved(")") {
             let iter = expression(token, vars)?;
             token.expect_reserved(")")?;
             iter
         } else {
-            Expression::Num(1)
+            node::new_expression(ExpressionKind::Num(1), token.next_position())
         };
         let stmt = statement(token, vars)?;
+        vars.leave_scope();
         Ok(node::new_for(init, cond, iter, stmt))
     } else if token.consume_reserved("return") {
         let node = node::new_return(expression(token, vars)?);
         token.expect_reserved(";")?;
         Ok(node)
-    } else if token.next_reserved("int") {
+    } else if next_declaration(token) {
         let node = declaration(token, vars)?;
         token.expect_reserved(";")?;
-        Ok(Statement::Declaration(node))
+        Ok(node)
     } else {
         let node = expression(token, vars)?;
         token.expect_reserved(";")?;
@@ -156,27 +470,11 @@ fn relational(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Exp
 
 fn add(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Expression> {
     let mut node = mul(token, vars)?;
-    let rate = match node.kind() {
-        Ok(VariableType::Pointer(t)) | Ok(VariableType::Array(t, _)) => t.size(),
-        _ => 1,
-    };
     loop {
         if token.consume_reserved("+") {
-            let mul = mul(token, vars)?;
-            let rated = if rate != 1 {
-                node::new_binary(BinaryKind::Multiply, mul, Expression::Num(rate))
-            } else {
-                mul
-            };
-            node = node::new_binary(BinaryKind::Add, node, rated);
+            node = node::new_binary(BinaryKind::Add, node, mul(token, vars)?);
         } else if token.consume_reserved("-") {
-            let mul = mul(token, vars)?;
-            let rated = if rate != 1 {
-                node::new_binary(BinaryKind::Multiply, mul, Expression::Num(rate))
-            } else {
-                mul
-            };
-            node = node::new_binary(BinaryKind::Subtract, node, rated);
+            node = node::new_binary(BinaryKind::Subtract, node, mul(token, vars)?);
         } else {
             return Ok(node);
         }
@@ -197,109 +495,697 @@ fn mul(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Expression
 }
 
 fn unary(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Expression> {
+    let position = token.next_position();
     if token.consume_reserved("+") {
-        Ok(primary(token, vars)?)
+        Ok(postfix(token, vars)?)
     } else if token.consume_reserved("-") {
         Ok(node::new_binary(
             BinaryKind::Subtract,
-            Expression::Num(0),
-            primary(token, vars)?,
+            node::new_expression(ExpressionKind::Num(0), position),
+            postfix(token, vars)?,
         ))
     } else if token.consume_reserved("*") {
-        Ok(node::new_unary(UnaryKind::Deref, unary(token, vars)?))
+        Ok(node::new_unary(
+            UnaryKind::Deref,
+            unary(token, vars)?,
+            position,
+        ))
     } else if token.consume_reserved("&") {
-        Ok(node::new_unary(UnaryKind::Address, unary(token, vars)?))
+        Ok(node::new_unary(
+            UnaryKind::Address,
+            unary(token, vars)?,
+            position,
+        ))
     } else if token.consume_reserved("sizeof") {
-        match unary(token, vars)?.kind() {
-            Ok(t) => Ok(Expression::Num(t.size())),
-            Err(s) => Err(token.error(s)),
+        if token.next_reserved("(") && nth_type(token, 1) {
+            token.expect_reserved("(")?;
+            let t = type_name(token, vars)?;
+            token.expect_reserved(")")?;
+            // sizeofの値はunsigned long型
+            let size = node::new_expression(ExpressionKind::Num(t.size()), position);
+            return Ok(node::new_cast(VariableType::UnsignedLong, size, position));
         }
+        let expression = unary(token, vars)?;
+        Ok(node::new_expression(
+            ExpressionKind::SizeOf(Box::new(expression)),
+            position,
+        ))
+    } else if token.next_reserved("(") && nth_type(token, 1) {
+        token.expect_reserved("(")?;
+        let t = type_name(token, vars)?;
+        token.expect_reserved(")")?;
+        Ok(node::new_cast(t, unary(token, vars)?, position))
     } else {
-        Ok(primary(token, vars)?)
+        Ok(postfix(token, vars)?)
+    }
+}
+
+fn postfix(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Expression> {
+    let mut node = primary(token, vars)?;
+    loop {
+        if token.consume_reserved("[") {
+            // a[i] は *(a + i) として扱う
+            let position = node.position();
+            let index = expression(token, vars)?;
+            token.expect_reserved("]")?;
+            node = node::new_unary(
+                UnaryKind::Deref,
+                node::new_binary(BinaryKind::Add, node, index),
+                position,
+            );
+        } else if token.consume_reserved("(") {
+            let mut args = Vec::new();
+            while !token.consume_reserved(")") {
+                if !args.is_empty() {
+                    token.expect_reserved(",")?;
+                }
+                args.push(assign(token, vars)?);
+            }
+            node = node::new_call(node, args);
+        } else {
+            return Ok(node);
+        }
     }
 }
 
 fn primary(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Expression> {
+    let position = token.next_position();
     if token.consume_reserved("(") {
         let node = expression(token, vars)?;
         token.expect_reserved(")")?;
         Ok(node)
     } else if token.next_identify() {
         Ok(identify(token, vars)?)
+    } else if token.next_string() {
+        // 隣り合った文字列リテラルは連結する
+        let mut bytes = token.expect_string()?;
+        while token.next_string() {
+            bytes.extend(token.expect_string()?);
+        }
+        Ok(node::new_expression(
+            ExpressionKind::StringLiteral(bytes),
+            position,
+        ))
+    } else if token.consume_reserved("va_start") {
+        token.expect_reserved("(")?;
+        let ap = assign(token, vars)?;
+        token.expect_reserved(",")?;
+        if token.expect_identify().is_none() {
+            return Err(token.error("引数の名前ではありません。"));
+        }
+        token.expect_reserved(")")?;
+        match vars.va_area {
+            Some((named, area)) => Ok(node::new_expression(
+                ExpressionKind::VaStart {
+                    ap: Box::new(ap),
+                    named,
+                    area,
+                },
+                position,
+            )),
+            None => Err(token.error("可変長引数の関数ではありません。")),
+        }
+    } else if token.consume_reserved("va_arg") {
+        token.expect_reserved("(")?;
+        let ap = assign(token, vars)?;
+        token.expect_reserved(",")?;
+        let var_type = type_name(token, vars)?;
+        token.expect_reserved(")")?;
+        Ok(node::new_expression(
+            ExpressionKind::VaArg {
+                ap: Box::new(ap),
+                var_type,
+            },
+            position,
+        ))
+    } else if token.consume_reserved("va_end") {
+        token.expect_reserved("(")?;
+        let ap = assign(token, vars)?;
+        token.expect_reserved(")")?;
+        Ok(node::new_expression(
+            ExpressionKind::VaEnd(Box::new(ap)),
+            position,
+        ))
     } else {
         Ok(number(token, vars)?)
     }
 }
 
 fn number(token: &mut TokenList, _vars: &mut VariableList) -> ParseResult<Expression> {
-    Ok(Expression::Num(token.expect_num()?))
+    let position = token.next_position();
+    Ok(node::new_expression(
+        ExpressionKind::Num(token.expect_num()?),
+        position,
+    ))
 }
 
 fn identify(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Expression> {
+    let position = token.next_position();
     if let Some(name) = token.expect_identify() {
-        if token.consume_reserved("(") {
-            let mut args = Vec::new();
-            let mut multi = false;
-            while !token.consume_reserved(")") {
-                if multi {
-                    token.expect_reserved(",")?;
-                }
-                args.push(expression(token, vars)?);
-                multi = true;
-            }
-            return Ok(Expression::FunctionCall { name, args });
-        }
-
-        let mut offset = 0;
-        for (var, t, i) in &*vars {
-            offset += i;
-            if var == &name {
-                return Ok(Expression::LocalVariable(node::new_variable(
-                    t.clone(),
-                    name,
-                    offset,
-                )));
-            }
+        if let Some(var) = vars.find(&name) {
+            return Ok(node::new_expression(
+                ExpressionKind::Variable(var.clone()),
+                position,
+            ));
+        }
+
+        if let Some(var_type) = vars.find_function(&name) {
+            let var_type = var_type.clone();
+            return Ok(node::new_expression(
+                ExpressionKind::FunctionName { name, var_type },
+                position,
+            ));
+        }
+
+        // 宣言されていない関数は、intを返す関数として呼び出す
+        if token.next_reserved("(") {
+            let var_type = VariableType::Function {
+                return_type: Box::new(VariableType::Int),
+                parameters: Vec::new(),
+                variadic: false,
+                prototype: false,
+            };
+            return Ok(node::new_expression(
+                ExpressionKind::FunctionName { name, var_type },
+                position,
+            ));
         }
 
-        Err(token.error("宣言された変数ではありません。"))
+        // 後のエラーも報告できるように、エラーを記録して解析を続ける
+        vars.errors.push(CompileError::new(
+            "宣言された変数ではありません。",
+            position,
+            token.code(),
+        ));
+        Ok(node::new_expression(
+            ExpressionKind::Undeclared(name),
+            position,
+        ))
     } else {
         Err(token.error("識別子ではありません。"))
     }
 }
 
-fn declaration(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Variable> {
-    token.expect_reserved("int")?;
-    let (t, s) = declaration_identify(token)?;
-    let mut ofs = 0;
-    for (var, _, i) in &*vars {
-        ofs += i;
-        if *var == s {
-            return Err(token.error("すでに宣言された変数です。"));
+/// 同じ関数の宣言として、型が合うか確かめる。引数の型を宣言していない宣言は、どの引数の並びとも合う。
+fn same_function(old: &VariableType, new: &VariableType) -> bool {
+    match (old, new) {
+        (
+            VariableType::Function {
+                return_type: r1,
+                parameters: p1,
+                variadic: v1,
+                ..
+            },
+            VariableType::Function {
+                return_type: r2,
+                parameters: p2,
+                variadic: v2,
+                ..
+            },
+        ) => {
+            r1 == r2
+                && (parameters_unknown(old)
+                    || parameters_unknown(new)
+                    || v1 == v2
+                        && p1.len() == p2.len()
+                        && p1
+                            .iter()
+                            .zip(p2)
+                            .all(|(a, b)| a.unqualified() == b.unqualified()))
         }
+        _ => false,
     }
-    if token.consume_reserved("[") {
-        let size = token.expect_num()?;
-        token.expect_reserved("]")?;
-        let byte_size = t.size() * size;
-        let ty = VariableType::Array(Box::new(t), size);
-        vars.push((s.clone(), ty.clone(), byte_size));
-        Ok(node::new_variable(ty, s, ofs + byte_size))
+}
+
+/// 引数の型を宣言していない、f()のような関数の型か確かめる。f(void)は引数のない関数として区別する。
+fn parameters_unknown(var_type: &VariableType) -> bool {
+    matches!(
+        var_type,
+        VariableType::Function {
+            prototype: false,
+            ..
+        }
+    )
+}
+
+const TYPE_KEYWORDS: [&str; 5] = ["void", "char", "short", "int", "long"];
+
+fn next_type(token: &mut TokenList) -> bool {
+    nth_type(token, 0)
+}
+
+/// 次のトークンが宣言の始まりか確かめる。
+fn next_declaration(token: &mut TokenList) -> bool {
+    next_type(token) || token.next_reserved("static") || token.next_reserved("extern")
+}
+
+/// 宣言の先頭の記憶域クラス指定子を読む。
+fn storage_class(token: &mut TokenList) -> StorageClass {
+    if token.consume_reserved("static") {
+        StorageClass::Static
+    } else if token.consume_reserved("extern") {
+        StorageClass::Extern
     } else {
-        let i = t.size();
-        vars.push((s.clone(), t.clone(), i));
-        Ok(node::new_variable(t, s, ofs + i))
+        StorageClass::Auto
+    }
+}
+
+/// ファイルスコープの宣言の先頭の記憶域クラス指定子と、関数指定子inlineを読む。
+fn specifiers(token: &mut TokenList) -> (StorageClass, bool) {
+    let mut inline = token.consume_reserved("inline");
+    let class = storage_class(token);
+    inline |= token.consume_reserved("inline");
+    (class, inline)
+}
+
+/// n個先のトークンが型の始まりか確かめる。
+fn nth_type(token: &TokenList, n: usize) -> bool {
+    TYPE_KEYWORDS.iter().any(|t| token.nth_reserved(n, t))
+        || token.nth_reserved(n, "va_list")
+        || token.nth_reserved(n, "const")
+        || token.nth_reserved(n, "volatile")
+        || token.nth_reserved(n, "signed")
+        || token.nth_reserved(n, "unsigned")
+}
+
+/// 型修飾子を読めるだけ読む。
+fn qualifiers(token: &mut TokenList) -> Qualifiers {
+    let mut qualifiers = Qualifiers::default();
+    loop {
+        if token.consume_reserved("const") {
+            qualifiers.is_const = true;
+        } else if token.consume_reserved("volatile") {
+            qualifiers.is_volatile = true;
+        } else {
+            return qualifiers;
+        }
+    }
+}
+
+/// 型指定子を読む。型修飾子は型指定子の前後どちらにも書ける。
+fn type_specifier(token: &mut TokenList) -> ParseResult<VariableType> {
+    let mut q = qualifiers(token);
+    // va_listはSystem V ABIの__va_list_tag[1]と同じ大きさの領域として扱う
+    if token.consume_reserved("va_list") {
+        let t = VariableType::Array(Box::new(VariableType::Char), 24);
+        return Ok(t.qualify(q));
+    }
+
+    // 各キーワードが現れた回数を数えて、組み合わせから型を決める
+    let mut counts = [0; 5];
+    let mut signed = 0;
+    let mut unsigned = 0;
+    loop {
+        if let Some(i) = TYPE_KEYWORDS.iter().position(|t| token.next_reserved(t)) {
+            token.expect_reserved(TYPE_KEYWORDS[i])?;
+            counts[i] += 1;
+        } else if token.consume_reserved("signed") {
+            signed += 1;
+        } else if token.consume_reserved("unsigned") {
+            unsigned += 1;
+        } else if token.next_reserved("const") || token.next_reserved("volatile") {
+            let next = qualifiers(token);
+            q.is_const |= next.is_const;
+            q.is_volatile |= next.is_volatile;
+        } else {
+            break;
+        }
+    }
+    let t = match (counts, signed, unsigned) {
+        ([1, 0, 0, 0, 0], 0, 0) => VariableType::Void,
+        ([0, 1, 0, 0, 0], _, 0) => VariableType::Char,
+        ([0, 0, 1, 0, 0], _, 0) | ([0, 0, 1, 1, 0], _, 0) => VariableType::Short,
+        ([0, 0, 0, 1, 0], _, 0) | ([0, 0, 0, 0, 0], 1, 0) => VariableType::Int,
+        ([0, 0, 0, 0, 1], _, 0)
+        | ([0, 0, 0, 1, 1], _, 0)
+        | ([0, 0, 0, 0, 2], _, 0)
+        | ([0, 0, 0, 1, 2], _, 0) => VariableType::Long,
+        ([0, 1, 0, 0, 0], 0, 1) => VariableType::UnsignedChar,
+        ([0, 0, 1, 0, 0], 0, 1) | ([0, 0, 1, 1, 0], 0, 1) => VariableType::UnsignedShort,
+        ([0, 0, 0, 1, 0], 0, 1) | ([0, 0, 0, 0, 0], 0, 1) => VariableType::UnsignedInt,
+        ([0, 0, 0, 0, 1], 0, 1)
+        | ([0, 0, 0, 1, 1], 0, 1)
+        | ([0, 0, 0, 0, 2], 0, 1)
+        | ([0, 0, 0, 1, 2], 0, 1) => VariableType::UnsignedLong,
+        _ => return Err(token.error("型ではありません。")),
+    };
+    Ok(t.qualify(q))
+}
+
+/// キャストやsizeofに書かれる、変数名のない型を読む。
+fn type_name(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<VariableType> {
+    let base = type_specifier(token)?;
+    let (derivations, name) = declarator(token, vars)?;
+    if name.is_some() {
+        return Err(token.error("型名に変数名は書けません。"));
+    }
+    apply_derivations(token, base, derivations)
+}
+
+/// 宣言子が基本の型に付け加える型の組み立て方
+enum Derivation {
+    /// ポインタ自身の修飾子
+    Pointer(Qualifiers),
+    /// 大きさが省略された場合はNone
+    Array(Option<i64>),
+    /// 引数の型と名前、可変長引数か、引数の型を宣言しているか
+    Function(ParameterList, bool, bool),
+}
+
+/// 宣言子を読み、基本の型に当てはめる順の組み立て方と、変数名を返す。
+///
+/// 変数名のない抽象宣言子も読む。
+fn declarator(
+    token: &mut TokenList,
+    vars: &mut VariableList,
+) -> ParseResult<(Vec<Derivation>, Option<String>)> {
+    let mut pointers = Vec::new();
+    while token.consume_reserved("*") {
+        pointers.push(Derivation::Pointer(qualifiers(token)));
+    }
+
+    // 括弧の中の宣言子は、外側の宣言子を当てはめた後に当てはめる
+    let (inner, name) =
+        if token.next_reserved("(") && !nth_type(token, 1) && !token.nth_reserved(1, ")") {
+            token.expect_reserved("(")?;
+            let inner = declarator(token, vars)?;
+            token.expect_reserved(")")?;
+            inner
+        } else if token.next_identify() {
+            (Vec::new(), token.expect_identify())
+        } else {
+            (Vec::new(), None)
+        };
+
+    let mut suffixes = Vec::new();
+    loop {
+        if token.consume_reserved("[") {
+            let size = if token.next_reserved("]") {
+                None
+            } else {
+                let mut expr = equality(token, vars)?;
+                let size = semantic::integer_constant(&mut expr, token.code())?;
+                if size < 0 {
+                    return Err(token.error("配列の大きさが負です。"));
+                }
+                Some(size)
+            };
+            token.expect_reserved("]")?;
+            suffixes.push(Derivation::Array(size));
+        } else if token.consume_reserved("(") {
+            let (parameters, variadic, prototype) = parameter_list(token, vars)?;
+            suffixes.push(Derivation::Function(parameters, variadic, prototype));
+        } else {
+            break;
+        }
+    }
+
+    let mut derivations = pointers;
+    derivations.extend(suffixes.into_iter().rev());
+    derivations.extend(inner);
+    Ok((derivations, name))
+}
+
+/// 基本の型に宣言子の組み立て方を順に当てはめる。
+fn apply_derivations(
+    token: &TokenList,
+    base: VariableType,
+    derivations: Vec<Derivation>,
+) -> ParseResult<VariableType> {
+    let mut t = base;
+    for derivation in derivations {
+        t = match (derivation, t) {
+            (Derivation::Pointer(q), t) => VariableType::Pointer(Box::new(t)).qualify(q),
+            (Derivation::Array(_), VariableType::Function { .. }) => {
+                return Err(token.error("関数の配列は宣言できません。"))
+            }
+            (Derivation::Array(Some(size)), t) => {
+                let t = VariableType::Array(Box::new(t), size);
+                if t.checked_size().is_none() {
+                    return Err(token.error("配列が大きすぎます。"));
+                }
+                t
+            }
+            (Derivation::Array(None), _) => return Err(token.error("配列の大きさが分かりません。")),
+            (Derivation::Function(..), VariableType::Array(_, _))
+            | (Derivation::Function(..), VariableType::Function { .. }) => {
+                return Err(token.error("配列や関数を返す関数は宣言できません。"))
+            }
+            (Derivation::Function(parameters, variadic, prototype), t) => VariableType::Function {
+                return_type: Box::new(t),
+                parameters: parameters.into_iter().map(|(t, _)| t).collect(),
+                variadic,
+                prototype,
+            },
+        };
+    }
+    Ok(t)
+}
+
+/// 関数の引数の並びを"("の後から")"まで読み、引数の型と名前、可変長引数か、引数の型を宣言しているかを返す。
+///
+/// プロトタイプ宣言では引数の名前を省略できる。
+fn parameter_list(
+    token: &mut TokenList,
+    vars: &mut VariableList,
+) -> ParseResult<(ParameterList, bool, bool)> {
+    let mut parameters = Vec::new();
+    // ()は引数を宣言しておらず、(void)は引数がないことを宣言している
+    let prototype = !token.next_reserved(")");
+    if token.next_reserved("void") && token.nth_reserved(1, ")") {
+        token.expect_reserved("void")?;
+    }
+    while !token.consume_reserved(")") {
+        if !parameters.is_empty() {
+            token.expect_reserved(",")?;
+        }
+        if token.consume_reserved("...") {
+            token.expect_reserved(")")?;
+            return Ok((parameters, true, prototype));
+        }
+        let base = type_specifier(token)?;
+        let (mut derivations, name) = declarator(token, vars)?;
+        // 配列型の引数はポインタとして受け取る
+        if let Some(Derivation::Array(_)) = derivations.last() {
+            derivations.pop();
+            derivations.push(Derivation::Pointer(Qualifiers::default()));
+        }
+        let t = match apply_derivations(token, base, derivations)? {
+            // 関数型の引数は関数へのポインタとして受け取る
+            t @ VariableType::Function { .. } => VariableType::Pointer(Box::new(t)),
+            VariableType::Array(t, _) => VariableType::Pointer(t),
+            t => t,
+        };
+        parameters.push((t, name));
+    }
+    Ok((parameters, false, prototype))
+}
+
+fn declaration(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Statement> {
+    let class = storage_class(token);
+    let base = type_specifier(token)?;
+    let mut declarations = Vec::new();
+    loop {
+        declarations.push(init_declarator(token, vars, class, base.clone())?);
+        if !token.consume_reserved(",") {
+            return Ok(Statement::Declaration(declarations));
+        }
+    }
+}
+
+fn init_declarator(
+    token: &mut TokenList,
+    vars: &mut VariableList,
+    class: StorageClass,
+    base: VariableType,
+) -> ParseResult<Declaration> {
+    let (derivations, name) = declarator(token, vars)?;
+    declare_variable(token, vars, class, base, derivations, name)
+}
+
+/// 宣言子を読んだ後の変数を、初期化子とともに記憶域クラスに合わせて宣言する。
+fn declare_variable(
+    token: &mut TokenList,
+    vars: &mut VariableList,
+    class: StorageClass,
+    base: VariableType,
+    mut derivations: Vec<Derivation>,
+    name: Option<String>,
+) -> ParseResult<Declaration> {
+    let name = name.ok_or_else(|| token.error("宣言が変数ではありません。"))?;
+    // 一番外側の配列は、大きさを初期化子から決められる
+    let incomplete = matches!(derivations.last(), Some(Derivation::Array(None)));
+    if incomplete {
+        derivations.pop();
+    }
+    let t = apply_derivations(token, base, derivations)?;
+    if let VariableType::Function { .. } = t {
+        return Err(token.error("関数型の変数は宣言できません。"));
+    }
+    if *t.unqualified() == VariableType::Void {
+        return Err(token.error("void型の変数は宣言できません。"));
     }
+
+    let init = if token.consume_reserved("=") {
+        Some(initializer(token, vars)?)
+    } else {
+        None
+    };
+
+    let (t, init) = match init {
+        None if incomplete => return Err(token.error("配列の大きさが分かりません。")),
+        None => (t, None),
+        Some(init) if incomplete => {
+            let list = array_initializer(token, &t, None, init)?;
+            let size = list.len() as i64;
+            (
+                VariableType::Array(Box::new(t), size),
+                Some(Initializer::List(list)),
+            )
+        }
+        Some(init) => {
+            let init = normalize_initializer(token, &t, init)?;
+            (t, Some(init))
+        }
+    };
+
+    let var = match (class, &init) {
+        (StorageClass::Auto, _) if vars.in_function => vars.declare(token, name, t)?,
+        // 初期化子のあるextern宣言は定義として扱う
+        (StorageClass::Extern, Some(_)) if vars.in_function => {
+            return Err(token.error("ブロック内のextern宣言は初期化できません。"))
+        }
+        (StorageClass::Extern, Some(_)) => {
+            vars.declare_static(token, name, t, StorageClass::Auto)?
+        }
+        (class, _) => vars.declare_static(token, name, t, class)?,
+    };
+    Ok(node::new_declaration(var, init))
 }
 
-fn declaration_identify(token: &mut TokenList) -> Result<(VariableType, String), CompileError> {
-    if token.consume_reserved("*") {
-        let (t, s) = declaration_identify(token)?;
-        Ok((VariableType::Pointer(Box::new(t)), s))
+fn initializer(token: &mut TokenList, vars: &mut VariableList) -> ParseResult<Initializer> {
+    if token.consume_reserved("{") {
+        let mut list = Vec::new();
+        while !token.consume_reserved("}") {
+            list.push(initializer(token, vars)?);
+            if !token.consume_reserved(",") {
+                token.expect_reserved("}")?;
+                break;
+            }
+        }
+        Ok(Initializer::List(list))
     } else {
-        match token.expect_identify() {
-            Some(s) => Ok((VariableType::Int, s)),
-            None => Err(token.error("宣言が変数ではありません。")),
+        Ok(Initializer::Expression(assign(token, vars)?))
+    }
+}
+
+/// 初期化子を変数の型に合わせて、括弧が省略されていない形にする。
+///
+/// 型に合わない初期化子や、配列の大きさを超える初期化子はエラーにする。
+fn normalize_initializer(
+    token: &TokenList,
+    var_type: &VariableType,
+    init: Initializer,
+) -> ParseResult<Initializer> {
+    match (var_type, init) {
+        (VariableType::Array(t, size), init) => Ok(Initializer::List(array_initializer(
+            token,
+            t,
+            Some(*size),
+            init,
+        )?)),
+        (_, Initializer::List(list)) => {
+            let mut list = list.into_iter();
+            match (list.next(), list.next()) {
+                (Some(init @ Initializer::Expression(_)), None) => {
+                    normalize_initializer(token, var_type, init)
+                }
+                _ => Err(token.error("スカラーの初期化子が正しくありません。")),
+            }
+        }
+        (_, init) => Ok(init),
+    }
+}
+
+/// 要素の型がelementの配列の初期化子を、要素ごとの初期化子の並びにする。
+///
+/// sizeがNoneのときは、初期化子の数だけ要素を読む。
+fn array_initializer(
+    token: &TokenList,
+    element: &VariableType,
+    size: Option<i64>,
+    init: Initializer,
+) -> ParseResult<Vec<Initializer>> {
+    match init {
+        Initializer::Expression(expr) => match expr.kind() {
+            ExpressionKind::StringLiteral(bytes)
+                if *element.unqualified() == VariableType::Char =>
+            {
+                string_initializer(token, bytes.to_vec(), expr.position(), size)
+            }
+            _ => Err(token.error("配列の初期化子は{}で囲んでください。")),
+        },
+        Initializer::List(list) => {
+            let mut items = list.into_iter().peekable();
+            let mut list = Vec::new();
+            while items.peek().is_some() {
+                if Some(list.len() as i64) == size {
+                    return Err(token.error("配列の初期化子が多すぎます。"));
+                }
+                list.push(element_initializer(token, element, &mut items)?);
+            }
+            Ok(list)
+        }
+    }
+}
+
+/// 初期化子の並びから、型がelementの要素1つ分の初期化子を取り出す。
+///
+/// 要素が配列で{}が省略されている場合は、その配列の大きさだけ続けて取り出す。
+fn element_initializer(
+    token: &TokenList,
+    element: &VariableType,
+    items: &mut std::iter::Peekable<std::vec::IntoIter<Initializer>>,
+) -> ParseResult<Initializer> {
+    match (element, items.peek()) {
+        (VariableType::Array(t, size), Some(Initializer::Expression(e)))
+            if !(*t.unqualified() == VariableType::Char
+                && matches!(e.kind(), ExpressionKind::StringLiteral(_))) =>
+        {
+            let mut list = Vec::new();
+            while (list.len() as i64) < *size && items.peek().is_some() {
+                list.push(element_initializer(token, t, items)?);
+            }
+            Ok(Initializer::List(list))
+        }
+        _ => normalize_initializer(token, element, items.next().unwrap()),
+    }
+}
+
+/// char配列を初期化する文字列リテラルを、文字の初期化子の並びにする。
+///
+/// 配列の大きさが文字列の長さと等しい場合、終端のヌル文字は含めない。
+fn string_initializer(
+    token: &TokenList,
+    mut bytes: Vec<u8>,
+    position: usize,
+    size: Option<i64>,
+) -> ParseResult<Vec<Initializer>> {
+    if size != Some(bytes.len() as i64) {
+        bytes.push(0);
+    }
+    if let Some(size) = size {
+        if bytes.len() as i64 > size {
+            return Err(token.error("配列の初期化子が多すぎます。"));
         }
     }
+    Ok(bytes
+        .into_iter()
+        .map(|b| {
+            Initializer::Expression(node::new_expression(
+                ExpressionKind::Num(b as i64),
+                position,
+            ))
+        })
+        .collect())
 }
@@ -2,3 +2,4 @@ mod analyze;
 pub mod node;
 
 pub use analyze::program as analyze;
+pub use analyze::STACK_LIMIT;
@@ -3,6 +3,9 @@ use std::fmt::{Debug, Formatter, Result};
 impl Debug for super::Program {
     fn fmt(&self, f: &mut Formatter) -> Result {
         write!(f, "Program {{ ")?;
+        for global in &self.globals {
+            write!(f, "{:?}; ", global)?;
+        }
         for func in &self.codes {
             write!(f, "{:?} ", &func)?;
         }
@@ -12,10 +15,16 @@ impl Debug for super::Program {
 
 impl Debug for super::Function {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "{} (", self.name)?;
+        if self.internal {
+            write!(f, "Static ")?;
+        }
+        write!(f, "{:?} {} (", self.return_type, self.name)?;
         for arg in &self.arguments {
             write!(f, "{:?} ", arg)?;
         }
+        if self.va_area.is_some() {
+            write!(f, "... ")?;
+        }
         write!(f, ") {{ ")?;
         for stmt in &self.statements {
             write!(f, "{:?}; ", stmt)?;
@@ -29,7 +38,13 @@ impl Debug for super::Statement {
         use super::Statement::{Block, Declaration, Expression, For, If, IfElse, Return, While};
         match self {
             Return(expr) => write!(f, "Return {:?}", expr),
-            Declaration(var) => write!(f, "Declaration {:?}", var),
+            Declaration(declarations) => {
+                write!(f, "Declaration")?;
+                for declaration in declarations {
+                    write!(f, " {:?}", declaration)?;
+                }
+                Ok(())
+            }
             Expression(expr) => write!(f, "{:?}", expr),
             If {
                 condition,
@@ -71,10 +86,20 @@ impl Debug for super::Statement {
 
 impl Debug for super::Expression {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        use super::Expression::{BinaryOperator, FunctionCall, LocalVariable, Num, UnaryOperator};
+        write!(f, "{:?}", self.kind)
+    }
+}
+
+impl Debug for super::ExpressionKind {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        use super::ExpressionKind::{
+            BinaryOperator, Cast, FunctionCall, FunctionName, Num, SizeOf, StringLiteral,
+            UnaryOperator, Undeclared, VaArg, VaEnd, VaStart, Variable,
+        };
         match self {
-            FunctionCall { name, args } => {
-                write!(f, "{} ( ", name)?;
+            FunctionName { name, .. } => write!(f, "{}", name),
+            FunctionCall { callee, args } => {
+                write!(f, "{:?} ( ", callee)?;
                 for arg in args {
                     write!(f, "{:?} ", arg)?;
                 }
@@ -84,15 +109,65 @@ impl Debug for super::Expression {
                 write!(f, "({:?} {:?} {:?})", kind, left, right)
             }
             UnaryOperator { kind, expression } => write!(f, "({:?} {:?})", kind, expression),
+            Cast {
+                var_type,
+                expression,
+            } => write!(f, "(Cast:{:?} {:?})", var_type, expression),
             Num(i) => write!(f, "{}", i),
-            LocalVariable(v) => write!(f, "{:?}", v),
+            StringLiteral(bytes) => write!(f, "{:?}", String::from_utf8_lossy(bytes)),
+            Variable(v) => write!(f, "{:?}", v),
+            VaStart { ap, .. } => write!(f, "(VaStart {:?})", ap),
+            VaArg { ap, var_type } => write!(f, "(VaArg {:?} {:?})", ap, var_type),
+            VaEnd(ap) => write!(f, "(VaEnd {:?})", ap),
+            SizeOf(expr) => write!(f, "(SizeOf {:?})", expr),
+            Undeclared(name) => write!(f, "(Undeclared {})", name),
+        }
+    }
+}
+
+impl Debug for super::Declaration {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match &self.initializer {
+            Some(init) => write!(f, "{:?} = {:?}", self.variable, init),
+            None => write!(f, "{:?}", self.variable),
+        }
+    }
+}
+
+impl Debug for super::Initializer {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            super::Initializer::Expression(expr) => write!(f, "{:?}", expr),
+            super::Initializer::List(list) => {
+                write!(f, "{{ ")?;
+                for init in list {
+                    write!(f, "{:?} ", init)?;
+                }
+                write!(f, "}}")
+            }
         }
     }
 }
 
 impl Debug for super::Variable {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "{} {:?}:{}", self.name, self.var_type, self.offset)
+        write!(f, "{} {:?}:{:?}", self.name, self.var_type, self.storage)
+    }
+}
+
+impl Debug for super::Storage {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        use super::Storage::{Extern, Local, Static};
+        match self {
+            Local(offset) => write!(f, "{}", offset),
+            Static { symbol, internal } => {
+                if *internal {
+                    write!(f, "Static ")?;
+                }
+                write!(f, "{}", symbol)
+            }
+            Extern => write!(f, "Extern"),
+        }
     }
 }
 
@@ -134,11 +209,49 @@ impl Debug for super::UnaryKind {
 
 impl Debug for super::VariableType {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        use super::VariableType::{Array, Int, Pointer};
+        use super::VariableType::{
+            Array, Char, Function, Int, Long, Pointer, Qualified, Short, UnsignedChar, UnsignedInt,
+            UnsignedLong, UnsignedShort, Void,
+        };
         match self {
+            Void => write!(f, "Void"),
+            Char => write!(f, "Char"),
+            Short => write!(f, "Short"),
             Int => write!(f, "Int"),
+            Long => write!(f, "Long"),
+            UnsignedChar => write!(f, "UChar"),
+            UnsignedShort => write!(f, "UShort"),
+            UnsignedInt => write!(f, "UInt"),
+            UnsignedLong => write!(f, "ULong"),
             Pointer(ty) => write!(f, "P({:?})", ty),
             Array(ty, size) => write!(f, "{:?}[{}]", ty, size),
+            Function {
+                return_type,
+                parameters,
+                variadic,
+                prototype,
+            } => {
+                write!(f, "Fn(")?;
+                if *prototype && parameters.is_empty() && !*variadic {
+                    write!(f, "void ")?;
+                }
+                for parameter in parameters {
+                    write!(f, "{:?} ", parameter)?;
+                }
+                if *variadic {
+                    write!(f, "... ")?;
+                }
+                write!(f, "-> {:?})", return_type)
+            }
+            Qualified(ty, q) => {
+                if q.is_const {
+                    write!(f, "Const ")?;
+                }
+                if q.is_volatile {
+                    write!(f, "Volatile ")?;
+                }
+                write!(f, "{:?}", ty)
+            }
         }
     }
 }
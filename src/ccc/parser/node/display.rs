@@ -0,0 +1,415 @@
+//! 構文木をC言語のソースコードとして出力する。
+//!
+//! 出力したソースコードを構文解析すると、同じ構文木になる。
+//! 演算子の優先順位に頼らないように、すべての演算を括弧で囲む。
+
+use super::visit::{self, Visitor};
+use super::{
+    BinaryKind, CompareKind, Declaration, Expression, ExpressionKind, Function, Initializer,
+    Program, Statement, Storage, UnaryKind, VariableType,
+};
+use std::fmt::{Display, Formatter, Result};
+
+/// 字下げ1段の幅
+const INDENT: &str = "    ";
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        // 関数は定義の順番に関係なく呼び出せるように、先にすべて宣言する
+        let mut prototypes = Prototypes::default();
+        prototypes.visit_program(self);
+        for (name, var_type, internal) in &prototypes.functions {
+            if *internal {
+                write!(f, "static ")?;
+            }
+            writeln!(f, "{};", declare(var_type, name.clone()))?;
+        }
+
+        for global in &self.globals {
+            let storage = match global.variable.storage() {
+                Storage::Static { internal: true, .. } => "static ",
+                Storage::Extern => "extern ",
+                _ => "",
+            };
+            let printer = Printer { function: None };
+            writeln!(f, "{}{};", storage, printer.declaration(global))?;
+        }
+
+        for function in &self.codes {
+            writeln!(f)?;
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let mut parameters = self
+            .arguments
+            .iter()
+            .map(|arg| declare(arg.var_type(), arg.name().clone()))
+            .collect::<Vec<_>>();
+        if self.va_area.is_some() {
+            parameters.push("...".to_string());
+        }
+        if self.prototype && parameters.is_empty() {
+            parameters.push("void".to_string());
+        }
+        if self.internal {
+            write!(f, "static ")?;
+        }
+        if self.inline {
+            write!(f, "inline ")?;
+        }
+        let declarator = format!("{}({})", self.name, parameters.join(", "));
+        writeln!(f, "{} {{", declare(&self.return_type, declarator))?;
+        let printer = Printer {
+            function: Some(self),
+        };
+        for statement in &self.statements {
+            printer.statement(f, statement, 1)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// 出力する関数の宣言を集める。
+#[derive(Default)]
+struct Prototypes {
+    /// 関数の名前と型、他のファイルから見えないか
+    functions: Vec<(String, VariableType, bool)>,
+}
+
+impl Visitor for Prototypes {
+    fn visit_program(&mut self, program: &Program) {
+        for function in program.codes() {
+            let parameters = function
+                .arguments()
+                .iter()
+                .map(|arg| arg.var_type().clone())
+                .collect();
+            let var_type = VariableType::Function {
+                return_type: Box::new(function.return_type().clone()),
+                parameters,
+                variadic: function.va_area().is_some(),
+                prototype: function.prototype(),
+            };
+            self.add(function.name(), &var_type, function.internal());
+        }
+        visit::walk_program(self, program);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let ExpressionKind::FunctionName { name, var_type } = expression.kind() {
+            self.add(name, var_type, false);
+        }
+        visit::walk_expression(self, expression);
+    }
+}
+
+impl Prototypes {
+    fn add(&mut self, name: &str, var_type: &VariableType, internal: bool) {
+        if self.functions.iter().all(|(n, _, _)| n != name) {
+            self.functions
+                .push((name.to_string(), var_type.clone(), internal));
+        }
+    }
+}
+
+/// 文と式を出力する。
+struct Printer<'a> {
+    /// va_startの引数の名前を知るための、文や式を含む関数。関数の外の式ではNone。
+    function: Option<&'a Function>,
+}
+
+impl Printer<'_> {
+    fn statement(&self, f: &mut Formatter, statement: &Statement, depth: usize) -> Result {
+        let indent = INDENT.repeat(depth);
+        match statement {
+            Statement::Block { statements } => {
+                writeln!(f, "{}{{", indent)?;
+                for statement in statements {
+                    self.statement(f, statement, depth + 1)?;
+                }
+                writeln!(f, "{}}}", indent)
+            }
+            Statement::If {
+                condition,
+                true_statement,
+            } => {
+                writeln!(f, "{}if ({})", indent, self.expression(condition))?;
+                self.body(f, true_statement, depth)
+            }
+            Statement::IfElse {
+                condition,
+                true_statement,
+                false_statement,
+            } => {
+                writeln!(f, "{}if ({})", indent, self.expression(condition))?;
+                self.body(f, true_statement, depth)?;
+                writeln!(f, "{}else", indent)?;
+                self.body(f, false_statement, depth)
+            }
+            Statement::While {
+                condition,
+                statement,
+            } => {
+                writeln!(f, "{}while ({})", indent, self.expression(condition))?;
+                self.body(f, statement, depth)
+            }
+            Statement::For {
+                init,
+                condition,
+                iteration,
+                statement,
+            } => {
+                writeln!(
+                    f,
+                    "{}for ({} {}; {})",
+                    indent,
+                    self.simple_statement(init),
+                    self.expression(condition),
+                    self.expression(iteration)
+                )?;
+                self.body(f, statement, depth)
+            }
+            statement => writeln!(f, "{}{}", indent, self.simple_statement(statement)),
+        }
+    }
+
+    /// if文やwhile文の中の文。ブロックでなければ1段深く字下げする。
+    fn body(&self, f: &mut Formatter, statement: &Statement, depth: usize) -> Result {
+        match statement {
+            Statement::Block { .. } => self.statement(f, statement, depth),
+            _ => self.statement(f, statement, depth + 1),
+        }
+    }
+
+    /// 1行に収まる、セミコロンで終わる文
+    fn simple_statement(&self, statement: &Statement) -> String {
+        match statement {
+            Statement::Return(expr) => format!("return {};", self.expression(expr)),
+            Statement::Expression(expr) => format!("{};", self.expression(expr)),
+            Statement::Declaration(declarations) => {
+                let storage = match declarations.first().map(|d| d.variable.storage()) {
+                    Some(Storage::Static { .. }) => "static ",
+                    Some(Storage::Extern) => "extern ",
+                    _ => "",
+                };
+                // 基本の型は最初の変数の前にだけ書く
+                let base = declarations
+                    .first()
+                    .map(|d| split(d.variable.var_type(), String::new()).0)
+                    .unwrap_or_default();
+                let declarators = declarations
+                    .iter()
+                    .map(|d| self.init_declarator(d))
+                    .collect::<Vec<_>>();
+                format!("{}{} {};", storage, base, declarators.join(", "))
+            }
+            _ => unreachable!("複数行の文です。"),
+        }
+    }
+
+    fn declaration(&self, d: &Declaration) -> String {
+        let (base, _) = split(d.variable.var_type(), String::new());
+        format!("{} {}", base, self.init_declarator(d))
+    }
+
+    /// 基本の型を除いた、宣言子と初期化子
+    fn init_declarator(&self, d: &Declaration) -> String {
+        let (_, declarator) = split(d.variable.var_type(), d.variable.name().clone());
+        match &d.initializer {
+            Some(init) => format!("{} = {}", declarator, self.initializer(init)),
+            None => declarator,
+        }
+    }
+
+    fn initializer(&self, init: &Initializer) -> String {
+        match init {
+            Initializer::Expression(expr) => self.expression(expr),
+            Initializer::List(list) => {
+                let list = list
+                    .iter()
+                    .map(|init| self.initializer(init))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", list.join(", "))
+            }
+        }
+    }
+
+    fn expression(&self, expr: &Expression) -> String {
+        match expr.kind() {
+            ExpressionKind::Num(n) => n.to_string(),
+            ExpressionKind::StringLiteral(bytes) => string_literal(bytes),
+            ExpressionKind::Undeclared(name) => name.clone(),
+            ExpressionKind::Variable(var) => var.name().clone(),
+            ExpressionKind::FunctionName { name, .. } => name.clone(),
+            ExpressionKind::FunctionCall { callee, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.expression(arg))
+                    .collect::<Vec<_>>();
+                format!("{}({})", self.expression(callee), args.join(", "))
+            }
+            ExpressionKind::BinaryOperator { kind, left, right } => {
+                let operator = match kind {
+                    BinaryKind::Add => "+",
+                    BinaryKind::Subtract => "-",
+                    BinaryKind::Multiply => "*",
+                    BinaryKind::Divide => "/",
+                    BinaryKind::Compare(CompareKind::Equal) => "==",
+                    BinaryKind::Compare(CompareKind::NotEqual) => "!=",
+                    BinaryKind::Compare(CompareKind::LessThan) => "<",
+                    BinaryKind::Compare(CompareKind::LessEqual) => "<=",
+                    BinaryKind::Assign => "=",
+                };
+                format!(
+                    "({} {} {})",
+                    self.expression(left),
+                    operator,
+                    self.expression(right)
+                )
+            }
+            ExpressionKind::UnaryOperator { kind, expression } => {
+                let operator = match kind {
+                    UnaryKind::Address => "&",
+                    UnaryKind::Deref => "*",
+                };
+                format!("({}{})", operator, self.expression(expression))
+            }
+            ExpressionKind::Cast {
+                var_type,
+                expression,
+            } => format!(
+                "(({}){})",
+                declare(var_type, String::new()),
+                self.expression(expression)
+            ),
+            ExpressionKind::SizeOf(expression) => {
+                format!("(sizeof {})", self.expression(expression))
+            }
+            ExpressionKind::VaStart { ap, named, .. } => {
+                let last = match (self.function, named.checked_sub(1)) {
+                    (Some(function), Some(i)) => function.arguments[i].name().clone(),
+                    _ => String::new(),
+                };
+                format!("va_start({}, {})", self.expression(ap), last)
+            }
+            ExpressionKind::VaArg { ap, var_type } => format!(
+                "va_arg({}, {})",
+                self.expression(ap),
+                declare(var_type, String::new())
+            ),
+            ExpressionKind::VaEnd(ap) => format!("va_end({})", self.expression(ap)),
+        }
+    }
+}
+
+/// 型var_typeの変数declaratorを宣言する、基本の型と宣言子をつなげる。
+///
+/// declaratorが空の場合は、キャストなどに使う変数名のない型名になる。
+fn declare(var_type: &VariableType, declarator: String) -> String {
+    match split(var_type, declarator) {
+        (base, declarator) if declarator.is_empty() => base,
+        (base, declarator) => format!("{} {}", base, declarator),
+    }
+}
+
+/// 型var_typeの変数declaratorを宣言する、基本の型と宣言子を組み立てる。
+fn split(var_type: &VariableType, declarator: String) -> (String, String) {
+    match var_type {
+        VariableType::Qualified(t, q) => match &**t {
+            VariableType::Pointer(pointee) => {
+                let pointer = format!("*{}{}", qualifiers(*q), declarator);
+                split(pointee, wrap(pointee, pointer.trim_end().to_string()))
+            }
+            t => (format!("{}{}", qualifiers(*q), base_name(t)), declarator),
+        },
+        VariableType::Pointer(pointee) => split(pointee, wrap(pointee, format!("*{}", declarator))),
+        VariableType::Array(element, size) => split(element, format!("{}[{}]", declarator, size)),
+        VariableType::Function {
+            return_type,
+            parameters,
+            variadic,
+            prototype,
+        } => {
+            let mut parameters = parameters
+                .iter()
+                .map(|t| declare(t, String::new()))
+                .collect::<Vec<_>>();
+            if *variadic {
+                parameters.push("...".to_string());
+            }
+            // 引数のない関数は、引数を宣言していない関数と区別する
+            if *prototype && parameters.is_empty() {
+                parameters.push("void".to_string());
+            }
+            split(
+                return_type,
+                format!("{}({})", declarator, parameters.join(", ")),
+            )
+        }
+        base => (base_name(base).to_string(), declarator),
+    }
+}
+
+/// 配列や関数へのポインタの宣言子は、括弧で囲む。
+fn wrap(pointee: &VariableType, pointer: String) -> String {
+    match pointee.unqualified() {
+        VariableType::Array(_, _) | VariableType::Function { .. } => format!("({})", pointer),
+        _ => pointer,
+    }
+}
+
+fn base_name(var_type: &VariableType) -> &'static str {
+    match var_type {
+        VariableType::Void => "void",
+        VariableType::Char => "char",
+        VariableType::Short => "short",
+        VariableType::Int => "int",
+        VariableType::Long => "long",
+        VariableType::UnsignedChar => "unsigned char",
+        VariableType::UnsignedShort => "unsigned short",
+        VariableType::UnsignedInt => "unsigned int",
+        VariableType::UnsignedLong => "unsigned long",
+        _ => unreachable!("基本の型ではありません。"),
+    }
+}
+
+/// 後ろに空白をつけた型修飾子
+fn qualifiers(q: super::Qualifiers) -> &'static str {
+    match (q.is_const, q.is_volatile) {
+        (true, true) => "const volatile ",
+        (true, false) => "const ",
+        (false, true) => "volatile ",
+        (false, false) => "",
+    }
+}
+
+/// エスケープが必要な文字をエスケープした文字列リテラル
+/// 文字列リテラルとして出力する。
+///
+/// 制御文字とUTF-8として読めないバイトは、後に数字が続いても読み違えないように3桁の8進数にする。
+fn string_literal(bytes: &[u8]) -> String {
+    let mut literal = String::from("\"");
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => literal.push_str("\\\""),
+                '\\' => literal.push_str("\\\\"),
+                '\n' => literal.push_str("\\n"),
+                '\t' => literal.push_str("\\t"),
+                '\r' => literal.push_str("\\r"),
+                c if c.is_ascii_control() => literal.push_str(&format!("\\{:03o}", c as u8)),
+                c => literal.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            literal.push_str(&format!("\\{:03o}", b));
+        }
+    }
+    literal.push('"');
+    literal
+}
@@ -1,17 +1,31 @@
 mod debug;
+mod display;
+pub mod visit;
 
 pub struct Program {
     codes: Vec<Function>,
+    /// ファイルスコープで宣言された変数
+    globals: Vec<Declaration>,
 }
 
 pub struct Function {
     name: String,
+    return_type: VariableType,
+    /// staticな関数のとき、他のファイルから見えない
+    internal: bool,
+    /// inline指定された関数。最適化で呼び出しを展開しやすくする
+    inline: bool,
+    /// 引数の型を宣言しているか。f(void)はtrueで、f()はfalse
+    prototype: bool,
     arguments: Vec<Variable>,
     statements: Vec<Statement>,
+    stack_size: i64,
+    /// 可変長引数の関数のとき、レジスタの引数を保存する領域の位置
+    va_area: Option<i64>,
 }
 
 pub enum Statement {
-    Declaration(Variable),
+    Declaration(Vec<Declaration>),
     Expression(Expression),
     Return(Expression),
     If {
@@ -28,7 +42,7 @@ pub enum Statement {
         statement: Box<Statement>,
     },
     For {
-        init: Expression,
+        init: Box<Statement>,
         condition: Expression,
         iteration: Expression,
         statement: Box<Statement>,
@@ -38,11 +52,24 @@ pub enum Statement {
     },
 }
 
-pub enum Expression {
+/// 式と、意味解析で決まる式の型
+pub struct Expression {
+    kind: ExpressionKind,
+    var_type: Option<VariableType>,
+    /// 式が書かれたソースコード上の位置
+    position: usize,
+}
+
+pub enum ExpressionKind {
     FunctionCall {
-        name: String,
+        callee: Box<Expression>,
         args: Vec<Expression>,
     },
+    /// 関数名。値として使われると関数へのポインタになる。
+    FunctionName {
+        name: String,
+        var_type: VariableType,
+    },
     BinaryOperator {
         kind: BinaryKind,
         left: Box<Expression>,
@@ -52,15 +79,62 @@ pub enum Expression {
         kind: UnaryKind,
         expression: Box<Expression>,
     },
+    Cast {
+        var_type: VariableType,
+        expression: Box<Expression>,
+    },
     Num(i64),
-    LocalVariable(Variable),
+    StringLiteral(Vec<u8>),
+    Variable(Variable),
+    /// va_start(ap, last)
+    ///
+    /// namedは名前のついた引数の個数、areaはレジスタの引数を保存した領域の位置。
+    VaStart {
+        ap: Box<Expression>,
+        named: usize,
+        area: i64,
+    },
+    /// va_arg(ap, type)
+    VaArg {
+        ap: Box<Expression>,
+        var_type: VariableType,
+    },
+    /// va_end(ap)
+    VaEnd(Box<Expression>),
+    /// sizeof 式。意味解析で数に置き換える。
+    SizeOf(Box<Expression>),
+    /// 宣言されていない名前。構文解析でエラーを報告済みで、意味解析では型をつけない。
+    Undeclared(String),
+}
+
+/// 宣言される変数と初期化子
+pub struct Declaration {
+    variable: Variable,
+    initializer: Option<Initializer>,
+}
+
+/// 変数の初期化子
+pub enum Initializer {
+    Expression(Expression),
+    List(Vec<Initializer>),
 }
 
 #[derive(Clone)]
 pub struct Variable {
     var_type: VariableType,
     name: String,
-    offset: i64,
+    storage: Storage,
+}
+
+/// 変数が置かれる場所
+#[derive(Clone)]
+pub enum Storage {
+    /// rbpからのオフセットの位置に置かれるローカル変数
+    Local(i64),
+    /// このファイルで定義される静的な変数。internalのときは他のファイルから見えない。
+    Static { symbol: String, internal: bool },
+    /// 他のファイルで定義される変数
+    Extern,
 }
 
 pub enum UnaryKind {
@@ -85,15 +159,52 @@ pub enum CompareKind {
 }
 
 pub enum VariableType {
+    Void,
+    Char,
+    Short,
     Int,
+    Long,
+    UnsignedChar,
+    UnsignedShort,
+    UnsignedInt,
+    UnsignedLong,
     Pointer(Box<VariableType>),
     Array(Box<VariableType>, i64),
+    Function {
+        return_type: Box<VariableType>,
+        parameters: Vec<VariableType>,
+        variadic: bool,
+        /// 引数の型を宣言しているか。f()のように宣言していない場合は、呼び出しの引数を確かめない
+        prototype: bool,
+    },
+    /// const・volatileで修飾された型。配列の修飾子は要素の型につける。
+    Qualified(Box<VariableType>, Qualifiers),
+}
+
+/// 型修飾子
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Qualifiers {
+    pub is_const: bool,
+    /// volatileな領域の読み書きは、最適化で取り除いたりまとめたりしてはいけない
+    pub is_volatile: bool,
 }
 
 impl Program {
     pub fn codes(&self) -> &Vec<Function> {
         &self.codes
     }
+
+    pub fn globals(&self) -> &Vec<Declaration> {
+        &self.globals
+    }
+
+    pub fn codes_mut(&mut self) -> &mut Vec<Function> {
+        &mut self.codes
+    }
+
+    pub fn globals_mut(&mut self) -> &mut Vec<Declaration> {
+        &mut self.globals
+    }
 }
 
 impl Function {
@@ -101,6 +212,26 @@ impl Function {
         &self.name
     }
 
+    pub fn internal(&self) -> bool {
+        self.internal
+    }
+
+    pub fn inline(&self) -> bool {
+        self.inline
+    }
+
+    pub fn set_inline(&mut self, inline: bool) {
+        self.inline = inline;
+    }
+
+    pub fn prototype(&self) -> bool {
+        self.prototype
+    }
+
+    pub fn set_prototype(&mut self, prototype: bool) {
+        self.prototype = prototype;
+    }
+
     pub fn arguments(&self) -> &Vec<Variable> {
         &self.arguments
     }
@@ -108,33 +239,73 @@ impl Function {
     pub fn statements(&self) -> &Vec<Statement> {
         &self.statements
     }
+
+    pub fn return_type(&self) -> &VariableType {
+        &self.return_type
+    }
+
+    pub fn arguments_mut(&mut self) -> &mut Vec<Variable> {
+        &mut self.arguments
+    }
+
+    pub fn statements_mut(&mut self) -> &mut Vec<Statement> {
+        &mut self.statements
+    }
+
+    pub fn stack_size(&self) -> i64 {
+        self.stack_size
+    }
+
+    pub fn va_area(&self) -> Option<i64> {
+        self.va_area
+    }
+}
+
+impl Declaration {
+    pub fn variable(&self) -> &Variable {
+        &self.variable
+    }
+
+    pub fn initializer(&self) -> Option<&Initializer> {
+        self.initializer.as_ref()
+    }
+
+    pub fn variable_mut(&mut self) -> &mut Variable {
+        &mut self.variable
+    }
+
+    pub fn initializer_mut(&mut self) -> Option<&mut Initializer> {
+        self.initializer.as_mut()
+    }
 }
 
 impl Expression {
-    pub fn kind(&self) -> Result<VariableType, &'static str> {
-        use Expression::{BinaryOperator, FunctionCall, Num, UnaryOperator};
-        use VariableType::{Int, Pointer};
-        match self {
-            Num(_) | FunctionCall { .. } => Ok(Int),
-            Expression::LocalVariable(Variable { var_type, .. }) => Ok(var_type.clone()),
-
-            BinaryOperator { kind, left, right } => match kind {
-                BinaryKind::Assign => left.kind(),
-                BinaryKind::Compare(_) => Ok(Int),
-                _ => match left.kind()? {
-                    Int => right.kind(),
-                    k => Ok(k),
-                },
-            },
+    pub fn kind(&self) -> &ExpressionKind {
+        &self.kind
+    }
 
-            UnaryOperator { kind, expression } => match kind {
-                UnaryKind::Address => Ok(Pointer(Box::new(expression.kind()?))),
-                UnaryKind::Deref => match expression.kind()? {
-                    VariableType::Pointer(t) | VariableType::Array(t, _) => Ok(*t),
-                    VariableType::Int => Err("無効な参照です。"),
-                },
-            },
-        }
+    pub fn kind_mut(&mut self) -> &mut ExpressionKind {
+        &mut self.kind
+    }
+
+    /// 意味解析で決まった式の型
+    pub fn var_type(&self) -> &VariableType {
+        self.var_type
+            .as_ref()
+            .expect("意味解析されていない式です。")
+    }
+
+    /// 式の型。意味解析の前はNone。
+    pub fn checked_type(&self) -> Option<&VariableType> {
+        self.var_type.as_ref()
+    }
+
+    pub fn set_var_type(&mut self, var_type: VariableType) {
+        self.var_type = Some(var_type);
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
     }
 }
 
@@ -143,29 +314,137 @@ impl Variable {
         &self.var_type
     }
 
-    pub fn offset(&self) -> i64 {
-        self.offset
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
     }
 }
 
 impl VariableType {
+    /// 型の大きさ。大きさがi64に収まらない配列は、宣言するときにエラーにしている
     pub fn size(&self) -> i64 {
-        use VariableType::{Array, Int, Pointer};
+        self.checked_size()
+            .expect("型の大きさがi64に収まりません。")
+    }
+
+    /// 型の大きさ。i64に収まらない場合はNone
+    pub fn checked_size(&self) -> Option<i64> {
+        use VariableType::{
+            Array, Char, Function, Int, Long, Pointer, Qualified, Short, UnsignedChar, UnsignedInt,
+            UnsignedLong, UnsignedShort, Void,
+        };
+        match self {
+            Void | Char | UnsignedChar | Function { .. } => Some(1),
+            Short | UnsignedShort => Some(2),
+            Int | UnsignedInt => Some(4),
+            Long | UnsignedLong | Pointer(_) => Some(8),
+            Array(ref_type, size) => ref_type.checked_size()?.checked_mul(*size),
+            Qualified(t, _) => t.checked_size(),
+        }
+    }
+
+    /// 変数をスタックに置くときのアライメント
+    pub fn align(&self) -> i64 {
         match self {
-            Int => 4,
-            Pointer(_) => 8,
-            Array(ref_type, size) => ref_type.size() * size,
+            VariableType::Array(ref_type, _) | VariableType::Qualified(ref_type, _) => {
+                ref_type.align()
+            }
+            t => t.size(),
         }
     }
+
+    /// 型を修飾子で修飾する。配列の場合は要素の型を修飾する。
+    pub fn qualify(self, qualifiers: Qualifiers) -> VariableType {
+        if qualifiers == Qualifiers::default() {
+            return self;
+        }
+        match self {
+            VariableType::Array(t, size) => {
+                VariableType::Array(Box::new(t.qualify(qualifiers)), size)
+            }
+            VariableType::Qualified(t, q) => VariableType::Qualified(
+                t,
+                Qualifiers {
+                    is_const: q.is_const || qualifiers.is_const,
+                    is_volatile: q.is_volatile || qualifiers.is_volatile,
+                },
+            ),
+            t => VariableType::Qualified(Box::new(t), qualifiers),
+        }
+    }
+
+    /// 修飾子を取り除いた型
+    pub fn unqualified(&self) -> &VariableType {
+        match self {
+            VariableType::Qualified(t, _) => t,
+            t => t,
+        }
+    }
+
+    /// 型の一番外側の修飾子
+    pub fn qualifiers(&self) -> Qualifiers {
+        match self {
+            VariableType::Qualified(_, q) => *q,
+            _ => Qualifiers::default(),
+        }
+    }
+
+    pub fn is_const(&self) -> bool {
+        self.qualifiers().is_const
+    }
+
+    pub fn is_volatile(&self) -> bool {
+        self.qualifiers().is_volatile
+    }
+
+    /// 符号なし整数型か確かめる。
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self.unqualified(),
+            VariableType::UnsignedChar
+                | VariableType::UnsignedShort
+                | VariableType::UnsignedInt
+                | VariableType::UnsignedLong
+        )
+    }
 }
 
 impl PartialEq for VariableType {
     fn eq(&self, other: &Self) -> bool {
-        use VariableType::{Array, Int, Pointer};
+        use VariableType::{
+            Array, Char, Function, Int, Long, Pointer, Qualified, Short, UnsignedChar, UnsignedInt,
+            UnsignedLong, UnsignedShort, Void,
+        };
         match (self, other) {
+            (Void, Void) => true,
+            (Char, Char) => true,
+            (Short, Short) => true,
             (Int, Int) => true,
+            (Long, Long) => true,
+            (UnsignedChar, UnsignedChar) => true,
+            (UnsignedShort, UnsignedShort) => true,
+            (UnsignedInt, UnsignedInt) => true,
+            (UnsignedLong, UnsignedLong) => true,
             (Pointer(ty), Pointer(pe)) => ty == pe,
             (Array(ty, s), Array(pe, o)) => ty == pe && s == o,
+            (
+                Function {
+                    return_type: r1,
+                    parameters: p1,
+                    variadic: v1,
+                    prototype: s1,
+                },
+                Function {
+                    return_type: r2,
+                    parameters: p2,
+                    variadic: v2,
+                    prototype: s2,
+                },
+            ) => r1 == r2 && p1 == p2 && v1 == v2 && s1 == s2,
+            (Qualified(ty, q), Qualified(pe, r)) => ty == pe && q == r,
             (_, _) => false,
         }
     }
@@ -173,33 +452,64 @@ impl PartialEq for VariableType {
 
 impl Clone for VariableType {
     fn clone(&self) -> Self {
-        use VariableType::{Array, Int, Pointer};
+        use VariableType::{
+            Array, Char, Function, Int, Long, Pointer, Qualified, Short, UnsignedChar, UnsignedInt,
+            UnsignedLong, UnsignedShort, Void,
+        };
         match self {
+            Void => Void,
+            Char => Char,
+            Short => Short,
             Int => Int,
+            Long => Long,
+            UnsignedChar => UnsignedChar,
+            UnsignedShort => UnsignedShort,
+            UnsignedInt => UnsignedInt,
+            UnsignedLong => UnsignedLong,
             Pointer(b) => Pointer(Box::new(*b.clone())),
             Array(t, s) => Array(Box::new(*t.clone()), *s),
+            Function {
+                return_type,
+                parameters,
+                variadic,
+                prototype,
+            } => Function {
+                return_type: return_type.clone(),
+                parameters: parameters.clone(),
+                variadic: *variadic,
+                prototype: *prototype,
+            },
+            Qualified(t, q) => Qualified(t.clone(), *q),
         }
     }
 }
 
-pub fn new_program(codes: Vec<Function>) -> Program {
-    Program { codes }
+pub fn new_program(codes: Vec<Function>, globals: Vec<Declaration>) -> Program {
+    Program { codes, globals }
 }
 
-pub fn new_binary(kind: BinaryKind, left: Expression, right: Expression) -> Expression {
-    Expression::BinaryOperator {
+pub fn new_expression(kind: ExpressionKind, position: usize) -> Expression {
+    Expression {
         kind,
-        left: Box::new(left),
-        right: Box::new(right),
+        var_type: None,
+        position,
     }
 }
 
+pub fn new_binary(kind: BinaryKind, left: Expression, right: Expression) -> Expression {
+    let position = left.position;
+    new_expression(
+        ExpressionKind::BinaryOperator {
+            kind,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        position,
+    )
+}
+
 pub fn new_compare(kind: CompareKind, left: Expression, right: Expression) -> Expression {
-    Expression::BinaryOperator {
-        kind: BinaryKind::Compare(kind),
-        left: Box::new(left),
-        right: Box::new(right),
-    }
+    new_binary(BinaryKind::Compare(kind), left, right)
 }
 
 pub fn new_return(expression: Expression) -> Statement {
@@ -233,13 +543,13 @@ pub fn new_while(condition: Expression, statement: Statement) -> Statement {
 }
 
 pub fn new_for(
-    init: Expression,
+    init: Statement,
     condition: Expression,
     iteration: Expression,
     statement: Statement,
 ) -> Statement {
     Statement::For {
-        init,
+        init: Box::new(init),
         condition,
         iteration,
         statement: Box::new(statement),
@@ -252,27 +562,68 @@ pub fn new_block(statements: Vec<Statement>) -> Statement {
 
 pub fn new_function(
     name: String,
+    return_type: VariableType,
+    internal: bool,
     arguments: Vec<Variable>,
     statements: Vec<Statement>,
+    stack_size: i64,
+    va_area: Option<i64>,
 ) -> Function {
     Function {
         name,
+        return_type,
+        internal,
+        inline: false,
+        prototype: true,
         arguments,
         statements,
+        stack_size,
+        va_area,
     }
 }
 
-pub fn new_unary(kind: UnaryKind, expression: Expression) -> Expression {
-    Expression::UnaryOperator {
-        kind,
-        expression: Box::new(expression),
-    }
+pub fn new_unary(kind: UnaryKind, expression: Expression, position: usize) -> Expression {
+    new_expression(
+        ExpressionKind::UnaryOperator {
+            kind,
+            expression: Box::new(expression),
+        },
+        position,
+    )
 }
 
-pub fn new_variable(var_type: VariableType, name: String, offset: i64) -> Variable {
+pub fn new_call(callee: Expression, args: Vec<Expression>) -> Expression {
+    let position = callee.position;
+    new_expression(
+        ExpressionKind::FunctionCall {
+            callee: Box::new(callee),
+            args,
+        },
+        position,
+    )
+}
+
+pub fn new_cast(var_type: VariableType, expression: Expression, position: usize) -> Expression {
+    new_expression(
+        ExpressionKind::Cast {
+            var_type,
+            expression: Box::new(expression),
+        },
+        position,
+    )
+}
+
+pub fn new_variable(var_type: VariableType, name: String, storage: Storage) -> Variable {
     Variable {
         var_type,
         name,
-        offset,
+        storage,
+    }
+}
+
+pub fn new_declaration(variable: Variable, initializer: Option<Initializer>) -> Declaration {
+    Declaration {
+        variable,
+        initializer,
     }
 }
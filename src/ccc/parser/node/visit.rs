@@ -0,0 +1,305 @@
+//! 構文木をたどるトレイト
+//!
+//! `Visitor`は構文木を読むだけの処理に、`VisitorMut`は構文木を書き換える処理に使う。
+//! 各メソッドの既定の実装は、同じ名前の`walk_`関数で子のノードをたどる。
+//! メソッドを上書きした場合は、子のノードもたどるなら`walk_`関数を呼び出す。
+
+use super::{
+    Declaration, Expression, ExpressionKind, Function, Initializer, Program, Statement, Variable,
+};
+
+/// 構文木を読むだけの処理
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        walk_function(self, function);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_declaration(&mut self, declaration: &Declaration) {
+        walk_declaration(self, declaration);
+    }
+
+    fn visit_initializer(&mut self, initializer: &Initializer) {
+        walk_initializer(self, initializer);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+
+    /// 変数の宣言と、式の中の変数の参照で呼ばれる。
+    fn visit_variable(&mut self, _variable: &Variable) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for global in program.globals() {
+        visitor.visit_declaration(global);
+    }
+    for function in program.codes() {
+        visitor.visit_function(function);
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, function: &Function) {
+    for argument in function.arguments() {
+        visitor.visit_variable(argument);
+    }
+    for statement in function.statements() {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::Declaration(declarations) => {
+            for declaration in declarations {
+                visitor.visit_declaration(declaration);
+            }
+        }
+        Statement::Expression(expression) | Statement::Return(expression) => {
+            visitor.visit_expression(expression);
+        }
+        Statement::If {
+            condition,
+            true_statement,
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_statement(true_statement);
+        }
+        Statement::IfElse {
+            condition,
+            true_statement,
+            false_statement,
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_statement(true_statement);
+            visitor.visit_statement(false_statement);
+        }
+        Statement::While {
+            condition,
+            statement,
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_statement(statement);
+        }
+        Statement::For {
+            init,
+            condition,
+            iteration,
+            statement,
+        } => {
+            visitor.visit_statement(init);
+            visitor.visit_expression(condition);
+            visitor.visit_expression(iteration);
+            visitor.visit_statement(statement);
+        }
+        Statement::Block { statements } => {
+            for statement in statements {
+                visitor.visit_statement(statement);
+            }
+        }
+    }
+}
+
+pub fn walk_declaration<V: Visitor + ?Sized>(visitor: &mut V, declaration: &Declaration) {
+    visitor.visit_variable(declaration.variable());
+    if let Some(initializer) = declaration.initializer() {
+        visitor.visit_initializer(initializer);
+    }
+}
+
+pub fn walk_initializer<V: Visitor + ?Sized>(visitor: &mut V, initializer: &Initializer) {
+    match initializer {
+        Initializer::Expression(expression) => visitor.visit_expression(expression),
+        Initializer::List(list) => {
+            for initializer in list {
+                visitor.visit_initializer(initializer);
+            }
+        }
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression.kind() {
+        ExpressionKind::FunctionCall { callee, args } => {
+            visitor.visit_expression(callee);
+            for arg in args {
+                visitor.visit_expression(arg);
+            }
+        }
+        ExpressionKind::BinaryOperator { left, right, .. } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        ExpressionKind::UnaryOperator { expression, .. }
+        | ExpressionKind::Cast { expression, .. }
+        | ExpressionKind::SizeOf(expression)
+        | ExpressionKind::VaStart { ap: expression, .. }
+        | ExpressionKind::VaArg { ap: expression, .. }
+        | ExpressionKind::VaEnd(expression) => visitor.visit_expression(expression),
+        ExpressionKind::Variable(variable) => visitor.visit_variable(variable),
+        ExpressionKind::FunctionName { .. }
+        | ExpressionKind::Num(_)
+        | ExpressionKind::StringLiteral(_)
+        | ExpressionKind::Undeclared(_) => {}
+    }
+}
+
+/// 構文木を書き換える処理
+///
+/// 式を別の式に置き換える場合は、`visit_expression_mut`で`*expression`に代入する。
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        walk_function_mut(self, function);
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_declaration_mut(&mut self, declaration: &mut Declaration) {
+        walk_declaration_mut(self, declaration);
+    }
+
+    fn visit_initializer_mut(&mut self, initializer: &mut Initializer) {
+        walk_initializer_mut(self, initializer);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression);
+    }
+
+    /// 変数の宣言と、式の中の変数の参照で呼ばれる。
+    fn visit_variable_mut(&mut self, _variable: &mut Variable) {}
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for global in program.globals_mut() {
+        visitor.visit_declaration_mut(global);
+    }
+    for function in program.codes_mut() {
+        visitor.visit_function_mut(function);
+    }
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(visitor: &mut V, function: &mut Function) {
+    for argument in function.arguments_mut() {
+        visitor.visit_variable_mut(argument);
+    }
+    for statement in function.statements_mut() {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+    match statement {
+        Statement::Declaration(declarations) => {
+            for declaration in declarations {
+                visitor.visit_declaration_mut(declaration);
+            }
+        }
+        Statement::Expression(expression) | Statement::Return(expression) => {
+            visitor.visit_expression_mut(expression);
+        }
+        Statement::If {
+            condition,
+            true_statement,
+        } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_statement_mut(true_statement);
+        }
+        Statement::IfElse {
+            condition,
+            true_statement,
+            false_statement,
+        } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_statement_mut(true_statement);
+            visitor.visit_statement_mut(false_statement);
+        }
+        Statement::While {
+            condition,
+            statement,
+        } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_statement_mut(statement);
+        }
+        Statement::For {
+            init,
+            condition,
+            iteration,
+            statement,
+        } => {
+            visitor.visit_statement_mut(init);
+            visitor.visit_expression_mut(condition);
+            visitor.visit_expression_mut(iteration);
+            visitor.visit_statement_mut(statement);
+        }
+        Statement::Block { statements } => {
+            for statement in statements {
+                visitor.visit_statement_mut(statement);
+            }
+        }
+    }
+}
+
+pub fn walk_declaration_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    declaration: &mut Declaration,
+) {
+    visitor.visit_variable_mut(declaration.variable_mut());
+    if let Some(initializer) = declaration.initializer_mut() {
+        visitor.visit_initializer_mut(initializer);
+    }
+}
+
+pub fn walk_initializer_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    initializer: &mut Initializer,
+) {
+    match initializer {
+        Initializer::Expression(expression) => visitor.visit_expression_mut(expression),
+        Initializer::List(list) => {
+            for initializer in list {
+                visitor.visit_initializer_mut(initializer);
+            }
+        }
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut Expression) {
+    match expression.kind_mut() {
+        ExpressionKind::FunctionCall { callee, args } => {
+            visitor.visit_expression_mut(callee);
+            for arg in args {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        ExpressionKind::BinaryOperator { left, right, .. } => {
+            visitor.visit_expression_mut(left);
+            visitor.visit_expression_mut(right);
+        }
+        ExpressionKind::UnaryOperator { expression, .. }
+        | ExpressionKind::Cast { expression, .. }
+        | ExpressionKind::SizeOf(expression)
+        | ExpressionKind::VaStart { ap: expression, .. }
+        | ExpressionKind::VaArg { ap: expression, .. }
+        | ExpressionKind::VaEnd(expression) => visitor.visit_expression_mut(expression),
+        ExpressionKind::Variable(variable) => visitor.visit_variable_mut(variable),
+        ExpressionKind::FunctionName { .. }
+        | ExpressionKind::Num(_)
+        | ExpressionKind::StringLiteral(_)
+        | ExpressionKind::Undeclared(_) => {}
+    }
+}
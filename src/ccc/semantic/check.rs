@@ -0,0 +1,618 @@
+use crate::ccc::{
+    error::CompileError,
+    parser::node::{
+        self, BinaryKind, Declaration, Expression, ExpressionKind, Function, Initializer, Program,
+        Statement, Storage, UnaryKind, VariableType,
+    },
+    semantic::constant,
+};
+
+/// 構文木のすべての式に型をつけ、暗黙の型変換をキャストとして書き加える。
+///
+/// 型が正しくない式を見つけても最後まで調べ、見つかったエラーをすべて返す。
+pub fn program(program: &mut Program, code: &str) -> Result<(), Vec<CompileError>> {
+    let mut checker = Checker {
+        code,
+        errors: Vec::new(),
+        return_type: VariableType::Int,
+    };
+    for global in program.globals_mut() {
+        checker.declaration(global);
+    }
+    for function in program.codes_mut() {
+        checker.function(function);
+    }
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+/// 配列の大きさのような整数定数式に型をつけて、その値を計算する。
+pub fn integer_constant(expr: &mut Expression, code: &str) -> Result<i64, CompileError> {
+    let mut checker = Checker {
+        code,
+        errors: Vec::new(),
+        return_type: VariableType::Int,
+    };
+    let t = checker.expression(expr);
+    if let Some(e) = checker.errors.into_iter().next() {
+        return Err(e);
+    }
+    match t {
+        Some(t) if is_integer(&t) => {
+            constant::eval_integer(expr).map_err(|e| CompileError::new(e, expr.position(), code))
+        }
+        _ => Err(CompileError::new(
+            "整数定数式ではありません。",
+            expr.position(),
+            code,
+        )),
+    }
+}
+
+/// 式の型。エラーがあった場合はNoneで、そのエラーは報告済み。
+type Checked = Option<VariableType>;
+
+/// 意味解析の途中の状態
+struct Checker<'a> {
+    code: &'a str,
+    errors: Vec<CompileError>,
+    /// 調べている関数の戻り値の型
+    return_type: VariableType,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, message: &str, position: usize) -> Checked {
+        self.errors
+            .push(CompileError::new(message, position, self.code));
+        None
+    }
+
+    fn function(&mut self, function: &mut Function) {
+        self.return_type = function.return_type().clone();
+        for statement in function.statements_mut() {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::Declaration(declarations) => {
+                for declaration in declarations {
+                    self.declaration(declaration);
+                }
+            }
+            Statement::Expression(expr) => {
+                self.expression(expr);
+            }
+            Statement::Return(expr) => {
+                let return_type = self.return_type.clone();
+                if is_void(&return_type) {
+                    self.error("void型の関数では値を返せません。", expr.position());
+                } else if self.expression(expr).is_some() {
+                    self.convert(expr, &return_type);
+                }
+            }
+            Statement::If {
+                condition,
+                true_statement,
+            } => {
+                self.condition(condition);
+                self.statement(true_statement);
+            }
+            Statement::IfElse {
+                condition,
+                true_statement,
+                false_statement,
+            } => {
+                self.condition(condition);
+                self.statement(true_statement);
+                self.statement(false_statement);
+            }
+            Statement::While {
+                condition,
+                statement,
+            } => {
+                self.condition(condition);
+                self.statement(statement);
+            }
+            Statement::For {
+                init,
+                condition,
+                iteration,
+                statement,
+            } => {
+                self.statement(init);
+                self.condition(condition);
+                self.expression(iteration);
+                self.statement(statement);
+            }
+            Statement::Block { statements } => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+        }
+    }
+
+    /// 条件式を調べる。条件は数かポインタでなければならない。
+    fn condition(&mut self, condition: &mut Expression) {
+        if let Some(t) = self.expression(condition) {
+            if !is_scalar(&t) {
+                self.error("条件が数やポインタではありません。", condition.position());
+            }
+        }
+    }
+
+    fn declaration(&mut self, declaration: &mut Declaration) {
+        let var = declaration.variable().clone();
+        let is_static = !matches!(var.storage(), Storage::Local(_));
+        if let Some(init) = declaration.initializer_mut() {
+            self.initializer(var.var_type(), init, is_static);
+        }
+    }
+
+    /// 初期化子を型var_typeの領域に合わせる。静的な領域の初期化子は定数でなければならない。
+    fn initializer(&mut self, var_type: &VariableType, init: &mut Initializer, is_static: bool) {
+        match (var_type.unqualified(), init) {
+            (VariableType::Array(t, _), Initializer::List(list)) => {
+                for init in list {
+                    self.initializer(t, init, is_static);
+                }
+            }
+            (_, Initializer::List(list)) => {
+                for init in list {
+                    self.initializer(var_type, init, is_static);
+                }
+            }
+            (_, Initializer::Expression(expr)) => {
+                if self.expression(expr).is_none() || self.convert(expr, var_type).is_none() {
+                    return;
+                }
+                if is_static {
+                    if let Err(e) = constant::eval_static(expr) {
+                        self.error(e, expr.position());
+                    }
+                }
+            }
+        }
+    }
+
+    /// 式とその中の式に型をつけて、式の型を返す。
+    fn expression(&mut self, expr: &mut Expression) -> Checked {
+        use ExpressionKind::{
+            BinaryOperator, Cast, FunctionCall, FunctionName, Num, SizeOf, StringLiteral,
+            UnaryOperator, Undeclared, VaArg, VaEnd, VaStart, Variable,
+        };
+        let position = expr.position();
+
+        // sizeof 式は、式の型の大きさに置き換える
+        if let SizeOf(expression) = expr.kind_mut() {
+            let size = self.expression(expression)?.size();
+            *expr = node::new_expression(Num(size), position);
+            expr.set_var_type(VariableType::UnsignedLong);
+            return Some(VariableType::UnsignedLong);
+        }
+
+        let t = match expr.kind_mut() {
+            // int型で表せない数はlong型にする
+            Num(n) if *n as i32 as i64 != *n => VariableType::Long,
+            Num(_) => VariableType::Int,
+            StringLiteral(bytes) => {
+                VariableType::Array(Box::new(VariableType::Char), bytes.len() as i64 + 1)
+            }
+            Variable(var) => var.var_type().clone(),
+            FunctionName { var_type, .. } => var_type.clone(),
+            Cast {
+                var_type,
+                expression,
+            } => {
+                let from = self.expression(expression)?;
+                if matches!(
+                    var_type.unqualified(),
+                    VariableType::Array(_, _) | VariableType::Function { .. }
+                ) {
+                    return self.error("配列や関数にはキャストできません。", position);
+                }
+                if is_void(&from) && !is_void(var_type) {
+                    return self.error("void型の値は使えません。", position);
+                }
+                var_type.unqualified().clone()
+            }
+            FunctionCall { callee, args } => self.call(callee, args, position)?,
+            BinaryOperator { kind, left, right } => self.binary(kind, left, right, position)?,
+            UnaryOperator { kind, expression } => self.unary(kind, expression, position)?,
+            // エラーは構文解析で報告済み
+            Undeclared(_) => return None,
+            VaStart { ap, .. } | VaEnd(ap) => {
+                self.expression(ap)?;
+                VariableType::Void
+            }
+            VaArg { ap, var_type } => {
+                self.expression(ap)?;
+                var_type.clone()
+            }
+            SizeOf(_) => unreachable!(),
+        };
+        expr.set_var_type(t.clone());
+
+        // ポインタどうしの差は、指す先の型の大きさで割って要素の数にする
+        if let BinaryOperator {
+            kind: BinaryKind::Subtract,
+            left,
+            ..
+        } = expr.kind()
+        {
+            if let Some(element) = pointee(left.var_type()) {
+                if t == VariableType::Long {
+                    divide(expr, element.size());
+                }
+            }
+        }
+        Some(t)
+    }
+
+    fn call(
+        &mut self,
+        callee: &mut Expression,
+        args: &mut [Expression],
+        position: usize,
+    ) -> Checked {
+        let callee = self.expression(callee);
+        let mut checked = true;
+        for arg in args.iter_mut() {
+            checked &= self.expression(arg).is_some();
+        }
+        let callee = callee?;
+        if !checked {
+            return None;
+        }
+
+        let (return_type, parameters, variadic, prototype) = match function_type(&callee) {
+            Some(VariableType::Function {
+                return_type,
+                parameters,
+                variadic,
+                prototype,
+            }) => (
+                return_type.clone(),
+                parameters.clone(),
+                *variadic,
+                *prototype,
+            ),
+            _ => return self.error("関数ではない値は呼び出せません。", position),
+        };
+        // 引数の型を宣言していない関数は、引数を確かめない
+        if prototype
+            && (args.len() < parameters.len() || args.len() > parameters.len() && !variadic)
+        {
+            return self.error("引数の個数が正しくありません。", position);
+        }
+        for (parameter, arg) in parameters.iter().zip(args.iter_mut()) {
+            self.convert(arg, parameter)?;
+        }
+        Some(return_type.unqualified().clone())
+    }
+
+    fn binary(
+        &mut self,
+        kind: &BinaryKind,
+        left: &mut Expression,
+        right: &mut Expression,
+        position: usize,
+    ) -> Checked {
+        let l = self.expression(left);
+        let r = self.expression(right);
+        let (l, r) = (l?, r?);
+        match kind {
+            BinaryKind::Assign => {
+                if !is_lvalue(left) {
+                    return self.error("左辺値ではない値には代入できません。", position);
+                }
+                if matches!(
+                    l.unqualified(),
+                    VariableType::Array(_, _) | VariableType::Function { .. }
+                ) {
+                    return self.error("配列や関数には代入できません。", position);
+                }
+                if l.is_const() {
+                    return self.error("constな変数には代入できません。", position);
+                }
+                self.convert(right, &l)
+            }
+            BinaryKind::Compare(_) => match (pointee(&l), pointee(&r)) {
+                (Some(a), Some(b)) if !is_compatible(&a, &b) && !is_void(&a) && !is_void(&b) => {
+                    self.error("型の違うポインタは比較できません。", position)
+                }
+                // ポインタと比べられる整数は、ヌルポインタになる0だけ
+                (Some(_), None) if !is_null_pointer(right) => {
+                    self.error("ポインタと整数は比較できません。", position)
+                }
+                (None, Some(_)) if !is_null_pointer(left) => {
+                    self.error("ポインタと整数は比較できません。", position)
+                }
+                // ヌルポインタは比べる相手のポインタの型にする
+                (Some(_), None) => {
+                    cast(right, l.unqualified().clone());
+                    Some(VariableType::Int)
+                }
+                (None, Some(_)) => {
+                    cast(left, r.unqualified().clone());
+                    Some(VariableType::Int)
+                }
+                (Some(_), Some(_)) => Some(VariableType::Int),
+                _ if is_integer(&l) && is_integer(&r) => {
+                    self.usual_conversion(left, right);
+                    Some(VariableType::Int)
+                }
+                _ => self.error("比較できない値です。", position),
+            },
+            // ポインタに整数を足し引きするときは、整数に指す先の型の大きさを掛ける
+            BinaryKind::Add => match (pointee(&l), pointee(&r)) {
+                (Some(_), Some(_)) => self.error("ポインタどうしは足せません。", position),
+                (Some(t), None) if is_integer(&r) => {
+                    scale(right, t.size());
+                    Some(VariableType::Pointer(Box::new(t)))
+                }
+                (None, Some(t)) if is_integer(&l) => {
+                    scale(left, t.size());
+                    Some(VariableType::Pointer(Box::new(t)))
+                }
+                (None, None) if is_integer(&l) && is_integer(&r) => {
+                    Some(self.usual_conversion(left, right))
+                }
+                _ => self.error("数ではない値は足せません。", position),
+            },
+            BinaryKind::Subtract => match (pointee(&l), pointee(&r)) {
+                // ポインタどうしの差は、式を包んで要素の数にする
+                (Some(a), Some(b)) if is_compatible(&a, &b) => Some(VariableType::Long),
+                (Some(_), Some(_)) => self.error("型の違うポインタどうしは引けません。", position),
+                (Some(t), None) if is_integer(&r) => {
+                    scale(right, t.size());
+                    Some(VariableType::Pointer(Box::new(t)))
+                }
+                (None, Some(_)) => self.error("整数からポインタは引けません。", position),
+                (None, None) if is_integer(&l) && is_integer(&r) => {
+                    Some(self.usual_conversion(left, right))
+                }
+                _ => self.error("数ではない値は引けません。", position),
+            },
+            BinaryKind::Multiply | BinaryKind::Divide => {
+                if !is_integer(&l) || !is_integer(&r) {
+                    return self.error("数ではない値は掛けたり割ったりできません。", position);
+                }
+                Some(self.usual_conversion(left, right))
+            }
+        }
+    }
+
+    fn unary(&mut self, kind: &UnaryKind, expression: &mut Expression, position: usize) -> Checked {
+        let t = self.expression(expression)?;
+        match kind {
+            UnaryKind::Address => {
+                if !is_lvalue(expression)
+                    && !matches!(expression.kind(), ExpressionKind::FunctionName { .. })
+                {
+                    return self.error("アドレスを取れない値です。", position);
+                }
+                Some(VariableType::Pointer(Box::new(t)))
+            }
+            UnaryKind::Deref => match t.unqualified() {
+                VariableType::Pointer(t) | VariableType::Array(t, _) => Some(*t.clone()),
+                // 関数は関数へのポインタに変換されてから参照される
+                t @ VariableType::Function { .. } => Some(t.clone()),
+                _ => self.error("ポインタではない値は参照できません。", position),
+            },
+        }
+    }
+
+    /// 整数どうしの計算の両辺を、通常の算術型変換で同じ型にそろえて、その型を返す。
+    fn usual_conversion(&mut self, left: &mut Expression, right: &mut Expression) -> VariableType {
+        let t = arithmetic(left.var_type(), right.var_type());
+        if *left.var_type().unqualified() != t {
+            cast(left, t.clone());
+        }
+        if *right.var_type().unqualified() != t {
+            cast(right, t.clone());
+        }
+        t
+    }
+
+    /// 型のついた式を、代入や引数のように型toの値として受け取る。
+    ///
+    /// 代入できない型の組み合わせや、ポインタの指す先の修飾子が失われる場合はエラーにし、
+    /// 型が違う場合はキャストを書き加える。
+    fn convert(&mut self, expr: &mut Expression, to: &VariableType) -> Checked {
+        let to = to.unqualified();
+        let from = expr.var_type().unqualified().clone();
+        if is_void(to) {
+            return Some(VariableType::Void);
+        }
+        if is_void(&from) {
+            return self.error("void型の値は使えません。", expr.position());
+        }
+        // 代入できる型の組み合わせは、C11 6.5.16.1に従う
+        match (to, pointee(&from)) {
+            (VariableType::Pointer(t), Some(f)) => {
+                let void = |a: &VariableType, b: &VariableType| {
+                    is_void(a) && !matches!(b.unqualified(), VariableType::Function { .. })
+                };
+                if !is_compatible(t, &f) && !void(t, &f) && !void(&f, t) {
+                    return self.error(
+                        "互換性のない型を指すポインタには変換できません。",
+                        expr.position(),
+                    );
+                }
+                if f.is_const() && !t.is_const() {
+                    return self.error(
+                        "constを指すポインタを、constでないポインタとして渡せません。",
+                        expr.position(),
+                    );
+                }
+                if f.is_volatile() && !t.is_volatile() {
+                    return self.error(
+                        "volatileを指すポインタを、volatileでないポインタとして渡せません。",
+                        expr.position(),
+                    );
+                }
+            }
+            // ポインタに変換できる整数は、ヌルポインタになる0だけ
+            (VariableType::Pointer(_), None) if is_null_pointer(expr) => cast(expr, to.clone()),
+            (VariableType::Pointer(_), None) => {
+                return self.error("整数をポインタに変換できません。", expr.position());
+            }
+            (_, Some(_)) if is_integer(to) => {
+                return self.error("ポインタを整数に変換できません。", expr.position());
+            }
+            _ if !is_integer(to) => {
+                return self.error("この型には変換できません。", expr.position());
+            }
+            _ if from != *to => cast(expr, to.clone()),
+            _ => {}
+        }
+        Some(to.clone())
+    }
+}
+
+/// 式をtoへのキャストで包む。
+fn cast(expr: &mut Expression, to: VariableType) {
+    let position = expr.position();
+    let inner = std::mem::replace(expr, node::new_expression(ExpressionKind::Num(0), position));
+    *expr = node::new_cast(to.clone(), inner, position);
+    expr.set_var_type(to);
+}
+
+/// ポインタに足し引きする整数をlong型にして、指す先の型の大きさを掛ける。
+fn scale(expr: &mut Expression, size: i64) {
+    if *expr.var_type().unqualified() != VariableType::Long {
+        cast(expr, VariableType::Long);
+    }
+    if size == 1 {
+        return;
+    }
+    let position = expr.position();
+    let inner = std::mem::replace(expr, node::new_expression(ExpressionKind::Num(0), position));
+    let mut rate = node::new_expression(ExpressionKind::Num(size), position);
+    rate.set_var_type(VariableType::Int);
+    *expr = node::new_binary(BinaryKind::Multiply, inner, rate);
+    expr.set_var_type(VariableType::Long);
+}
+
+/// ポインタどうしの差を、要素の大きさで割る。
+fn divide(expr: &mut Expression, size: i64) {
+    if size == 1 {
+        return;
+    }
+    let position = expr.position();
+    let inner = std::mem::replace(expr, node::new_expression(ExpressionKind::Num(0), position));
+    let mut rate = node::new_expression(ExpressionKind::Num(size), position);
+    rate.set_var_type(VariableType::Int);
+    *expr = node::new_binary(BinaryKind::Divide, inner, rate);
+    expr.set_var_type(VariableType::Long);
+}
+
+/// 関数か関数へのポインタの型から、関数の型を取り出す。
+fn function_type(var_type: &VariableType) -> Option<&VariableType> {
+    match var_type.unqualified() {
+        t @ VariableType::Function { .. } => Some(t),
+        VariableType::Pointer(t) => match t.unqualified() {
+            t @ VariableType::Function { .. } => Some(t),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// ポインタや配列の指す先の型。関数は関数へのポインタとして扱う。
+fn pointee(var_type: &VariableType) -> Option<VariableType> {
+    match var_type.unqualified() {
+        VariableType::Pointer(t) | VariableType::Array(t, _) => Some(*t.clone()),
+        t @ VariableType::Function { .. } => Some(t.clone()),
+        _ => None,
+    }
+}
+
+/// 修飾子を除いて同じ型か確かめる。
+fn is_compatible(left: &VariableType, right: &VariableType) -> bool {
+    left.unqualified() == right.unqualified()
+}
+
+/// ヌルポインタになる整数の0か確かめる。
+fn is_null_pointer(expr: &Expression) -> bool {
+    matches!(expr.kind(), ExpressionKind::Num(0))
+}
+
+/// 整数拡張。int型より小さい整数型は、int型で計算する。
+fn promote(var_type: &VariableType) -> VariableType {
+    match var_type.unqualified() {
+        VariableType::Char
+        | VariableType::Short
+        | VariableType::UnsignedChar
+        | VariableType::UnsignedShort => VariableType::Int,
+        t => t.clone(),
+    }
+}
+
+/// 通常の算術型変換。整数どうしの計算の結果の型を決める。
+fn arithmetic(left: &VariableType, right: &VariableType) -> VariableType {
+    let (left, right) = (promote(left), promote(right));
+    if left == right {
+        return left;
+    }
+    // 符号が同じなら大きい方の型にする
+    if left.is_unsigned() == right.is_unsigned() {
+        return if left.size() >= right.size() {
+            left
+        } else {
+            right
+        };
+    }
+    let (unsigned, signed) = if left.is_unsigned() {
+        (left, right)
+    } else {
+        (right, left)
+    };
+    // 符号ありの型が符号なしの型のすべての値を表せる場合だけ、符号ありの型にする
+    if signed.size() > unsigned.size() {
+        signed
+    } else {
+        unsigned
+    }
+}
+
+fn is_void(var_type: &VariableType) -> bool {
+    *var_type.unqualified() == VariableType::Void
+}
+
+fn is_integer(var_type: &VariableType) -> bool {
+    matches!(
+        var_type.unqualified(),
+        VariableType::Char
+            | VariableType::Short
+            | VariableType::Int
+            | VariableType::Long
+            | VariableType::UnsignedChar
+            | VariableType::UnsignedShort
+            | VariableType::UnsignedInt
+            | VariableType::UnsignedLong
+    )
+}
+
+/// 条件や比較に使える、数かポインタとして扱える型か確かめる。
+fn is_scalar(var_type: &VariableType) -> bool {
+    !is_void(var_type)
+}
+
+/// 代入やアドレスの取得ができる式か確かめる。
+fn is_lvalue(expr: &Expression) -> bool {
+    matches!(
+        expr.kind(),
+        ExpressionKind::Variable(_)
+            | ExpressionKind::UnaryOperator {
+                kind: UnaryKind::Deref,
+                ..
+            }
+    )
+}
@@ -0,0 +1,170 @@
+use crate::ccc::parser::node::{
+    BinaryKind, CompareKind, Expression, ExpressionKind, Storage, UnaryKind, Variable, VariableType,
+};
+
+/// 静的な変数の初期値になる、コンパイル時に決まる値
+pub enum StaticValue {
+    Number(i64),
+    /// シンボルのアドレスとそこからのオフセット
+    Address(Symbol, i64),
+}
+
+/// アドレスが静的に決まる領域
+pub enum Symbol {
+    /// 変数や関数の名前
+    Name(String),
+    /// 文字列リテラルの内容
+    Literal(Vec<u8>),
+}
+
+/// 型のついた整数定数式を計算する。
+///
+/// 値はその式の型で表せる範囲に収まるように保つ。符号ありの計算が型の範囲を超える場合と、
+/// 0で割る場合はエラーにする。
+pub fn eval_integer(expr: &Expression) -> Result<i64, &'static str> {
+    match expr.kind() {
+        ExpressionKind::Num(n) => Ok(*n),
+        ExpressionKind::Cast {
+            var_type,
+            expression,
+        } if is_integer(var_type) && is_integer(expression.var_type()) => {
+            Ok(truncate(eval_integer(expression)?, var_type))
+        }
+        ExpressionKind::BinaryOperator { kind, left, right } => {
+            let l = eval_integer(left)?;
+            let r = eval_integer(right)?;
+            let t = expr.var_type();
+            // 比較や割り算の符号は、そろえられた両辺の型で決まる
+            let unsigned = left.var_type().is_unsigned();
+            match kind {
+                BinaryKind::Add => fit(l.checked_add(r), l.wrapping_add(r), t),
+                BinaryKind::Subtract => fit(l.checked_sub(r), l.wrapping_sub(r), t),
+                BinaryKind::Multiply => fit(l.checked_mul(r), l.wrapping_mul(r), t),
+                BinaryKind::Divide if r == 0 => Err("0で割っています。"),
+                BinaryKind::Divide if unsigned => Ok(truncate((l as u64 / r as u64) as i64, t)),
+                BinaryKind::Divide => fit(l.checked_div(r), l.wrapping_div(r), t),
+                BinaryKind::Compare(cmp) if unsigned => Ok(match cmp {
+                    CompareKind::Equal => l == r,
+                    CompareKind::NotEqual => l != r,
+                    CompareKind::LessThan => (l as u64) < r as u64,
+                    CompareKind::LessEqual => l as u64 <= r as u64,
+                } as i64),
+                BinaryKind::Compare(cmp) => Ok(match cmp {
+                    CompareKind::Equal => l == r,
+                    CompareKind::NotEqual => l != r,
+                    CompareKind::LessThan => l < r,
+                    CompareKind::LessEqual => l <= r,
+                } as i64),
+                BinaryKind::Assign => Err("整数定数式に代入は書けません。"),
+            }
+        }
+        _ => Err("整数定数式ではありません。"),
+    }
+}
+
+/// 計算の結果を型tの値にする。符号ありの型で表せない場合はオーバーフローのエラーにする。
+fn fit(checked: Option<i64>, wrapped: i64, t: &VariableType) -> Result<i64, &'static str> {
+    if t.is_unsigned() {
+        return Ok(truncate(wrapped, t));
+    }
+    match checked {
+        Some(n) if truncate(n, t) == n => Ok(n),
+        _ => Err("定数式の計算がオーバーフローしました。"),
+    }
+}
+
+/// 値を型tの大きさに切り詰めて、符号に合わせて拡張する。
+fn truncate(n: i64, t: &VariableType) -> i64 {
+    match t.unqualified() {
+        VariableType::Char => n as i8 as i64,
+        VariableType::Short => n as i16 as i64,
+        VariableType::Int => n as i32 as i64,
+        VariableType::UnsignedChar => n as u8 as i64,
+        VariableType::UnsignedShort => n as u16 as i64,
+        VariableType::UnsignedInt => n as u32 as i64,
+        _ => n,
+    }
+}
+
+/// 整数型か確かめる。
+pub fn is_integer(var_type: &VariableType) -> bool {
+    matches!(
+        var_type.unqualified(),
+        VariableType::Char
+            | VariableType::Short
+            | VariableType::Int
+            | VariableType::Long
+            | VariableType::UnsignedChar
+            | VariableType::UnsignedShort
+            | VariableType::UnsignedInt
+            | VariableType::UnsignedLong
+    )
+}
+
+/// 型のついた初期化子の式を、コンパイル時に決まる値として計算する。
+///
+/// 整数の値は整数定数式として計算し、アドレスはシンボルとオフセットとして計算する。
+pub fn eval_static(expr: &Expression) -> Result<StaticValue, &'static str> {
+    use StaticValue::{Address, Number};
+    match expr.kind() {
+        // ポインタと整数の間のキャストは、値をそのまま使う。アドレスは8バイトの型にしか入らない
+        ExpressionKind::Cast {
+            var_type,
+            expression,
+        } if !is_integer(var_type) || !is_integer(expression.var_type()) => {
+            match eval_static(expression)? {
+                Number(n) if is_integer(var_type) => Ok(Number(truncate(n, var_type))),
+                Address(..) if is_integer(var_type) && var_type.checked_size() != Some(8) => {
+                    Err("アドレスは8バイトの整数型かポインタにしか変換できません。")
+                }
+                value => Ok(value),
+            }
+        }
+        _ if is_integer(expr.var_type()) => eval_integer(expr).map(Number),
+        ExpressionKind::StringLiteral(bytes) => Ok(Address(Symbol::Literal(bytes.clone()), 0)),
+        ExpressionKind::FunctionName { name, .. } => Ok(Address(Symbol::Name(name.clone()), 0)),
+        // 配列は先頭のアドレスになる
+        ExpressionKind::Variable(var)
+            if matches!(var.var_type().unqualified(), VariableType::Array(_, _)) =>
+        {
+            static_address(var)
+        }
+        ExpressionKind::UnaryOperator {
+            kind: UnaryKind::Address,
+            expression,
+        } => match expression.kind() {
+            ExpressionKind::Variable(var) => static_address(var),
+            ExpressionKind::UnaryOperator {
+                kind: UnaryKind::Deref,
+                expression,
+            } => eval_static(expression),
+            ExpressionKind::FunctionName { .. } => eval_static(expression),
+            _ => Err("アドレスが定数ではありません。"),
+        },
+        // 配列を参照した結果の配列は、先頭のアドレスのまま
+        ExpressionKind::UnaryOperator {
+            kind: UnaryKind::Deref,
+            expression,
+        } if matches!(expr.var_type().unqualified(), VariableType::Array(_, _)) => {
+            eval_static(expression)
+        }
+        ExpressionKind::BinaryOperator { kind, left, right } => {
+            match (kind, eval_static(left)?, eval_static(right)?) {
+                (BinaryKind::Add, Address(s, o), Number(n))
+                | (BinaryKind::Add, Number(n), Address(s, o)) => Ok(Address(s, o + n)),
+                (BinaryKind::Subtract, Address(s, o), Number(n)) => Ok(Address(s, o - n)),
+                _ => Err("初期化子が定数ではありません。"),
+            }
+        }
+        _ => Err("初期化子が定数ではありません。"),
+    }
+}
+
+/// 静的な領域に置かれる変数のアドレス
+fn static_address(var: &Variable) -> Result<StaticValue, &'static str> {
+    match var.storage() {
+        Storage::Static { symbol, .. } => Ok(StaticValue::Address(Symbol::Name(symbol.clone()), 0)),
+        Storage::Extern => Ok(StaticValue::Address(Symbol::Name(var.name().clone()), 0)),
+        Storage::Local(_) => Err("ローカル変数のアドレスは定数ではありません。"),
+    }
+}
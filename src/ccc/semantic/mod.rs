@@ -0,0 +1,4 @@
+mod check;
+pub mod constant;
+
+pub use check::{integer_constant, program as analyze};
@@ -1,15 +1,396 @@
-mod ccc;
+use ccc9::ccc;
+use ccc9::ccc::parser::node::Program;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 標準エラー出力に書き込む。eprint!と違い、パイプが閉じられていて書き込めなくてもpanicしない
+macro_rules! message {
+    ($($arg:tt)*) => {{
+        let _ = writeln!(std::io::stderr(), $($arg)*);
+    }};
+}
+
+const USAGE: &str = "\
+使い方: ccc9 [オプション] ファイル...
+
+ファイルが - の場合は標準入力から読み込む。
+.c以外のファイル(.s、.o、.a)は、そのままアセンブラやリンカに渡す。
+
+オプション:
+  -o <ファイル>  出力するファイル。- の場合は標準出力
+  -S             アセンブリを出力する
+  -c             オブジェクトファイルを出力する
+  --emit=c       構文解析した結果をC言語のソースコードとして出力する
+  --emit=ir      中間表現を出力する。-O1以上では最適化した後の中間表現
+  -l<ライブラリ>、-L<ディレクトリ>、-static
+                 リンカに渡す
+  -O<n>          最適化のレベル。-O1以上で中間表現での最適化、関数の展開、
+                 末尾呼び出しの最適化、レジスタの割り当て、のぞき穴最適化をする。
+                 -O は -O1 と同じ。-Og はスタックマシンとして計算する
+                 命令に、のぞき穴最適化だけをする
+  -g、-std=<規格>、-W<警告>
+                 他のコンパイラと同じように書けるが、無視する
+  --help         この使い方を出力する
+  --version      バージョンを出力する
+
+終了ステータス:
+  0  成功
+  1  ソースコードにエラーがあった
+  2  コマンドライン引数が正しくない
+  3  ファイルの読み書きや、アセンブラ・リンカの実行に失敗した
+  4  コンパイラの内部エラー";
+
+/// どこまで処理して出力するか。後の段階ほど大きい。
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    /// 構文解析した結果のC言語のソースコード
+    Source,
+    /// 中間表現
+    Ir,
+    /// アセンブリ
+    Assembly,
+    /// オブジェクトファイル
+    Object,
+    /// 実行ファイル
+    Executable,
+}
+
+/// 処理を続けられなくなった理由
+enum Failure {
+    /// コマンドライン引数が正しくない
+    Usage(String),
+    /// ソースコードにエラーがあった。エラーは出力済み
+    Compile,
+    /// ファイルの読み書きや、アセンブラ・リンカの実行に失敗した
+    System(String),
+    /// コンパイラがpanicした。メッセージはpanicのフックで出力済み
+    Internal,
+}
+
+/// コマンドライン引数
+struct Options {
+    stage: Stage,
+    /// 最適化のレベル
+    optimization: u8,
+    /// -Og。スタックマシンとして計算して、のぞき穴最適化だけをする
+    peephole: bool,
+    output: Option<String>,
+    inputs: Vec<String>,
+    /// リンカに渡す引数
+    linker_args: Vec<String>,
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        eprintln!("引数の個数が正しくありません。");
-        return;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // コンパイラのバグでpanicした場合は、内部エラーとして終了する
+    std::panic::set_hook(Box::new(|info| message!("ccc9: 内部エラー: {}", info)));
+    let result = std::panic::catch_unwind(|| match parse_args(&args) {
+        Ok(Some(options)) => run(&options),
+        Ok(None) => Ok(()),
+        Err(failure) => Err(failure),
+    })
+    .unwrap_or(Err(Failure::Internal));
+    if let Err(failure) = result {
+        std::process::exit(failure.exit_code());
+    }
+}
+
+impl Failure {
+    /// メッセージを出力して、終了ステータスを返す。
+    fn exit_code(self) -> i32 {
+        match self {
+            Failure::Compile => 1,
+            Failure::Usage(message) => {
+                message!("ccc9: {}", message);
+                message!("詳しくは ccc9 --help を見てください。");
+                2
+            }
+            Failure::System(message) => {
+                message!("ccc9: {}", message);
+                3
+            }
+            Failure::Internal => 4,
+        }
+    }
+}
+
+/// コマンドライン引数を読む。--helpや--versionを出力した場合はNoneを返す。
+fn parse_args(args: &[String]) -> Result<Option<Options>, Failure> {
+    let mut options = Options {
+        stage: Stage::Executable,
+        optimization: 0,
+        peephole: false,
+        output: None,
+        inputs: Vec::new(),
+        linker_args: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" => {
+                let _ = writeln!(std::io::stdout(), "{}", USAGE);
+                return Ok(None);
+            }
+            "--version" => {
+                let _ = writeln!(std::io::stdout(), "ccc9 {}", env!("CARGO_PKG_VERSION"));
+                return Ok(None);
+            }
+            "-o" => match args.next() {
+                Some(output) => options.output = Some(output.clone()),
+                None => return Err(usage("-o の後に出力するファイルがありません。")),
+            },
+            "-S" => options.stage = options.stage.min(Stage::Assembly),
+            "-c" => options.stage = options.stage.min(Stage::Object),
+            "--emit=c" => options.stage = Stage::Source,
+            "--emit=ir" => options.stage = Stage::Ir,
+            "-static" => options.linker_args.push(arg.clone()),
+            "-" => options.inputs.push(arg.clone()),
+            a if a.starts_with("-l") || a.starts_with("-L") => {
+                options.linker_args.push(arg.clone())
+            }
+            "-O" => {
+                options.optimization = 1;
+                options.peephole = false;
+            }
+            "-Og" => {
+                options.optimization = 0;
+                options.peephole = true;
+            }
+            a if a.starts_with("-O") => {
+                // -Osなどは-O1として扱う
+                options.optimization = a[2..].parse().unwrap_or(1);
+                options.peephole = false;
+            }
+            a if a == "-g" || a.starts_with("-std=") || a.starts_with("-W") => {}
+            a if a.starts_with('-') => return Err(usage(format!("知らないオプションです: {}", a))),
+            _ => options.inputs.push(arg.clone()),
+        }
+    }
+
+    if options.inputs.is_empty() {
+        return Err(usage("入力するファイルがありません。"));
+    }
+    if options.output.is_some() && options.inputs.len() > 1 && options.stage != Stage::Executable {
+        return Err(usage(
+            "複数のファイルを入力するときは、-o を指定できません。",
+        ));
+    }
+    // オブジェクトファイルは標準出力に出力できないので、名前を決めてもらう
+    let stdin = options.inputs.iter().any(|input| input == "-");
+    if stdin && options.output.is_none() && options.stage == Stage::Object {
+        return Err(usage("標準入力から -c するときは、-o を指定してください。"));
+    }
+    Ok(Some(options))
+}
+
+fn usage<S: ToString>(message: S) -> Failure {
+    Failure::Usage(message.to_string())
+}
+
+/// 入力されたファイルを順にコンパイルして、最後にリンクする。
+fn run(options: &Options) -> Result<(), Failure> {
+    // 一時ファイルは、失敗した場合もpanicした場合もTemporariesのdropで削除する
+    build(options, &mut Temporaries::default())
+}
+
+fn build(options: &Options, temporaries: &mut Temporaries) -> Result<(), Failure> {
+    let mut objects = Vec::new();
+    for input in &options.inputs {
+        let extension = Path::new(input).extension().and_then(|e| e.to_str());
+        let output = |extension: &str| match &options.output {
+            Some(output) => PathBuf::from(output),
+            None => output_name(input, extension),
+        };
+        match (extension, options.stage) {
+            // オブジェクトファイルやライブラリは、リンカに渡す
+            (Some("o"), Stage::Executable) | (Some("a"), Stage::Executable) => {
+                objects.push(PathBuf::from(input));
+            }
+            (Some("s"), Stage::Object) => assemble(Path::new(input), &output("o"))?,
+            (Some("s"), Stage::Executable) => {
+                let object = temporaries.create("o")?;
+                assemble(Path::new(input), &object)?;
+                objects.push(object);
+            }
+            // アセンブリやオブジェクトファイルより前の段階では、使わない入力
+            (Some("s"), _) | (Some("o"), _) | (Some("a"), _) => {
+                message!("ccc9: {} は使われません。", input);
+            }
+            (_, Stage::Source) => {
+                let source = read_source(input)?;
+                let c = ccc::format(&source).map_err(|errors| report(input, errors))?;
+                write_output(&output("c"), &c)?;
+            }
+            (_, stage) => {
+                let source = read_source(input)?;
+                let analyze = ccc::Options {
+                    stage: ccc::Stage::Analyze,
+                    optimization: options.optimization,
+                    peephole: options.peephole,
+                };
+                let compiled = ccc::compile_to_string(&source, &analyze)
+                    .map_err(|errors| report(input, errors))?;
+                let program = compiled.program();
+                match stage {
+                    Stage::Ir => write_ir(&output("ir"), program, &analyze)?,
+                    Stage::Assembly => write_assembly(&output("s"), program, &analyze)?,
+                    Stage::Object => {
+                        let asm = temporaries.create("s")?;
+                        write_assembly(&asm, program, &analyze)?;
+                        assemble(&asm, &output("o"))?;
+                    }
+                    _ => {
+                        let asm = temporaries.create("s")?;
+                        write_assembly(&asm, program, &analyze)?;
+                        let object = temporaries.create("o")?;
+                        assemble(&asm, &object)?;
+                        objects.push(object);
+                    }
+                }
+            }
+        }
+    }
+
+    if options.stage == Stage::Executable {
+        let output = options.output.as_deref().unwrap_or("a.out");
+        let mut command = Command::new("cc");
+        command.arg("-o").arg(output).args(&objects);
+        command.args(&options.linker_args);
+        execute(&mut command)?;
     }
+    Ok(())
+}
+
+/// ソースコードを読み込む。- の場合は標準入力から読む。
+fn read_source(input: &str) -> Result<String, Failure> {
+    let mut source = String::new();
+    let result = if input == "-" {
+        std::io::stdin().read_to_string(&mut source)
+    } else {
+        std::fs::File::open(input).and_then(|mut f| f.read_to_string(&mut source))
+    };
+    result.map_err(|e| Failure::System(format!("{} を読み込めません: {}", input, e)))?;
+    Ok(source)
+}
+
+/// ファイルに書き込む。- の場合は標準出力に書く。
+fn write_output(output: &Path, content: &str) -> Result<(), Failure> {
+    write_to(output, |w| w.write_all(content.as_bytes()))
+}
+
+/// 構文木から生成したアセンブリを、ファイルに直接書き込む。
+fn write_assembly(output: &Path, program: &Program, options: &ccc::Options) -> Result<(), Failure> {
+    write_to(output, |w| ccc::write_assembly(program, options, w))
+}
+
+/// 構文木を中間表現にして、ファイルに書き込む。
+fn write_ir(output: &Path, program: &Program, options: &ccc::Options) -> Result<(), Failure> {
+    write_to(output, |w| ccc::write_ir(program, options, w))
+}
+
+fn write_to<F>(output: &Path, write: F) -> Result<(), Failure>
+where
+    F: FnOnce(&mut dyn Write) -> std::io::Result<()>,
+{
+    let result = if output == Path::new("-") {
+        write(&mut std::io::stdout().lock())
+    } else {
+        std::fs::File::create(output).and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            write(&mut writer)?;
+            writer.flush()
+        })
+    };
+    result.map_err(|e| Failure::System(format!("{} に書き込めません: {}", output.display(), e)))
+}
+
+/// 入力ファイルの拡張子を変えた、出力ファイルの名前。標準入力の場合は標準出力に出力する。
+fn output_name(input: &str, extension: &str) -> PathBuf {
+    if input == "-" {
+        return PathBuf::from("-");
+    }
+    let file_name = Path::new(input).file_name().unwrap_or_default();
+    Path::new(file_name).with_extension(extension)
+}
+
+/// コンパイルエラーを出力する。
+fn report(input: &str, errors: ccc::error::CompileErrors) -> Failure {
+    for e in errors.iter() {
+        let _ = write!(std::io::stderr(), "{}:{:?}", input, e);
+    }
+    message!("{}: エラーが{}個ありました。", input, errors.count());
+    Failure::Compile
+}
+
+/// システムのアセンブラでアセンブリをオブジェクトファイルにする。
+fn assemble(assembly: &Path, object: &Path) -> Result<(), Failure> {
+    execute(Command::new("as").arg("-o").arg(object).arg(assembly))
+}
+
+fn execute(command: &mut Command) -> Result<(), Failure> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .map_err(|e| Failure::System(format!("{} を実行できません: {}", program, e)))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Failure::System(format!("{} が失敗しました。", program)))
+    }
+}
+
+/// 途中で作る一時ファイル。自分だけが読み書きできる一時ディレクトリに作り、最後にディレクトリごと削除する。
+#[derive(Default)]
+struct Temporaries {
+    /// 最初の一時ファイルを作るときに作るディレクトリ
+    directory: Option<PathBuf>,
+    count: usize,
+}
+
+impl Temporaries {
+    fn create(&mut self, extension: &str) -> Result<PathBuf, Failure> {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => self.directory.insert(private_directory()?),
+        };
+        let path = directory.join(format!("{}.{}", self.count, extension));
+        self.count += 1;
+        Ok(path)
+    }
+}
+
+impl Drop for Temporaries {
+    fn drop(&mut self) {
+        if let Some(directory) = &self.directory {
+            let _ = std::fs::remove_dir_all(directory);
+        }
+    }
+}
 
-    let program = &args[1];
-    match ccc::compile(program) {
-        Ok(()) => eprintln!("Success"),
-        Err(e) => eprintln!("Error {:?}", e),
+/// システムの一時ディレクトリの中に、自分だけが読み書きできるディレクトリを新しく作る。
+///
+/// 既にある名前は、シンボリックリンクでも使わずに別の名前で作り直す。
+fn private_directory() -> Result<PathBuf, Failure> {
+    use std::os::unix::fs::DirBuilderExt;
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+    let mut builder = std::fs::DirBuilder::new();
+    builder.mode(0o700);
+    for n in 0..100 {
+        let name = format!("ccc9-{}-{:x}-{}", std::process::id(), seed, n);
+        let path = std::env::temp_dir().join(name);
+        match builder.create(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                let message = format!("一時ディレクトリ {} を作れません: {}", path.display(), e);
+                return Err(Failure::System(message));
+            }
+        }
     }
+    Err(Failure::System(
+        "一時ディレクトリを作れません。".to_string(),
+    ))
 }
@@ -0,0 +1,11 @@
+//! Rustで書いたC言語のコンパイラ
+//!
+//! 構文木は`ccc::parser::node`にあり、`ccc::parser::node::visit`のトレイトでたどれる。
+//!
+//! ソースコードは`compile_to_string`でコンパイルし、トークン、構文木、アセンブリを受け取れる。
+//! 結果は文字列で返し、標準出力には書き込まない。
+
+pub mod ccc;
+
+pub use ccc::error::{CompileError, CompileErrors};
+pub use ccc::{compile_to_string, write_assembly, write_ir, Options, Output, Stage};
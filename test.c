@@ -7,6 +7,6 @@ int main() {
     p[5] = 15;
     a = 5;
     q = p + a;
-    r = q + p;
+    r = a + p;
     return *q;
 }
\ No newline at end of file
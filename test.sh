@@ -1,21 +1,78 @@
 #!/bin/bash
 
 # プログラム文が期待する戻り値を返すことを確かめる関数。
-# 期待する戻り値でない場合、シェルスクリプトを終了する。
+# 最適化しない場合と、のぞき穴最適化だけをする場合と、最適化する場合のすべてで確かめる。
+# 期待する戻り値でない場合や、コンパイルが標準エラー出力に何か出力した場合、シェルスクリプトを終了する。
 assert() {
     expected="$1"
     input="$2"
 
-    ./target/debug/ccc9 "$input" > tmp.s
-    cc -o tmp tmp.s func.o
-    ./tmp
+    for option in -O0 -Og -O1; do
+        if ! printf '%s\n' "$input" | ./target/debug/ccc9 -S $option - > tmp.s 2> tmp.err || [ -s tmp.err ]; then
+            echo "$input ($option) => compiled without any message expected, but got"
+            cat tmp.err
+            exit 1
+        fi
+        cc -o tmp tmp.s func.o
+        ./tmp
+        actual="$?"
+
+        if [ "$actual" != "$expected" ]; then
+            echo "$input ($option) => $expected expected, but got $actual"
+            exit 1
+        fi
+    done
+    echo "$input => $actual"
+    echo
+}
+
+# プログラム文がコンパイルエラーになることを確かめる関数。
+# アセンブリが出力された場合や、終了ステータスが1でない場合、シェルスクリプトを終了する。
+assert_error() {
+    input="$1"
+
+    printf '%s\n' "$input" | ./target/debug/ccc9 -S - > tmp.s 2> /dev/null
+    status="$?"
+
+    if [ -s tmp.s ] || [ "$status" != 1 ]; then
+        echo "$input => error expected, but compiled"
+        exit 1
+    else
+        echo "$input => error"
+        echo
+    fi
+}
+
+# 構文木をC言語のソースコードとして出力し直しても、同じ構文木になることを確かめる関数。
+# 出力したソースコードをコンパイルして、期待する戻り値を返すことも確かめる。
+assert_roundtrip() {
+    expected="$1"
+    input="$2"
+
+    printf '%s\n' "$input" | ./target/debug/ccc9 --emit=c - > tmp.c
+    ./target/debug/ccc9 --emit=c -o tmp2.c tmp.c
+
+    if ! cmp -s tmp.c tmp2.c; then
+        echo "$input => same source expected, but got"
+        diff tmp.c tmp2.c
+        exit 1
+    fi
+    assert "$expected" "$(cat tmp.c)"
+}
+
+# コマンドを実行して、期待する終了ステータスを返すことを確かめる関数。
+assert_command() {
+    expected="$1"
+    command="$2"
+
+    eval "$command" > /dev/null 2>&1
     actual="$?"
 
     if [ "$actual" = "$expected" ]; then
-        echo "$input => $actual"
+        echo "$command => $actual"
         echo
     else
-        echo "$input => $expected expected, but got $actual"
+        echo "$command => $expected expected, but got $actual"
         exit 1
     fi
 }
@@ -158,4 +215,442 @@ assert 40 "int main() { int a[10]; return sizeof a; }"
 assert 80 "int main() { int *a[10]; return sizeof a; }"
 assert 84 "int main() { int a[21]; return sizeof a; }"
 
+# 初期化子付きの宣言 複数の宣言子
+assert 5 "int main() { int a = 5; return a; }"
+assert 7 "int main() { int a = 3, b = a + 4; return b; }"
+assert 9 "int main() { int a, *b, c[3]; b = &a; *b = 9; return a; }"
+assert 24 "int main() { int a, *b, c[3]; return sizeof a + sizeof b + sizeof c; }"
+assert 3 "int main() { int a[3] = {1, 2, 3}; return *(a + 2); }"
+assert 0 "int main() { int a[5] = {1, 2}; return *(a + 4); }"
+assert 2 "int main() { int a[5] = {1, 2,}; return *(a + 1); }"
+assert 12 "int main() { int a[] = {1, 2, 3}; return sizeof a; }"
+assert 6 "int main() { int a[] = {1, 2, 3}; return *a + *(a + 1) + *(a + 2); }"
+assert 4 "int main() { char s[] = \"abc\"; return sizeof s; }"
+assert 98 "int main() { char s[] = \"abc\"; return *(s + 1); }"
+assert 0 "int main() { char s[] = \"abc\"; return *(s + 3); }"
+assert 3 "int main() { char s[3] = \"abc\"; return sizeof s; }"
+assert 0 "int main() { char s[8] = \"abc\"; return *(s + 7); }"
+assert 10 "int main() { char c = 10; return c; }"
+assert 4 "int main() { int a = {4}; return a; }"
+assert 45 "int main() { int s = 0; for (int i = 0; i < 10; i = i + 1) s = s + i; return s; }"
+assert 20 "int main() { int s = 0; for (int i = 0; i < 5; i = i + 1) s = s + i; for (int i = 0; i < 5; i = i + 1) s = s + i; return s; }"
+assert 3 "int main() { int a = 3; { int a = 5; } return a; }"
+assert 1 "int main() { int a[100] = {1}; int b = 1; return *a * b; }"
+
+# キャスト sizeof(型)
+assert 44 "int main() { return (char)300; }"
+assert 1 "int main() { return (short)65537; }"
+assert 1 "int main() { return (char)257 == 1; }"
+assert 1 "int main() { int a = 200; return (char)a == 0-56; }"
+assert 200 "int main() { int a = 456; return (char)a; }"
+assert 1 "int main() { long a = (long)(0-1); return a < (long)0; }"
+assert 3 "int main() { long a = 3; return (int)a; }"
+assert 5 "int main() { int a = 5; int *p = (int *)&a; return *p; }"
+assert 7 "int main() { int *p; alloc4(&p, 7, 2, 3, 4); char *q = (char *)p; return *(int *)q; }"
+assert 4 "int main() { return sizeof(int); }"
+assert 1 "int main() { return sizeof(char); }"
+assert 2 "int main() { return sizeof(short); }"
+assert 2 "int main() { return sizeof(short int); }"
+assert 8 "int main() { return sizeof(long); }"
+assert 8 "int main() { return sizeof(long long int); }"
+assert 8 "int main() { return sizeof(int *); }"
+assert 8 "int main() { return sizeof(char **); }"
+assert 24 "int main() { return sizeof(int *[3]); }"
+assert 12 "int main() { return sizeof(int[3]); }"
+assert 5 "int main() { return sizeof(char[5]); }"
+assert 4 "int main() { int a; return sizeof(a); }"
+assert 8 "int main() { short a = 3; long b = 5; return a + b; }"
+
+# 7個以上の引数
+assert 36 "int main() { return add8(1, 2, 3, 4, 5, 6, 7, 8); }"
+assert 45 "int main() { return 100 - sub9(100, 1, 2, 3, 4, 5, 6, 7, 8) + 9; }"
+assert 36 "int main() { int a = 1; return add8(a, a + 1, 3, 4, 5, 6, a * 7, 8); }"
+assert 68 "int main() { return hoge(1, 2, 3, 4, 5, 6, 7, 8, 9); } int hoge(int a, int b, int c, int d, int e, int f, int g, int h, int i) { return a + b + c + d + e + f + g * h - i; }"
+assert 1 "int main() { return hoge(1, 2, 3, 4, 5, 6, 7, 8) == 0; } int hoge(int a, int b, int c, int d, int e, int f, int g, int h) { return a - b + c - d + e - f + g - h + 4; }"
+assert 7 "int main() { return hoge(1, 2, 3, 4, 5, 6, 7); } int hoge(int a, int b, int c, int d, int e, int f, int g) { g = g * 1; return g; }"
+assert 1 "int main() { return aligned(); }"
+assert 2 "int main() { return 1 + aligned(); }"
+assert 3 "int main() { return 1 + (1 + aligned()); }"
+assert 8 "int main() { return add8(1, 1, 1, 1, 1, 1, 1, aligned()); }"
+assert 9 "int main() { return 1 + add8(1, 1, 1, 1, 1, 1, aligned(), 1); }"
+assert 8 "int main() { return add8(1, 1, 1, 1, 1, 1, 1, add8(0, 0, 0, 0, 0, 0, 0, aligned())); }"
+assert 2 "int main() { return hoge(1, 2, 3, 4, 5, 6, 7); } int hoge(int a, int b, int c, int d, int e, int f, int g) { return aligned() + aligned(); }"
+
+# 文字列リテラル 可変長引数
+assert 97 "int main() { char *s = \"abc\"; return *s; }"
+assert 4 "int main() { return sizeof \"abc\"; }"
+assert 99 "int main() { return *(\"ab\" \"cd\" + 2); }"
+assert 26 "int main() { char *s = \"日本語\"; /* 日本語のコメント */ return 0 - *s; } // コメント"
+assert 10 "int printf(char *fmt, ...); int main() { printf(\"日本語\\n\"); return sizeof \"日本語\"; }"
+assert 210 "int main() { char *s = \"\\x41\\101\\a\\012\\?\"; return s[0] + s[1] + s[2] + s[3] + s[4]; }"
+assert 6 "int main() { char *s = \"\\'\\\"\\b\\f\\v\\\\\"; return s[0] - 39 + s[1] - 34 + s[2] - 8 + s[3] - 12 + s[4] - 11 + s[5] - 86; }"
+assert 2 "int main() { char *s = \"\\0012\"; return s[1] - 48; }"
+assert_error "int main() { char *s = \"\\q\"; return 0; }"
+assert_error "int main() { char *s = \"\\x\"; return 0; }"
+assert_error "int main() { char *s = \"\\x100\"; return 0; }"
+assert_error "int main() { char *s = \"\\400\"; return 0; }"
+assert_error "int main() { return 0; } /* 閉じていないコメント"
+assert 3 "int printf(char *fmt, ...); int main() { return printf(\"%d\\n\", 42); }"
+assert 10 "int printf(char *, ...); int main() { return printf(\"%d %s %c%c\\n\", 42, \"abc\", 120, 121); }"
+assert 14 "int printf(char *fmt, ...); int main() { return printf(\"%d%d%d%d%d%d%d%d%d%d%d%d%d%d\", 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4); }"
+assert 55 "int sum(int n, ...) { va_list ap; va_start(ap, n); int s = 0; for (int i = 0; i < n; i = i + 1) s = s + va_arg(ap, int); va_end(ap); return s; } int main() { return sum(10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10); }"
+assert 7 "int last(int n, ...) { va_list ap; va_start(ap, n); long v = 0; for (int i = 0; i < n; i = i + 1) v = va_arg(ap, long); va_end(ap); return v; } int main() { return last(7, 1, 2, 3, 4, 5, 6, 7); }"
+assert 21 "int f(int a, int b, int c, int d, int e, int f, int g, ...) { va_list ap; va_start(ap, g); int s = va_arg(ap, int); s = s + va_arg(ap, int); va_end(ap); return s + g; } int main() { return f(1, 2, 3, 4, 5, 6, 7, 6, 8); }"
+assert 24 "int main() { va_list ap; return sizeof ap; }"
+assert 14 "int vprintf(char *fmt, va_list ap); int logf(char *fmt, ...) { va_list ap; va_start(ap, fmt); int n = vprintf(fmt, ap); va_end(ap); return n; } int main() { return logf(\"%d %d %d %d %d %d %d\\n\", 1, 2, 3, 4, 5, 6, 7); }"
+assert 8 "int snprintf(char *buf, long n, char *fmt, ...); int main() { char buf[16]; snprintf(buf, 16, \"%s-%d\", \"ab\", 57); return *(buf + 3) - 45; }"
+assert 5 "int f(void); int main() { return f(); } int f(void) { return 5; }"
+assert 3 "int f(); int f(int x) { return x; } int f(const int y); int main() { return f(3); }"
+assert_error "int f(int x); int f(int x, int y) { return x; } int main() { return 0; }"
+assert_error "int f(int x); long f(int x) { return x; } int main() { return 0; }"
+assert_error "int f(int x, ...); int f(int x) { return x; } int main() { return 0; }"
+assert 2 "int f(void); int f() { return 2; } int main() { return f(); }"
+assert_error "int f(void); int f(int x) { return x; } int main() { return 0; }"
+assert_error "int f(void); int main() { return f(3); }"
+
+# 関数ポインタ
+assert 7 "int add(int a, int b) { return a + b; } int main() { int (*fp)(int, int) = add; return fp(3, 4); }"
+assert 7 "int add(int a, int b) { return a + b; } int main() { int (*fp)(int, int) = &add; return (*fp)(3, 4); }"
+assert 12 "int mul(int a, int b) { return a * b; } int apply(int (*f)(int, int), int x, int y) { return f(x, y); } int main() { return apply(mul, 3, 4); }"
+assert 1 "int add(int a, int b) { return a + b; } int sub(int a, int b) { return a - b; } int main() { int (*table[2])(int, int); table[0] = add; table[1] = sub; return table[1](3, 2); }"
+assert 5 "int add(int a, int b) { return a + b; } int sub(int a, int b) { return a - b; } int main() { int (*table[2])(int, int) = {add, sub}; int i = 0; return table[i](3, 2); }"
+assert 9 "int sq(int x) { return x * x; } int (*get(void))(int) { return sq; } int main() { return get()(3); }"
+assert 32 "int foo(); int main() { int (*fp)() = foo; return fp(); }"
+assert 8 "int main() { int (*fp)(int, int); return sizeof fp; }"
+assert 24 "int main() { return sizeof(int (*[3])(int)); }"
+assert 7 "int add(int a, int b) { return a + b; } int main() { return (*add)(3, 4) + (&add == add) - 1; }"
+assert 21 "int add8(int, int, int, int, int, int, int, int); int main() { int (*fp)(int, int, int, int, int, int, int, int) = add8; return fp(1, 1, 1, 1, 1, 1, 7, 8); }"
+assert 3 "int printf(char *fmt, ...); int main() { int (*p)(char *, ...) = printf; return p(\"%d\\n\", 42); }"
+assert 100 "char f(int x) { return x; } int main() { return f(356); }"
+assert 98 "char *g() { return \"abc\"; } int main() { return g()[1]; }"
+assert 3 "int main() { int a[3] = {1, 2, 3}; return a[2]; }"
+assert 5 "int main() { int a[3]; a[1] = 5; return *(a + 1); }"
+
+# 多次元配列
+assert 48 "int main() { int a[3][4]; return sizeof a; }"
+assert 16 "int main() { int a[3][4]; return sizeof a[0]; }"
+assert 4 "int main() { int a[3][4]; return sizeof a[0][0]; }"
+assert 152 "int main() { int a[2][3][4]; return sizeof a + sizeof a[1] + sizeof a[1][2] + sizeof a[1][2][3] - 12; }"
+assert 11 "int main() { int a[3][4]; a[2][3] = 11; return *(*(a + 2) + 3); }"
+assert 6 "int main() { int a[2][3] = {{1, 2, 3}, {4, 5, 6}}; return a[1][2]; }"
+assert 5 "int main() { int a[2][3] = {1, 2, 3, 4, 5, 6}; return a[1][1]; }"
+assert 0 "int main() { int a[2][3] = {{1}, {4}}; return a[0][2] + a[1][1]; }"
+assert 4 "int main() { int a[2][3] = {{1}, 4}; return a[1][0]; }"
+assert 24 "int main() { int a[][2] = {1, 2, 3, 4, 5}; return sizeof a + a[1][1] - 4; }"
+assert 24 "int main() { int a[][3] = {{1, 2, 3}, {4, 5, 6}}; return sizeof a; }"
+assert 100 "int main() { char s[2][4] = {\"abc\", \"de\"}; return s[1][0]; }"
+assert 0 "int main() { char s[2][4] = {\"abc\", \"de\"}; return s[1][2]; }"
+assert 8 "int main() { char s[][4] = {\"abc\", \"de\"}; return sizeof s; }"
+assert 21 "int main() { int a[3][4]; int s = 0; for (int i = 0; i < 3; i = i + 1) for (int j = 0; j < 4; j = j + 1) a[i][j] = i * 4 + j; for (int i = 0; i < 3; i = i + 1) s = s + a[i][3]; return s; }"
+assert 7 "int main() { int a[3][4]; int (*p)[4] = a; a[1][3] = 7; return p[1][3]; }"
+assert 16 "int main() { int (*p)[4]; return sizeof *p; }"
+assert 8 "int main() { int (*p)[4]; return sizeof p; }"
+assert 9 "int main() { int a[3][4]; int (*p)[4] = a + 1; a[2][0] = 9; return (*(p + 1))[0]; }"
+assert 15 "int sum(int m[][3], int n) { int s = 0; for (int i = 0; i < n; i = i + 1) for (int j = 0; j < 3; j = j + 1) s = s + m[i][j]; return s; } int main() { int m[2][3] = {{1, 2, 3}, {4, 5, 0}}; return sum(m, 2); }"
+assert 14 "int main() { int a[2][2][2] = {{{1, 2}, {3, 4}}, {{5, 6}, {7, 8}}}; return a[1][0][1] + a[1][1][1]; }"
+assert 24 "int mul(int a[2][2], int b[2][2], int c[2][2]) { for (int i = 0; i < 2; i = i + 1) for (int j = 0; j < 2; j = j + 1) { c[i][j] = 0; for (int k = 0; k < 2; k = k + 1) c[i][j] = c[i][j] + a[i][k] * b[k][j]; } return 0; } int main() { int a[2][2] = {{1, 2}, {3, 4}}; int c[2][2]; mul(a, a, c); return c[0][0] + c[1][1] - 5; }"
+
+# グローバル変数 static extern
+assert 0 "int x; int main() { return x; }"
+assert 3 "int x; int main() { x = 3; return x; }"
+assert 7 "int x = 7; int main() { return x; }"
+assert 6 "int a, b = 2; int main() { a = 3; return a * b; }"
+assert 2 "int x; int main() { int x = 2; return x; }"
+assert 4 "int a[4] = {1, 2, 3}; int main() { return a[0] + a[2] + a[3]; }"
+assert 3 "int m[2][2] = {{1, 2}, {3, 4}}; int main() { return m[1][0]; }"
+assert 102 "char s[] = \"abc\"; int main() { return s[1] + sizeof s; }"
+assert 111 "char *p = \"hello\"; int main() { return p[4]; }"
+assert 44 "char c = 300; int main() { return c; }"
+assert 1 "long l = 0-1; int main() { return l < (long)0; }"
+assert 9 "int x = 3; int *p = &x; int main() { *p = 9; return x; }"
+assert 5 "int a[3] = {1, 2, 3}; int *p = &a[1]; int main() { return *p + p[1]; }"
+assert 25 "int sq(int x) { return x * x; } int (*g)(int) = sq; int main() { return g(5); }"
+assert 3 "int count() { static int n = 0; n = n + 1; return n; } int main() { count(); count(); return count(); }"
+assert 13 "int f() { static int n = 5; return n; } int g() { static int n = 8; return n; } int main() { return f() + g(); }"
+assert 16 "static int sq(int x) { return x * x; } int main() { return sq(4); }"
+assert 6 "static int x = 6; int main() { return x; }"
+assert 11 "extern int global_value; int main() { return global_value; }"
+assert 12 "int main() { extern int global_value; return global_value + 1; }"
+assert 21 "extern int y; int main() { return y; } int y = 21;"
+
+# const volatile
+assert 5 "int main() { const int x = 5; return x; }"
+assert 3 "int main() { int const x = 3; return x; }"
+assert 7 "int main() { int a = 7; const int *p = &a; return *p; }"
+assert 9 "int main() { int a = 1; int *const p = &a; *p = 9; return a; }"
+assert 4 "int len(const char *s) { int n = 0; while (s[n] != 0) n = n + 1; return n; } int main() { return len(\"abcd\"); }"
+assert 3 "int main() { const char s[] = \"abc\"; return sizeof s - 1; }"
+assert 6 "int main() { volatile int x = 6; return x; }"
+assert 2 "int main() { const volatile long x = 2; return x; }"
+assert 8 "int main() { return sizeof(const long); }"
+assert 1 "const int g = 1; int main() { return g; }"
+assert 98 "int main() { char *p = \"abc\"; const char *q = p; return q[1]; }"
+assert_error "int main() { const int x = 5; x = 3; return x; }"
+assert_error "int main() { int a; const int *p = &a; *p = 3; return a; }"
+assert_error "int main() { int a; int *const p = &a; p = &a; return 0; }"
+assert_error "int main() { const int a[2] = {1, 2}; a[0] = 3; return 0; }"
+assert_error "int f(int *p) { return *p; } int main() { const int a = 1; return f(&a); }"
+assert_error "int main() { const int a = 1; int *p = &a; return *p; }"
+assert_error "int main() { const char *s = \"abc\"; char *t; t = s; return 0; }"
+assert_error "int main() { volatile int v; int *p = &v; return 0; }"
+
+# 意味解析
+assert 1 "int main() { long l = 0-1; return l < 0; }"
+assert 1 "long f(long x) { return x; } int main() { return f(0-1) == (long)0-1; }"
+assert 2 "int main() { int a[4] = {1, 2, 3, 4}; int *p = a + 3; return *(p + (0-2)); }"
+assert 8 "int main() { int a[3]; return sizeof(a + 1); }"
+assert 12 "int main() { int a[3]; return sizeof a; }"
+assert 44 "char f(char c) { return c; } int main() { return f(300); }"
+assert_error "int main() { int a; return a(); }"
+assert_error "int f(int a, int b) { return a; } int main() { return f(1); }"
+assert_error "void f() { } int main() { return f(); }"
+assert_error "int main() { 1 = 2; return 0; }"
+assert_error "int main() { return &1; }"
+assert_error "int main() { int a; return *a; }"
+assert_error "int main() { int *p; return p * 2; }"
+assert_error "int main() { int a[2]; int b[2]; a = b; return 0; }"
+assert_error "int x; int y = x; int main() { return 0; }"
+assert_error "int main() { int a; static int *p = &a; return 0; }"
+assert_error "int a[2]; char c = a; int main() { return 0; }"
+assert_error "int g; int i = (int)&g; int main() { return 0; }"
+assert_error "int g; char c = (char)&g; int main() { return 0; }"
+assert_error "int g; unsigned int u = (unsigned int)(char *)&g; int main() { return 0; }"
+assert 3 "int g; long l = (long)&g; unsigned long u = (unsigned long)&g; int *p = (int *)(long)&g; int main() { return ((long)&g == l) + (u == l) + (p == &g); }"
+assert 1 "char c = (char)(char *)300; int main() { return c == 44; }"
+assert_error "int main() { int *p = 5; return 0; }"
+assert_error "int main() { int *p; long x = p; return 0; }"
+assert_error "int main() { int a; char *q = &a; return 0; }"
+assert_error "int inc(int x) { return x + 1; } int main() { char (*fp)(int) = inc; return 0; }"
+assert_error "int f(int *p) { return 0; } int main() { return f(1); }"
+assert_error "int main() { void x; return 0; }"
+assert_error "void f() { return 1; } int main() { return 0; }"
+assert_error "int f() { return 1; } int f() { return 2; } int main() { return 0; }"
+assert 0 "int main() { int a; int *p = 0; void *v = &a; p = v; int (*f)() = main; return p != &a; }"
+# 宣言されていない名前があっても、それより前のエラーも報告する
+printf 'int main() { int *p; int *q; p + q; const int c = 1; c = 2; return x; }\n' > tmp1.c
+assert_command 0 "./target/debug/ccc9 -S -o - tmp1.c 2>&1 | grep -q 'エラーが3個ありました。'"
+rm -f tmp1.c
+
+# ポインタの計算
+assert 3 "int main() { int a[4] = {1, 2, 3, 4}; return *(2 + a); }"
+assert 4 "int main() { long a[4] = {1, 2, 3, 4}; long *p = a; p = 1 + p; return p[2]; }"
+assert 5 "int main() { int a[10]; return &a[7] - &a[2]; }"
+assert 3 "int main() { long a[10]; long *p = a + 1; long *q = a + 4; return q - p; }"
+assert 8 "int main() { int a[2]; return sizeof(&a[1] - &a[0]); }"
+assert 1 "int main() { int a[4]; int *p = a + 1; int *q = a + 3; return p < q; }"
+assert 0 "int main() { int a[4]; int *p = a + 1; int *q = a + 3; return p >= q; }"
+assert 1 "int main() { int a[4]; int *p = a + 2; return p == &a[2]; }"
+assert 1 "int main() { int *p = 0; return p == 0; }"
+assert 1 "int main() { int a; void *v = &a; int *p = &a; return v == p; }"
+assert_error "int main() { int a[2]; return a + a; }"
+assert_error "int main() { int a[2]; return 1 - a; }"
+assert_error "int main() { int a[2]; return a < 1; }"
+assert_error "int main() { int a[2]; char c[2]; return a == c; }"
+assert_error "int main() { int a[2]; char c[2]; return a - c; }"
+
+# 整数拡張と通常の算術型変換
+assert 1 "int main() { char c = 0-1; long l = c; return l == 0-1; }"
+assert 255 "int main() { unsigned char c = 0-1; return c; }"
+assert 1 "int main() { unsigned char c = 255; int i = c + 1; return i == 256; }"
+assert 1 "int main() { char a = 100; char b = 100; int i = a + b; return i == 200; }"
+assert 1 "int main() { unsigned int u = 0-1; long l = u; return l == 4294967295; }"
+assert 1 "int main() { int i = 0-1; unsigned int u = 1; return i > u; }"
+assert 1 "int main() { int i = 0-1; long l = 1; return i < l; }"
+assert 1 "int main() { unsigned int u = 0-1; return u / 2 == 2147483647; }"
+assert 254 "int main() { int i = 0-4; return i / 2 + 256; }"
+assert 1 "int main() { unsigned long u = 0-1; return u > 0; }"
+assert 1 "int main() { unsigned short s = 65535; return s + 1 == 65536; }"
+assert 1 "int main() { long l = 4294967296; return l / 2 == 2147483648; }"
+assert 8 "int main() { return sizeof(sizeof(int)); }"
+assert 4 "int main() { unsigned u; signed s; return sizeof(u + s); }"
+assert 8 "int main() { unsigned int u; long l; return sizeof(u + l); }"
+assert 1 "unsigned char f(unsigned char c) { return c; } int main() { return f(257); }"
+assert 1 "static unsigned int g = 0-1; int main() { return g == 4294967295; }"
+assert_error "int main() { unsigned void v; return 0; }"
+assert_error "int main() { signed unsigned int i; return 0; }"
+
+# 整数定数式
+assert 64 "int main() { int a[2*8]; return sizeof a; }"
+assert 3 "int main() { char a[(10 - 1) / 3]; return sizeof a; }"
+assert 8 "int main() { int a[sizeof(long)]; return sizeof(a) / sizeof(a[0]); }"
+assert 2 "int main() { int x; char a[sizeof x / 2]; return sizeof a; }"
+assert 1 "int main() { char a[1 < 2]; return sizeof a; }"
+assert 4 "int main() { char a[(char)260]; return sizeof a; }"
+assert 6 "int a[2][1 + 2]; int main() { return sizeof(a) / sizeof(int); }"
+assert 5 "int g = 2 * 3 - 1; int main() { return g; }"
+assert 1 "unsigned int g = (unsigned int)4294967295 + 2 == 1; int main() { return g; }"
+assert 3 "long g = 3000000000 / 1000000000; int main() { return g; }"
+assert_error "int main() { int a[1 / 0]; return 0; }"
+assert_error "int main() { int a[0 - 1]; return 0; }"
+assert_error "int main() { int n = 3; int a[n]; return 0; }"
+assert_error "int g = 2147483647 + 1; int main() { return 0; }"
+assert_error "int g = 65536 * 65536; int main() { return 0; }"
+assert_error "int g = 1 / 0; int main() { return 0; }"
+assert_error "int a[9223372036854775807]; int main() { return 0; }"
+assert_error "int main() { return sizeof(long[4611686018427387904]); }"
+assert_error "int main() { int a[3000000000]; return 0; }"
+assert_error "int main() { char a[1000000000]; char b[1000000000]; return 0; }"
+
+# C言語のソースコードとしての出力
+assert_roundtrip 7 "int main() { int a = 3; int b = 4; return a + b; }"
+assert_roundtrip 14 "int main() { return 6--8; }"
+assert_roundtrip 1 "int main() { return 2 > 1; }"
+assert_roundtrip 6 "int main() { int a[2][3], *p = &a[1][2]; *p = 6; return a[1][2]; }"
+assert_roundtrip 3 "int main() { int a[3] = {1, 2}, (*q)[3] = &a; return (*q)[0] + (*q)[1]; }"
+assert_roundtrip 10 "int main() { int s = 0; for (int i = 0; i < 5; i = i + 1) s = s + i; return s; }"
+assert_roundtrip 2 "int main() { int i = 0; while (i < 2) { if (i > 5) return 9; else i = i + 1; } return i; }"
+assert_roundtrip 3 "int add(int a, int b) { return a + b; } int (*fp)(int, int) = add; int main() { return fp(1, 2); }"
+assert_roundtrip 5 "static long sum(int n, ...) { va_list ap; va_start(ap, n); long r = va_arg(ap, long); va_end(ap); return r + n; } int main() { return sum(2, (long)3); }"
+assert_roundtrip 4 "char *s = \"a\\\"b\\n\"; int main() { return sizeof(s) / 2 + (s[1] == 34) - (s[3] == 10); }"
+assert_roundtrip 8 "const int *const cp = 0; unsigned short us = 1; int main() { static int st; return sizeof(int*) + st + (cp == 0) - us; }"
+assert_roundtrip 75 "int main() { return bar(1, 2); }"
+assert_roundtrip 49 "int main() { char *s = \"a\\0001\\xff\"; return s[2] + (s[1] != 0) + (s[3] != -1); }"
+assert_roundtrip 3 "static int h(void) { return 3; } int main() { int (*p)(void) = h; return p(); }"
+printf 'static int h(void) { return 3; } int main() { return h(); }\n' > tmp1.c
+assert_command 0 "[ \"\$(./target/debug/ccc9 --emit=c -o - tmp1.c | grep -c 'h(void)')\" = 2 ]"
+rm -f tmp1.c
+
+# 最適化してもvolatileな変数の読み書きは取り除かない
+printf 'int main() { volatile int a = 1; a; a; int b = a + a; return b; }\n' > tmp1.c
+assert_command 0 "[ \"\$(./target/debug/ccc9 -O1 -S -o - tmp1.c | grep -c 'DWORD PTR \[rbp-4\]\$')\" = 4 ]"
+printf 'int g; int main() { volatile int *p = &g; *p; *p; return 0; }\n' > tmp1.c
+assert_command 0 "[ \"\$(./target/debug/ccc9 -O1 -S -o - tmp1.c | grep -c 'PTR g\[rip\]')\" = 2 ]"
+rm -f tmp1.c
+
+# レジスタの割り当て(関数呼び出しをまたぐ値と、レジスタが足りない場合の退避)
+assert 9 "int one() { return 1; } int main() { return one() + (one() + (one() + (one() + (one() + (one() + (one() + (one() + one()))))))); }"
+assert 16 "int main() { int a; a = 1; return a + (a + (a + (a + (a + (a + (a + (a + (a + (a + (a + (a + (a + (a + (a + a)))))))))))))); }"
+assert 26 "int sub(int a, int b) { return a - b; } int main() { int x; x = 30; return sub(x, 4) - sub(x / 6, x - 25) + 100 / (x - 10) * 0; }"
+assert 36 "int sum(int a, int b, int c, int d, int e, int f, int g, int h) { return a + b + c + d + e + f + g + h; } int main() { int x; x = 1; return sum(x, x + 1, x + 2, x + 3, x + 4, x + 5, x + 6, x + 7); }"
+assert 81 "int f(int *p, int a) { int b = a + 1; int c = a + 2; int d = a + 3; int e = a + 4; int g = a + 5; int h = a + 6; int i = a + 7; int j = a + 8; int k = a + 9; int l = a + 10; int m = a + 11; int *q = p + a; int n = a - 1; int v = a * 3; q[n] = v; return q[n] + a + b + c + d + e + g + h + i + j + k + l + m; } int main() { int x[5]; return f(x, 1); }"
+assert 106 "int f(int a) { int b = a + 1; int c = a + 2; int d = a + 3; int e = a + 4; int g = a + 5; int h = a + 6; int i = a + 7; int j = a + 8; int k = a + 9; int l = a + 10; int m = a + 11; int n = a + 12; int o = a + 13; return (n < o) + (m == n) + a + b + c + d + e + g + h + i + j + k + l + m + n + o; } int main() { return f(1); }"
+printf '%s\n' "int f(int a) { int b = a + 1; int c = a + 2; int d = a + 3; int e = a + 4; int g = a + 5; int h = a + 6; int i = a + 7; int j = a + 8; int k = a + 9; int l = a + 10; int m = a + 11; int n = a + 12; int o = a + 13; return (n < o) + (m == n) + a + b + c + d + e + g + h + i + j + k + l + m + n + o; } int main() { return f(1); }" > tmp1.c
+assert_command 1 "./target/debug/ccc9 -O1 -S -o - tmp1.c | grep -A1 cmp | grep -q 'mov \[rbp-'"
+assert_command 1 "./target/debug/ccc9 -O1 -S -o - tmp1.c | grep -B1 setl | grep -q 'mov r[0-9a-z]*, \[rbp-'"
+rm -f tmp1.c
+
+# 配列の要素のアドレスをメモリのオペランドにまとめる
+assert 9 "int f(int *p, int i) { return p[i] + p[i + 1]; } int main() { int x[4]; x[1] = 4; x[2] = 5; return f(x, 1); }"
+assert 7 "long g[4]; int main() { int i; long x[4]; for (i = 0; i < 4; i = i + 1) { x[i] = i; g[i] = i * 2; } return x[3] + g[2]; }"
+printf 'int f(int *p, long i) { return p[i]; } int main() { int x[4]; long i; for (i = 0; i < 4; i = i + 1) x[i] = i; return f(x, 2); }\n' > tmp1.c
+assert_command 0 "./target/debug/ccc9 -O1 -S -o - tmp1.c | grep -q 'DWORD PTR \[r[a-z0-9]*+r[a-z0-9]*\*4\]'"
+assert_command 0 "./target/debug/ccc9 -O1 -S -o - tmp1.c | grep -q 'DWORD PTR \[rbp+r[a-z0-9]*\*4-[0-9]*\]'"
+rm -f tmp1.c
+
+# のぞき穴最適化(スタックマシンの命令のpushとpopの組、アドレスの計算、次の命令へのジャンプ)
+printf 'int main() { int a = 3; int b = a + 4; if (b) { } else { } return b; }\n' > tmp1.c
+assert_command 0 "./target/debug/ccc9 -S -o - tmp1.c | grep -q 'push 3'"
+assert_command 1 "./target/debug/ccc9 -Og -S -o - tmp1.c | grep -q 'push 3'"
+assert_command 0 "[ \"\$(./target/debug/ccc9 -Og -S -o - tmp1.c | grep -c push)\" -lt \"\$(./target/debug/ccc9 -S -o - tmp1.c | grep -c push)\" ]"
+assert_command 0 "./target/debug/ccc9 -S -o - tmp1.c | grep -q 'sub rax, 4'"
+assert_command 1 "./target/debug/ccc9 -Og -S -o - tmp1.c | grep -q 'sub rax, 4'"
+assert_command 0 "./target/debug/ccc9 -Og -S -o - tmp1.c | grep -q 'lea rax, \[rbp-4\]'"
+assert_command 0 "./target/debug/ccc9 -Og -S -o - tmp1.c | grep -q 'movsxd rax, DWORD PTR \[rbp-4\]'"
+assert_command 0 "./target/debug/ccc9 -S -o - tmp1.c | grep -q jmp"
+assert_command 1 "./target/debug/ccc9 -Og -S -o - tmp1.c | grep -q jmp"
+rm -f tmp1.c
+
+# 定数の畳み込みと不要なコードの削除
+assert 15 "int main() { return 5*(9-6); }"
+assert 1 "int main() { unsigned int u = 4294967295; return u + 2 == 1; }"
+assert 1 "int main() { return (unsigned char)300 == 44; }"
+assert 1 "int main() { long a = 2147483647; return a + 1 == 2147483648; }"
+assert 7 "int main() { int x = 7; return x * 1 + 0 - 0 / 1; }"
+assert 0 "int g; int f() { g = 1; return 3; } int main() { return f() * 0 + g - 1; }"
+assert 4 "int main() { if (0) { return 3; } else return 4; return 5; }"
+assert 3 "int main() { int x = 0; while (0) x = 1; for (x = 3; 0;) x = 2; return x; }"
+assert 6 "int main() { volatile int v = 6; v; 1 + 2; return v; }"
+assert 5 "int f(int *p) { *p = 5; return 0; } int main() { int x = 1; f(&x) * 0; return x; }"
+
+# 中間表現(SSA形式への変換、共通部分式の削除、コピーの伝播)
+assert 55 "int main() { int i = 0; int s = 0; while (i < 10) { i = i + 1; s = s + i; } return s; }"
+assert 231 "int f(int a, int b) { int x; if (a < b) { if (a == 0) x = 1; else x = 2; } else x = 3; return x; } int main() { return f(0, 1) + f(2, 1) * 10 + f(1, 2) * 100; }"
+assert 21 "int main() { int a = 1; int b = 2; int t; int i; for (i = 0; i < 5; i = i + 1) { t = a; a = b; b = t + b; } return b; }"
+assert 12 "int main() { int x = 3; int *p = &x; *p = *p * 4; return x; }"
+assert 20 "int f(int a, int b) { return (a + b) * (a + b) - (b + a) * 2 + a * b - a * b + 5; } int main() { return f(2, 3); }"
+assert 2 "int main() { char c = 200; int i = c; unsigned char u = 200; return (i == -56) + (u == 200); }"
+assert 6 "int main() { int x; int y = 0; if (y) x = 1; x = 6; return x; }"
+printf 'int main() { int i = 0; int s = 0; while (i < 3) { s = s + i; i = i + 1; } return s; }\n' > tmp1.c
+assert_command 0 "./target/debug/ccc9 --emit=ir -o - tmp1.c | grep -q 'load i32'"
+assert_command 0 "./target/debug/ccc9 -O1 --emit=ir -o - tmp1.c | grep -q phi"
+assert_command 1 "./target/debug/ccc9 -O1 --emit=ir -o - tmp1.c | grep -q load"
+rm -f tmp1.c
+
+# 関数のインライン展開
+assert 7 "int get(int *p) { return *p; } int main() { int x = 7; return get(&x); }"
+assert 45 "static inline int add(int a, int b) { return a + b; } int main() { int s = 0; int i; for (i = 0; i < 10; i = i + 1) s = add(s, i); return s; }"
+assert 8 "int abs(int x) { if (x < 0) return -x; return x; } int main() { return abs(-3) + abs(5); }"
+assert 120 "int fact(int n) { if (n == 0) return 1; return n * fact(n - 1); } int main() { return fact(5); }"
+assert 1 "int odd(int n); int even(int n) { if (n == 0) return 1; return odd(n - 1); } int odd(int n) { if (n == 0) return 0; return even(n - 1); } int main() { return even(10); }"
+assert 3 "static int count() { static int c; c = c + 1; return c; } int main() { count(); count(); return count(); }"
+assert 6 "int sq(int x) { return x * x; } int twice(int x) { return sq(x) + sq(x); } int main() { int a[2] = {1, 2}; int t = twice(a[1]) - a[1]; return t; }"
+assert 5 "void set(int *p, int v) { *p = v; } int main() { int x = 1; set(&x, 5); return x; }"
+assert 9 "int three() { int a[3] = {1, 1, 1}; return a[0] + a[1] + a[2]; } int main() { return three() * three(); }"
+assert_roundtrip 4 "static inline int two() { return 2; } int main() { return two() + two(); }"
+assert_error "inline int x; int main() { return 0; }"
+printf 'static int one() { return 1; }\nint main() { return one() + one(); }\n' > tmp1.c
+assert_command 1 "./target/debug/ccc9 -O1 --emit=ir -o - tmp1.c | grep -q call"
+assert_command 1 "./target/debug/ccc9 -O1 -S -o - tmp1.c | grep -q one"
+assert_command 0 "./target/debug/ccc9 -S -o - tmp1.c | grep -q 'call one'"
+rm -f tmp1.c
+# 静的な変数の初期化子がアドレスを使うstaticな関数は取り除かない
+assert 3 "static int h(void) { return 3; } int (*p)(void) = h; int main() { return p(); }"
+assert 4 "static int h(void) { return 4; } static int g(void) { static int (*q)(void) = h; return q(); } int main() { return g(); }"
+
+# 末尾呼び出しの最適化
+assert 55 "int sum(int n, int acc) { if (n == 0) return acc; return sum(n - 1, acc + n); } int main() { return sum(10, 0); }"
+assert 1 "int odd(int n); int even(int n) { if (n == 0) return 1; return odd(n - 1); } int odd(int n) { if (n == 0) return 0; return even(n - 1); } int main() { return even(100); }"
+assert 9 "int id(int x) { return x; } int apply(int f(int), int x) { return f(x); } int main() { return apply(id, 9); }"
+assert 7 "int deref(int *p, int n) { if (n) return deref(p, n - 1); return *p; } int use(int n) { int x = n; return deref(&x, 3); } int main() { return use(7); }"
+assert 42 "int g(int a, int b) { return a * b; } int f(int a) { int b = g(a, 2); int c = g(a, 3); return g(b + c, 1) + a * 0 + 2; } int main() { return f(8); }"
+printf 'int count(int n, int acc) { if (n == 0) return acc; return count(n - 1, acc + 1); }\n' > tmp1.c
+printf 'int odd(int n); int even(int n) { if (n == 0) return 1; return odd(n - 1); } int odd(int n) { if (n == 0) return 0; return even(n - 1); }\n' >> tmp1.c
+printf 'int main() { return (count(10000000, 0) == 10000000) + even(10000001); }\n' >> tmp1.c
+assert_command 0 "./target/debug/ccc9 -O1 -o tmp tmp1.c"
+assert_command 1 "./tmp"
+assert_command 0 "./target/debug/ccc9 -O1 --emit=ir -o - tmp1.c | grep -q 'tail call count'"
+printf 'int deref(int *p, int n) { if (n) return deref(p, n - 1); return *p; }\nint use(int n) { int x = n; return deref(&x, 3); }\n' > tmp1.c
+assert_command 0 "./target/debug/ccc9 -O1 --emit=ir -o - tmp1.c | grep -q 'tail call deref(%[0-9]*, %'"
+assert_command 1 "./target/debug/ccc9 -O1 --emit=ir -o - tmp1.c | grep -q 'tail call deref(%[0-9]*, 3)'"
+rm -f tmp1.c
+
+# コマンドライン
+printf 'int add(int a, int b) { return a + b; }\n' > tmp1.c
+printf 'int add(int a, int b); int main() { return add(3, 4); }\n' > tmp2.c
+assert_command 0 "./target/debug/ccc9 --help"
+assert_command 0 "./target/debug/ccc9 --version"
+assert_command 2 "./target/debug/ccc9"
+assert_command 2 "./target/debug/ccc9 --unknown tmp1.c"
+assert_command 3 "./target/debug/ccc9 -S no_such_file.c"
+assert_command 3 "./target/debug/ccc9 -S -o /dev/full tmp1.c"
+assert_command 0 "./target/debug/ccc9 -o tmp tmp1.c tmp2.c"
+assert_command 7 "./tmp"
+assert_command 0 "./target/debug/ccc9 -c -o tmp1.o tmp1.c"
+assert_command 0 "./target/debug/ccc9 -o tmp tmp1.o tmp2.c"
+assert_command 7 "./tmp"
+assert_command 0 "./target/debug/ccc9 -S tmp1.c && ./target/debug/ccc9 -o tmp tmp1.s tmp2.c"
+assert_command 7 "./tmp"
+assert_command 0 "cat tmp2.c | ./target/debug/ccc9 -g -O0 -std=c11 -o tmp - tmp1.c"
+assert_command 7 "./tmp"
+assert_command 1 "echo 'int main() { return x; }' | ./target/debug/ccc9 -o tmp -"
+# 一時ファイルは自分だけが読み書きできるディレクトリに作り、最後に削除する
+mkdir -p tmpdir
+assert_command 0 "TMPDIR=tmpdir ./target/debug/ccc9 -o tmp tmp1.c tmp2.c && [ -z \"\$(ls -A tmpdir)\" ]"
+assert_command 0 "echo 'int main() { return x; }' | TMPDIR=tmpdir ./target/debug/ccc9 -o tmp tmp1.c -; [ \$? = 1 ] && [ -z \"\$(ls -A tmpdir)\" ]"
+assert_command 3 "TMPDIR=tmpdir/no_such_dir ./target/debug/ccc9 -o tmp tmp1.c tmp2.c"
+rmdir tmpdir
+# 内部エラーの終了ステータスを使い方に書く。エラーを出力できなくてもpanicしない
+assert_command 0 "./target/debug/ccc9 --help | grep -q '4  コンパイラの内部エラー'"
+assert_command 0 "./target/debug/ccc9 --help > /dev/full"
+assert_command 1 "echo 'int main() { return x; }' | ./target/debug/ccc9 -o tmp - 2> /dev/full"
+assert_command 2 "./target/debug/ccc9 --unknown tmp1.c 2> /dev/full"
+# 成功したときは何も出力しない
+assert_command 0 "[ -z \"\$(./target/debug/ccc9 -o tmp tmp1.c tmp2.c 2>&1)\" ]"
+assert_command 0 "[ -z \"\$(./target/debug/ccc9 -O1 -o tmp tmp1.c tmp2.c 2>&1)\" ]"
+# エラーはファイル名:行:列と、その行だけを出力する
+printf 'int main() {\n  return x;\n}\n' > tmp1.c
+assert_command 0 "./target/debug/ccc9 -o tmp tmp1.c 2>&1 | head -1 | grep -q '^tmp1.c:2:10: '"
+assert_command 0 "[ \"\$(./target/debug/ccc9 -o tmp tmp1.c 2>&1 | sed -n 2,3p)\" = \"\$(printf '  return x;\\n         ^')\" ]"
+rm -f tmp1.c tmp2.c tmp1.o tmp1.s tmp.err
+
 echo OK